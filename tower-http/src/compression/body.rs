@@ -1,30 +1,32 @@
 #![allow(unused_imports)]
 
+use super::FlushPolicy;
 use crate::compression::CompressionLevel;
-use crate::{
-    compression_utils::{AsyncReadBody, BodyIntoStream, DecorateAsyncRead, WrapBody},
-    BoxError,
-};
+use crate::{compression_utils::EncoderSettings, BoxError};
 #[cfg(feature = "compression-br")]
-use async_compression::tokio::bufread::BrotliEncoder;
+use async_compression::tokio::write::BrotliEncoder;
 #[cfg(feature = "compression-gzip")]
-use async_compression::tokio::bufread::GzipEncoder;
+use async_compression::tokio::write::GzipEncoder;
 #[cfg(feature = "compression-deflate")]
-use async_compression::tokio::bufread::ZlibEncoder;
+use async_compression::tokio::write::ZlibEncoder;
 #[cfg(feature = "compression-zstd")]
-use async_compression::tokio::bufread::ZstdEncoder;
+use async_compression::tokio::write::ZstdEncoder;
+
+#[cfg(feature = "compression-br")]
+use super::dictionary::DictionaryBrotliEncoder;
 
 use bytes::{Buf, Bytes};
 use http::HeaderMap;
-use http_body::Body;
+use http_body::{Body, Frame};
 use pin_project_lite::pin_project;
 use std::{
-    io,
-    marker::PhantomData,
+    future::Future,
+    io, mem,
     pin::Pin,
     task::{ready, Context, Poll},
 };
-use tokio_util::io::StreamReader;
+use tokio::io::AsyncWrite;
+use tokio::time::{sleep, Sleep};
 
 use super::pin_project_cfg::pin_project_cfg;
 
@@ -66,13 +68,15 @@ where
     pub fn get_ref(&self) -> &B {
         match &self.inner {
             #[cfg(feature = "compression-gzip")]
-            BodyInner::Gzip { inner } => inner.read.get_ref().get_ref().get_ref().get_ref(),
+            BodyInner::Gzip { inner } => &inner.body,
             #[cfg(feature = "compression-deflate")]
-            BodyInner::Deflate { inner } => inner.read.get_ref().get_ref().get_ref().get_ref(),
+            BodyInner::Deflate { inner } => &inner.body,
             #[cfg(feature = "compression-br")]
-            BodyInner::Brotli { inner } => inner.read.get_ref().get_ref().get_ref().get_ref(),
+            BodyInner::Brotli { inner } => &inner.body,
             #[cfg(feature = "compression-zstd")]
-            BodyInner::Zstd { inner } => inner.read.get_ref().get_ref().get_ref().get_ref(),
+            BodyInner::Zstd { inner } => &inner.body,
+            #[cfg(feature = "compression-br")]
+            BodyInner::DictionaryBrotli { inner } => &inner.body,
             BodyInner::Identity { inner } => inner,
         }
     }
@@ -81,13 +85,15 @@ where
     pub fn get_mut(&mut self) -> &mut B {
         match &mut self.inner {
             #[cfg(feature = "compression-gzip")]
-            BodyInner::Gzip { inner } => inner.read.get_mut().get_mut().get_mut().get_mut(),
+            BodyInner::Gzip { inner } => &mut inner.body,
             #[cfg(feature = "compression-deflate")]
-            BodyInner::Deflate { inner } => inner.read.get_mut().get_mut().get_mut().get_mut(),
+            BodyInner::Deflate { inner } => &mut inner.body,
             #[cfg(feature = "compression-br")]
-            BodyInner::Brotli { inner } => inner.read.get_mut().get_mut().get_mut().get_mut(),
+            BodyInner::Brotli { inner } => &mut inner.body,
             #[cfg(feature = "compression-zstd")]
-            BodyInner::Zstd { inner } => inner.read.get_mut().get_mut().get_mut().get_mut(),
+            BodyInner::Zstd { inner } => &mut inner.body,
+            #[cfg(feature = "compression-br")]
+            BodyInner::DictionaryBrotli { inner } => &mut inner.body,
             BodyInner::Identity { inner } => inner,
         }
     }
@@ -96,37 +102,15 @@ where
     pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut B> {
         match self.project().inner.project() {
             #[cfg(feature = "compression-gzip")]
-            BodyInnerProj::Gzip { inner } => inner
-                .project()
-                .read
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut(),
+            BodyInnerProj::Gzip { inner } => inner.project().body,
             #[cfg(feature = "compression-deflate")]
-            BodyInnerProj::Deflate { inner } => inner
-                .project()
-                .read
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut(),
+            BodyInnerProj::Deflate { inner } => inner.project().body,
             #[cfg(feature = "compression-br")]
-            BodyInnerProj::Brotli { inner } => inner
-                .project()
-                .read
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut(),
+            BodyInnerProj::Brotli { inner } => inner.project().body,
             #[cfg(feature = "compression-zstd")]
-            BodyInnerProj::Zstd { inner } => inner
-                .project()
-                .read
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut()
-                .get_pin_mut(),
+            BodyInnerProj::Zstd { inner } => inner.project().body,
+            #[cfg(feature = "compression-br")]
+            BodyInnerProj::DictionaryBrotli { inner } => inner.project().body,
             BodyInnerProj::Identity { inner } => inner,
         }
     }
@@ -135,49 +119,35 @@ where
     pub fn into_inner(self) -> B {
         match self.inner {
             #[cfg(feature = "compression-gzip")]
-            BodyInner::Gzip { inner } => inner
-                .read
-                .into_inner()
-                .into_inner()
-                .into_inner()
-                .into_inner(),
+            BodyInner::Gzip { inner } => inner.body,
             #[cfg(feature = "compression-deflate")]
-            BodyInner::Deflate { inner } => inner
-                .read
-                .into_inner()
-                .into_inner()
-                .into_inner()
-                .into_inner(),
+            BodyInner::Deflate { inner } => inner.body,
             #[cfg(feature = "compression-br")]
-            BodyInner::Brotli { inner } => inner
-                .read
-                .into_inner()
-                .into_inner()
-                .into_inner()
-                .into_inner(),
+            BodyInner::Brotli { inner } => inner.body,
             #[cfg(feature = "compression-zstd")]
-            BodyInner::Zstd { inner } => inner
-                .read
-                .into_inner()
-                .into_inner()
-                .into_inner()
-                .into_inner(),
+            BodyInner::Zstd { inner } => inner.body,
+            #[cfg(feature = "compression-br")]
+            BodyInner::DictionaryBrotli { inner } => inner.body,
             BodyInner::Identity { inner } => inner,
         }
     }
 }
 
 #[cfg(feature = "compression-gzip")]
-type GzipBody<B> = WrapBody<GzipEncoder<B>>;
+type GzipBody<B> = EncodeBody<B, GzipEncoder<Vec<u8>>>;
 
 #[cfg(feature = "compression-deflate")]
-type DeflateBody<B> = WrapBody<ZlibEncoder<B>>;
+type DeflateBody<B> = EncodeBody<B, ZlibEncoder<Vec<u8>>>;
 
 #[cfg(feature = "compression-br")]
-type BrotliBody<B> = WrapBody<BrotliEncoder<B>>;
+type BrotliBody<B> = EncodeBody<B, BrotliEncoder<Vec<u8>>>;
 
 #[cfg(feature = "compression-zstd")]
-type ZstdBody<B> = WrapBody<ZstdEncoder<B>>;
+type ZstdBody<B> = EncodeBody<B, ZstdEncoder<Vec<u8>>>;
+
+// Brotli compressed against a dictionary, for the `dcb` coding. The `dcz` coding uses `ZstdBody`.
+#[cfg(feature = "compression-br")]
+type DictionaryBrotliBody<B> = EncodeBody<B, DictionaryBrotliEncoder>;
 
 pin_project_cfg! {
     #[project = BodyInnerProj]
@@ -205,6 +175,11 @@ pin_project_cfg! {
             #[pin]
             inner: ZstdBody<B>,
         },
+        #[cfg(feature = "compression-br")]
+        DictionaryBrotli {
+            #[pin]
+            inner: DictionaryBrotliBody<B>,
+        },
         Identity {
             #[pin]
             inner: B,
@@ -214,25 +189,30 @@ pin_project_cfg! {
 
 impl<B: Body> BodyInner<B> {
     #[cfg(feature = "compression-gzip")]
-    pub(crate) fn gzip(inner: WrapBody<GzipEncoder<B>>) -> Self {
+    pub(crate) fn gzip(inner: EncodeBody<B, GzipEncoder<Vec<u8>>>) -> Self {
         Self::Gzip { inner }
     }
 
     #[cfg(feature = "compression-deflate")]
-    pub(crate) fn deflate(inner: WrapBody<ZlibEncoder<B>>) -> Self {
+    pub(crate) fn deflate(inner: EncodeBody<B, ZlibEncoder<Vec<u8>>>) -> Self {
         Self::Deflate { inner }
     }
 
     #[cfg(feature = "compression-br")]
-    pub(crate) fn brotli(inner: WrapBody<BrotliEncoder<B>>) -> Self {
+    pub(crate) fn brotli(inner: EncodeBody<B, BrotliEncoder<Vec<u8>>>) -> Self {
         Self::Brotli { inner }
     }
 
     #[cfg(feature = "compression-zstd")]
-    pub(crate) fn zstd(inner: WrapBody<ZstdEncoder<B>>) -> Self {
+    pub(crate) fn zstd(inner: EncodeBody<B, ZstdEncoder<Vec<u8>>>) -> Self {
         Self::Zstd { inner }
     }
 
+    #[cfg(feature = "compression-br")]
+    pub(crate) fn dictionary_brotli(inner: EncodeBody<B, DictionaryBrotliEncoder>) -> Self {
+        Self::DictionaryBrotli { inner }
+    }
+
     pub(crate) fn identity(inner: B) -> Self {
         Self::Identity { inner }
     }
@@ -259,6 +239,8 @@ where
             BodyInnerProj::Brotli { inner } => inner.poll_frame(cx),
             #[cfg(feature = "compression-zstd")]
             BodyInnerProj::Zstd { inner } => inner.poll_frame(cx),
+            #[cfg(feature = "compression-br")]
+            BodyInnerProj::DictionaryBrotli { inner } => inner.poll_frame(cx),
             BodyInnerProj::Identity { inner } => match ready!(inner.poll_frame(cx)) {
                 Some(Ok(frame)) => {
                     let frame = frame.map_data(|mut buf| buf.copy_to_bytes(buf.remaining()));
@@ -271,75 +253,261 @@ where
     }
 }
 
-#[cfg(feature = "compression-gzip")]
-impl<B> DecorateAsyncRead for GzipEncoder<B>
+/// An encoder writing the compressed data to a buffer in memory.
+///
+/// The write encoders of `async-compression` only return `Poll::Pending` when the writer they
+/// wrap does, and writing to a `Vec<u8>` always completes. So writing to, flushing and shutting
+/// down an `Encode`r never returns `Poll::Pending`, which lets [`EncodeBody`] drive it inline
+/// while polling the body. Implementations must wrap a `Vec<u8>` or, like the dictionary
+/// encoders, otherwise always be ready.
+pub(crate) trait Encode: AsyncWrite + Unpin {
+    /// The compressed data written so far.
+    fn output(&mut self) -> &mut Vec<u8>;
+}
+
+/// An `Encode`r of a regular content coding, configured only by the compression settings.
+pub(crate) trait EncodeWithSettings: Encode {
+    /// Create the encoder with the settings of its encoding.
+    fn new(settings: &EncoderSettings) -> Self;
+}
+
+pin_project! {
+    /// `Body` compressed by an `Encode`r, flushing it according to a `FlushPolicy`.
+    pub(crate) struct EncodeBody<B, E> {
+        #[pin]
+        body: B,
+        encoder: E,
+        flush: FlushPolicy,
+        // Uncompressed bytes written since the encoder was last flushed.
+        unflushed: usize,
+        // boxed to keep the body `Unpin`
+        idle: Option<Pin<Box<Sleep>>>,
+        state: EncodeState,
+    }
+}
+
+enum EncodeState {
+    Encoding,
+    Trailers(HeaderMap),
+    Done,
+}
+
+impl<B, E> EncodeBody<B, E>
 where
-    B: Body,
+    E: Encode,
 {
-    type Input = AsyncReadBody<B>;
-    type Output = GzipEncoder<Self::Input>;
-
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
-        GzipEncoder::with_quality(input, quality.into_async_compression())
+    pub(crate) fn new(body: B, settings: &EncoderSettings, flush: FlushPolicy) -> Self
+    where
+        E: EncodeWithSettings,
+    {
+        Self::with_encoder(body, E::new(settings), flush)
     }
 
-    fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
-        pinned.get_pin_mut()
+    pub(crate) fn with_encoder(body: B, encoder: E, flush: FlushPolicy) -> Self {
+        Self {
+            body,
+            encoder,
+            flush,
+            unflushed: 0,
+            idle: None,
+            state: EncodeState::Encoding,
+        }
     }
 }
 
-#[cfg(feature = "compression-deflate")]
-impl<B> DecorateAsyncRead for ZlibEncoder<B>
+impl<B, E> Body for EncodeBody<B, E>
 where
     B: Body,
+    B::Error: Into<BoxError>,
+    E: Encode,
 {
-    type Input = AsyncReadBody<B>;
-    type Output = ZlibEncoder<Self::Input>;
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+
+        loop {
+            match this.state {
+                EncodeState::Encoding => {}
+                EncodeState::Trailers(trailers) => {
+                    let trailers = mem::take(trailers);
+                    *this.state = EncodeState::Done;
+                    return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+                }
+                EncodeState::Done => return Poll::Ready(None),
+            }
+
+            let frame = match this.body.as_mut().poll_frame(cx) {
+                Poll::Ready(frame) => {
+                    *this.idle = None;
+                    frame
+                }
+                Poll::Pending => {
+                    if *this.unflushed == 0 {
+                        return Poll::Pending;
+                    }
+                    let idle_for = match *this.flush {
+                        FlushPolicy::Idle(idle_for) => idle_for,
+                        _ => return Poll::Pending,
+                    };
+                    // don't start a timer for a zero duration, which works without one
+                    if !idle_for.is_zero() {
+                        let idle = this.idle.get_or_insert_with(|| Box::pin(sleep(idle_for)));
+                        ready!(idle.as_mut().poll(cx));
+                        *this.idle = None;
+                    }
+                    flush(this.encoder, cx)?;
+                    *this.unflushed = 0;
+                    match take_output(this.encoder) {
+                        Some(chunk) => return Poll::Ready(Some(Ok(Frame::data(chunk)))),
+                        None => return Poll::Pending,
+                    }
+                }
+            };
+
+            match frame {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(mut data) => {
+                        while data.has_remaining() {
+                            let chunk = data.chunk();
+                            let written =
+                                must_not_block(Pin::new(&mut *this.encoder).poll_write(cx, chunk))?;
+                            *this.unflushed += written;
+                            data.advance(written);
+                        }
+
+                        let should_flush = match *this.flush {
+                            FlushPolicy::EveryFrame => *this.unflushed > 0,
+                            FlushPolicy::AfterBytes(limit) => *this.unflushed >= limit,
+                            FlushPolicy::EndOfStream | FlushPolicy::Idle(_) => false,
+                        };
+                        if should_flush {
+                            flush(this.encoder, cx)?;
+                            *this.unflushed = 0;
+                        }
+                    }
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            shutdown(this.encoder, cx)?;
+                            *this.state = EncodeState::Trailers(trailers);
+                        }
+                    }
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err.into()))),
+                None => {
+                    shutdown(this.encoder, cx)?;
+                    *this.state = EncodeState::Done;
+                }
+            }
+
+            if let Some(chunk) = take_output(this.encoder) {
+                return Poll::Ready(Some(Ok(Frame::data(chunk))));
+            }
+        }
+    }
+}
+
+fn flush<E: Encode>(encoder: &mut E, cx: &mut Context<'_>) -> io::Result<()> {
+    must_not_block(Pin::new(encoder).poll_flush(cx))
+}
+
+fn shutdown<E: Encode>(encoder: &mut E, cx: &mut Context<'_>) -> io::Result<()> {
+    must_not_block(Pin::new(encoder).poll_shutdown(cx))
+}
+
+fn take_output<E: Encode>(encoder: &mut E) -> Option<Bytes> {
+    let output = encoder.output();
+    if output.is_empty() {
+        None
+    } else {
+        Some(Bytes::from(mem::take(output)))
+    }
+}
+
+// Encoders write to a `Vec`, which is always ready, see `Encode`.
+fn must_not_block<T>(poll: Poll<io::Result<T>>) -> io::Result<T> {
+    match poll {
+        Poll::Ready(result) => result,
+        Poll::Pending => unreachable!("encoders writing to a `Vec` never return `Pending`"),
+    }
+}
+
+#[cfg(feature = "compression-gzip")]
+impl Encode for GzipEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+}
+
+#[cfg(feature = "compression-gzip")]
+impl EncodeWithSettings for GzipEncoder<Vec<u8>> {
+    fn new(settings: &EncoderSettings) -> Self {
+        GzipEncoder::with_quality(Vec::new(), settings.gzip_quality().into_async_compression())
+    }
+}
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
-        ZlibEncoder::with_quality(input, quality.into_async_compression())
+#[cfg(feature = "compression-deflate")]
+impl Encode for ZlibEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
     }
+}
 
-    fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
-        pinned.get_pin_mut()
+#[cfg(feature = "compression-deflate")]
+impl EncodeWithSettings for ZlibEncoder<Vec<u8>> {
+    fn new(settings: &EncoderSettings) -> Self {
+        ZlibEncoder::with_quality(
+            Vec::new(),
+            settings.deflate_quality().into_async_compression(),
+        )
     }
 }
 
 #[cfg(feature = "compression-br")]
-impl<B> DecorateAsyncRead for BrotliEncoder<B>
-where
-    B: Body,
-{
-    type Input = AsyncReadBody<B>;
-    type Output = BrotliEncoder<Self::Input>;
+impl Encode for BrotliEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
+    }
+}
 
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
+#[cfg(feature = "compression-br")]
+impl EncodeWithSettings for BrotliEncoder<Vec<u8>> {
+    fn new(settings: &EncoderSettings) -> Self {
         // The brotli crate used under the hood here has a default compression level of 11,
         // which is the max for brotli. This causes extremely slow compression times, so we
         // manually set a default of 4 here.
         //
         // This is the same default used by NGINX for on-the-fly brotli compression.
-        let level = match quality {
+        let level = match settings.br_quality() {
             CompressionLevel::Default => async_compression::Level::Precise(4),
             other => other.into_async_compression(),
         };
-        BrotliEncoder::with_quality(input, level)
+        let mut params = async_compression::brotli::EncoderParams::default().quality(level);
+        if let Some(window_size) = settings.br_window_size() {
+            params = params.window_size(window_size as i32);
+        }
+        if settings.br_mode() == crate::compression::BrotliMode::Text {
+            params = params.text_mode();
+        }
+        BrotliEncoder::with_params(Vec::new(), params)
     }
+}
 
-    fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
-        pinned.get_pin_mut()
+#[cfg(feature = "compression-zstd")]
+impl Encode for ZstdEncoder<Vec<u8>> {
+    fn output(&mut self) -> &mut Vec<u8> {
+        self.get_mut()
     }
 }
 
 #[cfg(feature = "compression-zstd")]
-impl<B> DecorateAsyncRead for ZstdEncoder<B>
-where
-    B: Body,
-{
-    type Input = AsyncReadBody<B>;
-    type Output = ZstdEncoder<Self::Input>;
-
-    fn apply(input: Self::Input, quality: CompressionLevel) -> Self::Output {
+impl EncodeWithSettings for ZstdEncoder<Vec<u8>> {
+    fn new(settings: &EncoderSettings) -> Self {
+        let quality = settings.zstd_quality();
         // See https://issues.chromium.org/issues/41493659:
         //  "For memory usage reasons, Chromium limits the window size to 8MB"
         // See https://datatracker.ietf.org/doc/html/rfc8878#name-window-descriptor
@@ -356,16 +524,19 @@ where
             _ => false,
         };
         // The parameter is not set for levels below 17 as it will increase the window size
-        // for those levels.
-        if needs_window_limit {
-            let params = [async_compression::zstd::CParameter::window_log(23)];
-            ZstdEncoder::with_quality_and_params(input, quality.into_async_compression(), &params)
+        // for those levels. A window configured explicitly is always used as is.
+        let window_log = settings
+            .zstd_window_log()
+            .or_else(|| needs_window_limit.then_some(23));
+        if let Some(window_log) = window_log {
+            let params = [async_compression::zstd::CParameter::window_log(window_log)];
+            ZstdEncoder::with_quality_and_params(
+                Vec::new(),
+                quality.into_async_compression(),
+                &params,
+            )
         } else {
-            ZstdEncoder::with_quality(input, quality.into_async_compression())
+            ZstdEncoder::with_quality(Vec::new(), quality.into_async_compression())
         }
     }
-
-    fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input> {
-        pinned.get_pin_mut()
-    }
 }
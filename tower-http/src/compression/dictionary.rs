@@ -0,0 +1,344 @@
+#[cfg(feature = "compression-br")]
+use super::body::Encode;
+use crate::compression_utils::EncoderSettings;
+use crate::content_encoding::{DictionaryEncoding, SupportedEncodings};
+use crate::CompressionLevel;
+#[cfg(feature = "compression-zstd")]
+use async_compression::tokio::write::ZstdEncoder;
+#[cfg(feature = "compression-br")]
+use brotli::enc::{
+    encode::{BrotliEncoderOperation, BrotliEncoderParameter, BrotliEncoderStateStruct},
+    StandardAlloc,
+};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use std::{collections::HashMap, convert::TryInto, fmt, io};
+#[cfg(feature = "compression-br")]
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+#[cfg(feature = "compression-br")]
+use tokio::io::AsyncWrite;
+
+pub(crate) const AVAILABLE_DICTIONARY: HeaderName = HeaderName::from_static("available-dictionary");
+pub(crate) const USE_AS_DICTIONARY: HeaderName = HeaderName::from_static("use-as-dictionary");
+
+/// A dictionary that responses can be compressed against, for clients that already have it.
+///
+/// Clients that support [Compression Dictionary Transport] store responses sent with a
+/// `Use-As-Dictionary` header, see [`Compression::use_as_dictionary`]. Later requests to
+/// matching URLs announce the hash of the dictionary in an `Available-Dictionary` header, and
+/// if it is one of the dictionaries registered with [`Compression::dictionary`] the response is
+/// compressed against it with the `dcb` (Brotli) or `dcz` (Zstd) content coding. This is
+/// particularly effective for small and repetitive responses such as JSON API payloads.
+///
+/// [Compression Dictionary Transport]: https://www.rfc-editor.org/rfc/rfc9842
+/// [`Compression::use_as_dictionary`]: super::Compression::use_as_dictionary
+/// [`Compression::dictionary`]: super::Compression::dictionary
+#[derive(Clone)]
+pub struct CompressionDictionary {
+    hash: [u8; 32],
+    bytes: Bytes,
+}
+
+impl CompressionDictionary {
+    /// Creates a dictionary from its contents and their SHA-256 hash.
+    ///
+    /// Clients identify dictionaries by the hash of the response they stored, so `bytes` must be
+    /// exactly the body of that response, before any content coding.
+    pub fn new(hash: [u8; 32], bytes: impl Into<Bytes>) -> Self {
+        Self {
+            hash,
+            bytes: bytes.into(),
+        }
+    }
+
+    /// The SHA-256 hash of the dictionary.
+    pub fn hash(&self) -> [u8; 32] {
+        self.hash
+    }
+
+    /// The contents of the dictionary.
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl fmt::Debug for CompressionDictionary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hash = self
+            .hash
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>();
+        f.debug_struct("CompressionDictionary")
+            .field("hash", &hash)
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// The dictionaries of a `Compression` middleware and the responses designated as dictionaries.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Dictionaries {
+    by_hash: HashMap<[u8; 32], CompressionDictionary>,
+    // `Use-As-Dictionary` header values by request path
+    designated: HashMap<String, HeaderValue>,
+}
+
+impl Dictionaries {
+    pub(crate) fn insert(&mut self, dictionary: CompressionDictionary) {
+        self.by_hash.insert(dictionary.hash, dictionary);
+    }
+
+    pub(crate) fn designate(&mut self, path: String, match_pattern: &str) {
+        // the pattern is a structured field string
+        let mut value = String::from("match=\"");
+        for c in match_pattern.chars() {
+            if c == '"' || c == '\\' {
+                value.push('\\');
+            }
+            value.push(c);
+        }
+        value.push('"');
+        let value = HeaderValue::from_str(&value)
+            .expect("dictionary match pattern must be printable ASCII characters");
+        self.designated.insert(path, value);
+    }
+
+    /// Pick a dictionary coding and a dictionary for a request.
+    pub(crate) fn negotiate(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+        supported_encoding: impl SupportedEncodings,
+    ) -> DictionaryNegotiation {
+        let selected = if self.by_hash.is_empty() {
+            None
+        } else {
+            headers
+                .get(AVAILABLE_DICTIONARY)
+                .and_then(parse_available_dictionary)
+                .and_then(|hash| self.by_hash.get(&hash))
+                .and_then(|dictionary| {
+                    DictionaryEncoding::from_headers(headers, supported_encoding)
+                        .map(|encoding| (encoding, dictionary.clone()))
+                })
+        };
+
+        DictionaryNegotiation {
+            selected,
+            use_as_dictionary: self.designated.get(path).cloned(),
+            vary: !self.by_hash.is_empty(),
+        }
+    }
+}
+
+/// The dictionaries of a `Compression` middleware, shared by reference so the middleware stays
+/// `Copy`.
+///
+/// Each change leaks an updated copy. Dictionaries are configured once when building the
+/// middleware, so that happens a few times at startup, and never if no dictionary is added.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SharedDictionaries(Option<&'static Dictionaries>);
+
+impl SharedDictionaries {
+    pub(crate) fn update(&mut self, f: impl FnOnce(&mut Dictionaries)) {
+        let mut dictionaries = self.0.cloned().unwrap_or_default();
+        f(&mut dictionaries);
+        self.0 = Some(Box::leak(Box::new(dictionaries)));
+    }
+
+    /// Pick a dictionary coding and a dictionary for a request.
+    pub(crate) fn negotiate(
+        &self,
+        path: &str,
+        headers: &HeaderMap,
+        supported_encoding: impl SupportedEncodings,
+    ) -> DictionaryNegotiation {
+        match self.0 {
+            Some(dictionaries) => dictionaries.negotiate(path, headers, supported_encoding),
+            None => DictionaryNegotiation::default(),
+        }
+    }
+}
+
+/// What a response needs to send for Compression Dictionary Transport.
+#[derive(Debug, Default)]
+pub(crate) struct DictionaryNegotiation {
+    /// The coding and dictionary to compress the response with.
+    pub(crate) selected: Option<(DictionaryEncoding, CompressionDictionary)>,
+    /// The `Use-As-Dictionary` header of the response, if it is designated as a dictionary.
+    pub(crate) use_as_dictionary: Option<HeaderValue>,
+    /// Whether compressed responses vary on `Available-Dictionary`.
+    pub(crate) vary: bool,
+}
+
+// The hash is a structured field byte sequence, that is base64 between colons.
+fn parse_available_dictionary(value: &HeaderValue) -> Option<[u8; 32]> {
+    use base64::Engine as _;
+
+    let value = value.to_str().ok()?.trim();
+    let encoded = value.strip_prefix(':')?.strip_suffix(':')?;
+    let hash = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .ok()?;
+    hash.try_into().ok()
+}
+
+/// Create a Zstd encoder for the `dcz` coding, compressing against `dictionary`.
+#[cfg(feature = "compression-zstd")]
+pub(crate) fn dcz_encoder(
+    settings: &EncoderSettings,
+    dictionary: &CompressionDictionary,
+) -> io::Result<ZstdEncoder<Vec<u8>>> {
+    // Decoders only have to support windows of up to 8MB, and levels above 19 use larger ones.
+    // Unlike for the regular coding the window can't be limited separately when compressing
+    // against a dictionary.
+    let quality = match settings.zstd_quality() {
+        CompressionLevel::Best => CompressionLevel::Precise(19),
+        CompressionLevel::Precise(level) => CompressionLevel::Precise(level.min(19)),
+        other => other,
+    };
+
+    // the compressed data starts with a magic number and the hash of the dictionary
+    let mut output = Vec::with_capacity(40);
+    output.extend_from_slice(&[0x5e, 0x2a, 0x4d, 0x18, 0x20, 0x00, 0x00, 0x00]);
+    output.extend_from_slice(&dictionary.hash);
+    ZstdEncoder::with_dict(output, quality.into_async_compression(), &dictionary.bytes)
+}
+
+/// Brotli encoder for the `dcb` coding, compressing against a dictionary.
+///
+/// `async-compression` doesn't support custom dictionaries for Brotli, so this drives the
+/// encoder of the `brotli` crate directly.
+#[cfg(feature = "compression-br")]
+pub(crate) struct DictionaryBrotliEncoder {
+    state: BrotliEncoderStateStruct<StandardAlloc>,
+    output: Vec<u8>,
+}
+
+#[cfg(feature = "compression-br")]
+impl DictionaryBrotliEncoder {
+    pub(crate) fn new(settings: &EncoderSettings, dictionary: &CompressionDictionary) -> Self {
+        // Same default as the regular coding. The fastest qualities are always raised to 2, as
+        // the brotli crate only uses the dictionary from there on.
+        let quality = match settings.br_quality() {
+            CompressionLevel::Fastest => 2,
+            CompressionLevel::Best => 11,
+            CompressionLevel::Default => 4,
+            CompressionLevel::Precise(quality) => quality.clamp(2, 11) as u32,
+        };
+
+        let mut state = BrotliEncoderStateStruct::new(StandardAlloc::default());
+        state.set_parameter(BrotliEncoderParameter::BROTLI_PARAM_QUALITY, quality);
+        if let Some(window_size) = settings.br_window_size() {
+            state.set_parameter(BrotliEncoderParameter::BROTLI_PARAM_LGWIN, window_size);
+        }
+        if settings.br_mode() == super::BrotliMode::Text {
+            state.set_parameter(BrotliEncoderParameter::BROTLI_PARAM_MODE, 1);
+        }
+        state.set_custom_dictionary(dictionary.bytes.len(), &dictionary.bytes);
+
+        // the compressed data starts with a magic number and the hash of the dictionary
+        let mut output = Vec::with_capacity(36);
+        output.extend_from_slice(&[0xff, 0x44, 0x43, 0x42]);
+        output.extend_from_slice(&dictionary.hash);
+
+        Self { state, output }
+    }
+
+    fn encode(&mut self, op: BrotliEncoderOperation, input: &[u8]) -> io::Result<()> {
+        let mut available_in = input.len();
+        let mut input_offset = 0;
+        let mut buffer = [0; 4096];
+
+        loop {
+            let mut available_out = buffer.len();
+            let mut output_offset = 0;
+            if !self.state.compress_stream(
+                op,
+                &mut available_in,
+                input,
+                &mut input_offset,
+                &mut available_out,
+                &mut buffer,
+                &mut output_offset,
+                &mut None,
+                &mut |_, _, _, _| (),
+            ) {
+                return Err(io::Error::new(io::ErrorKind::Other, "brotli error"));
+            }
+            self.output.extend_from_slice(&buffer[..output_offset]);
+
+            let done = available_in == 0
+                && !self.state.has_more_output()
+                && (op != BrotliEncoderOperation::BROTLI_OPERATION_FINISH
+                    || self.state.is_finished());
+            if done {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "compression-br")]
+impl AsyncWrite for DictionaryBrotliEncoder {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        Poll::Ready(
+            this.encode(BrotliEncoderOperation::BROTLI_OPERATION_PROCESS, buf)
+                .map(|()| buf.len()),
+        )
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        Poll::Ready(this.encode(BrotliEncoderOperation::BROTLI_OPERATION_FLUSH, &[]))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        Poll::Ready(this.encode(BrotliEncoderOperation::BROTLI_OPERATION_FINISH, &[]))
+    }
+}
+
+#[cfg(feature = "compression-br")]
+impl Encode for DictionaryBrotliEncoder {
+    fn output(&mut self) -> &mut Vec<u8> {
+        &mut self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression_utils::AcceptEncoding;
+
+    #[test]
+    fn parses_available_dictionary() {
+        let value = HeaderValue::from_static(":pZGm1Av0IEBKARczz7exkNYsZb8LzaMrV7J32a2fFG4=:");
+        let hash = parse_available_dictionary(&value).unwrap();
+        assert_eq!(hash[..4], [0xa5, 0x91, 0xa6, 0xd4]);
+
+        assert!(parse_available_dictionary(&HeaderValue::from_static("pZGm1Av0")).is_none());
+        assert!(parse_available_dictionary(&HeaderValue::from_static(":pZGm1Av0:")).is_none());
+    }
+
+    #[test]
+    fn escapes_match_patterns() {
+        let mut dictionaries = Dictionaries::default();
+        dictionaries.designate("/dictionary".to_owned(), r#"/api/"quoted"\*"#);
+        let negotiation =
+            dictionaries.negotiate("/dictionary", &HeaderMap::new(), AcceptEncoding::default());
+        assert_eq!(
+            negotiation.use_as_dictionary.unwrap(),
+            r#"match="/api/\"quoted\"\\*""#
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+/// When compressed data is flushed out of the encoder and sent as part of the response body.
+///
+/// Encoders hold on to data until they have enough of it to compress well, which delays
+/// streamed responses such as server-sent events or progress updates. Flushing makes the encoder
+/// emit everything written so far, at some cost to the compression ratio.
+///
+/// Whatever the policy, the encoder is flushed when the response body ends.
+///
+/// See [`Compression::flush_policy`](super::Compression::flush_policy).
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FlushPolicy {
+    /// Only flush when the response body ends, leaving it to the encoder when to emit data.
+    ///
+    /// This is the default, and compresses best.
+    #[default]
+    EndOfStream,
+    /// Flush once the response body has had no data ready for the given duration.
+    ///
+    /// [`Duration::ZERO`] flushes whenever the response body has no data ready, so nothing is
+    /// held back while waiting for more. Other durations require the tokio runtime to have its
+    /// timer enabled.
+    Idle(Duration),
+    /// Flush after every data frame of the response body, so each of them can be decoded as soon
+    /// as it is received.
+    EveryFrame,
+    /// Flush after this many bytes of the response body have been compressed since the last
+    /// flush.
+    ///
+    /// Data isn't flushed when the response body is idle, so a body that pauses with fewer bytes
+    /// pending keeps them buffered until more data arrives or it ends.
+    AfterBytes(usize),
+}
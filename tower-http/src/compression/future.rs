@@ -1,9 +1,17 @@
 #![allow(unused_imports)]
 
-use super::{body::BodyInner, CompressionBody};
+#[cfg(feature = "compression-br")]
+use super::dictionary::DictionaryBrotliEncoder;
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use super::dictionary::{DictionaryNegotiation, AVAILABLE_DICTIONARY, USE_AS_DICTIONARY};
+use super::{
+    body::{BodyInner, EncodeBody},
+    CompressionBody, FlushPolicy,
+};
 use crate::compression::predicate::Predicate;
-use crate::compression::CompressionLevel;
-use crate::compression_utils::WrapBody;
+use crate::compression_utils::EncoderSettings;
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use crate::content_encoding::DictionaryEncoding;
 use crate::content_encoding::Encoding;
 use http::{header, HeaderMap, HeaderValue, Response};
 use http_body::Body;
@@ -14,6 +22,10 @@ use std::{
     task::{ready, Context, Poll},
 };
 
+// there is nothing to negotiate without the dictionary codings
+#[cfg(not(any(feature = "compression-br", feature = "compression-zstd")))]
+pub(crate) type DictionaryNegotiation = ();
+
 pin_project! {
     /// Response future of [`Compression`].
     ///
@@ -24,7 +36,9 @@ pin_project! {
         pub(crate) inner: F,
         pub(crate) encoding: Encoding,
         pub(crate) predicate: P,
-        pub(crate) quality: CompressionLevel,
+        pub(crate) settings: EncoderSettings,
+        pub(crate) flush: FlushPolicy,
+        pub(crate) dictionary: DictionaryNegotiation,
     }
 }
 
@@ -47,69 +61,133 @@ where
 
         let (mut parts, body) = res.into_parts();
 
+        #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+        let dictionary = std::mem::take(self.as_mut().project().dictionary);
+
+        #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+        if let Some(use_as_dictionary) = dictionary.use_as_dictionary {
+            parts.headers.insert(USE_AS_DICTIONARY, use_as_dictionary);
+        }
+
         if should_compress {
             parts
                 .headers
                 .append(header::VARY, header::ACCEPT_ENCODING.into());
-        }
-
-        let body = match (should_compress, self.encoding) {
-            // if compression is _not_ supported or the client doesn't accept it
-            (false, _) | (_, Encoding::Identity) => {
-                return Poll::Ready(Ok(Response::from_parts(
-                    parts,
-                    CompressionBody::new(BodyInner::identity(body)),
-                )))
+            #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+            if dictionary.vary {
+                parts
+                    .headers
+                    .append(header::VARY, AVAILABLE_DICTIONARY.into());
             }
+        }
 
-            #[cfg(feature = "compression-gzip")]
-            (_, Encoding::Gzip) => {
-                CompressionBody::new(BodyInner::gzip(WrapBody::new(body, self.quality)))
-            }
-            #[cfg(feature = "compression-deflate")]
-            (_, Encoding::Deflate) => {
-                CompressionBody::new(BodyInner::deflate(WrapBody::new(body, self.quality)))
-            }
-            #[cfg(feature = "compression-br")]
-            (_, Encoding::Brotli) => {
-                CompressionBody::new(BodyInner::brotli(WrapBody::new(body, self.quality)))
-            }
-            #[cfg(feature = "compression-zstd")]
-            (_, Encoding::Zstd) => {
-                CompressionBody::new(BodyInner::zstd(WrapBody::new(body, self.quality)))
-            }
-            #[cfg(feature = "fs")]
-            #[allow(unreachable_patterns)]
-            (true, _) => {
-                // This should never happen because the `AcceptEncoding` struct which is used to determine
-                // `self.encoding` will only enable the different compression algorithms if the
-                // corresponding crate feature has been enabled. This means
-                // Encoding::[Gzip|Brotli|Deflate] should be impossible at this point without the
-                // features enabled.
-                //
-                // The match arm is still required though because the `fs` feature uses the
-                // Encoding struct independently and requires no compression logic to be enabled.
-                // This means a combination of an individual compression feature and `fs` will fail
-                // to compile without this branch even though it will never be reached.
-                //
-                // To safeguard against refactors that changes this relationship or other bugs the
-                // server will return an uncompressed response instead of panicking since that could
-                // become a ddos attack vector.
-                return Poll::Ready(Ok(Response::from_parts(
-                    parts,
-                    CompressionBody::new(BodyInner::identity(body)),
-                )));
+        // prefer compressing against a dictionary the client has
+        #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+        let body = match dictionary.selected {
+            Some((encoding, dictionary)) if should_compress => {
+                let body = match encoding {
+                    #[cfg(feature = "compression-br")]
+                    DictionaryEncoding::Brotli => {
+                        let encoder = DictionaryBrotliEncoder::new(&self.settings, &dictionary);
+                        Ok(BodyInner::dictionary_brotli(EncodeBody::with_encoder(
+                            body, encoder, self.flush,
+                        )))
+                    }
+                    #[cfg(feature = "compression-zstd")]
+                    DictionaryEncoding::Zstd => {
+                        match super::dictionary::dcz_encoder(&self.settings, &dictionary) {
+                            Ok(encoder) => Ok(BodyInner::zstd(EncodeBody::with_encoder(
+                                body, encoder, self.flush,
+                            ))),
+                            // fall back to the regular codings
+                            Err(_) => Err(body),
+                        }
+                    }
+                };
+                match body {
+                    Ok(body) => {
+                        let body = CompressionBody::new(body);
+                        let res = compressed(parts, body, encoding.into_header_value());
+                        return Poll::Ready(Ok(res));
+                    }
+                    Err(body) => body,
+                }
             }
+            _ => body,
         };
 
-        parts.headers.remove(header::ACCEPT_RANGES);
-        parts.headers.remove(header::CONTENT_LENGTH);
+        let body =
+            match (should_compress, self.encoding) {
+                // if compression is _not_ supported or the client doesn't accept it
+                (false, _) | (_, Encoding::Identity) => {
+                    return Poll::Ready(Ok(Response::from_parts(
+                        parts,
+                        CompressionBody::new(BodyInner::identity(body)),
+                    )))
+                }
 
-        parts
-            .headers
-            .insert(header::CONTENT_ENCODING, self.encoding.into_header_value());
+                #[cfg(feature = "compression-gzip")]
+                (_, Encoding::Gzip) => CompressionBody::new(BodyInner::gzip(EncodeBody::new(
+                    body,
+                    &self.settings,
+                    self.flush,
+                ))),
+                #[cfg(feature = "compression-deflate")]
+                (_, Encoding::Deflate) => CompressionBody::new(BodyInner::deflate(
+                    EncodeBody::new(body, &self.settings, self.flush),
+                )),
+                #[cfg(feature = "compression-br")]
+                (_, Encoding::Brotli) => CompressionBody::new(BodyInner::brotli(EncodeBody::new(
+                    body,
+                    &self.settings,
+                    self.flush,
+                ))),
+                #[cfg(feature = "compression-zstd")]
+                (_, Encoding::Zstd) => CompressionBody::new(BodyInner::zstd(EncodeBody::new(
+                    body,
+                    &self.settings,
+                    self.flush,
+                ))),
+                #[cfg(feature = "fs")]
+                #[allow(unreachable_patterns)]
+                (true, _) => {
+                    // This should never happen because the `AcceptEncoding` struct which is used to determine
+                    // `self.encoding` will only enable the different compression algorithms if the
+                    // corresponding crate feature has been enabled. This means
+                    // Encoding::[Gzip|Brotli|Deflate] should be impossible at this point without the
+                    // features enabled.
+                    //
+                    // The match arm is still required though because the `fs` feature uses the
+                    // Encoding struct independently and requires no compression logic to be enabled.
+                    // This means a combination of an individual compression feature and `fs` will fail
+                    // to compile without this branch even though it will never be reached.
+                    //
+                    // To safeguard against refactors that changes this relationship or other bugs the
+                    // server will return an uncompressed response instead of panicking since that could
+                    // become a ddos attack vector.
+                    return Poll::Ready(Ok(Response::from_parts(
+                        parts,
+                        CompressionBody::new(BodyInner::identity(body)),
+                    )));
+                }
+            };
 
-        let res = Response::from_parts(parts, body);
+        let res = compressed(parts, body, self.encoding.into_header_value());
         Poll::Ready(Ok(res))
     }
 }
+
+fn compressed<B>(
+    mut parts: http::response::Parts,
+    body: B,
+    content_encoding: HeaderValue,
+) -> Response<B> {
+    parts.headers.remove(header::ACCEPT_RANGES);
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, content_encoding);
+
+    Response::from_parts(parts, body)
+}
@@ -1,7 +1,15 @@
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use super::dictionary::SharedDictionaries;
 use super::{Compression, Predicate};
 use crate::compression::predicate::DefaultPredicate;
-use crate::compression::CompressionLevel;
-use crate::compression_utils::AcceptEncoding;
+#[cfg(feature = "compression-br")]
+use crate::compression::BrotliParams;
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use crate::compression::CompressionDictionary;
+#[cfg(feature = "compression-zstd")]
+use crate::compression::ZstdParams;
+use crate::compression::{CompressionLevel, FlushPolicy};
+use crate::compression_utils::{AcceptEncoding, EncoderSettings};
 use tower_layer::Layer;
 
 /// Compress response bodies of the underlying service.
@@ -14,7 +22,10 @@ use tower_layer::Layer;
 pub struct CompressionLayer<P = DefaultPredicate> {
     accept: AcceptEncoding,
     predicate: P,
-    quality: CompressionLevel,
+    settings: EncoderSettings,
+    flush: FlushPolicy,
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    dictionaries: SharedDictionaries,
 }
 
 impl<S, P> Layer<S> for CompressionLayer<P>
@@ -28,7 +39,10 @@ where
             inner,
             accept: self.accept,
             predicate: self.predicate.clone(),
-            quality: self.quality,
+            settings: self.settings,
+            flush: self.flush,
+            #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+            dictionaries: self.dictionaries,
         }
     }
 }
@@ -67,9 +81,112 @@ impl CompressionLayer {
         self
     }
 
-    /// Sets the compression quality.
+    /// Sets the compression quality of all encodings.
+    ///
+    /// The quality of each encoding can also be set on its own, for example with
+    /// [`br_quality`](Self::br_quality).
     pub fn quality(mut self, quality: CompressionLevel) -> Self {
-        self.quality = quality;
+        self.settings.quality = quality;
+        self
+    }
+
+    /// Sets the compression quality of the gzip encoding, overriding [`quality`].
+    ///
+    /// Unlike the other encodings, gzip has no further parameters. Its memory level in particular
+    /// can't be set, as the underlying encoder doesn't support it.
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-gzip")]
+    pub fn gzip_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.gzip_quality = Some(quality);
+        self
+    }
+
+    /// Sets the compression quality of the Deflate encoding, overriding [`quality`].
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-deflate")]
+    pub fn deflate_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.deflate_quality = Some(quality);
+        self
+    }
+
+    /// Sets the compression quality of the Brotli encoding, overriding [`quality`].
+    ///
+    /// Brotli's best qualities are very slow and rarely worth it for responses that are
+    /// generated on the fly, unlike gzip's.
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-br")]
+    pub fn br_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.br_quality = Some(quality);
+        self
+    }
+
+    /// Sets the parameters of the Brotli encoder.
+    #[cfg(feature = "compression-br")]
+    pub fn br_params(mut self, params: BrotliParams) -> Self {
+        self.settings.br_params = params;
+        self
+    }
+
+    /// Sets the compression quality of the Zstd encoding, overriding [`quality`].
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.zstd_quality = Some(quality);
+        self
+    }
+
+    /// Sets the parameters of the Zstd encoder.
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd_params(mut self, params: ZstdParams) -> Self {
+        self.settings.zstd_params = params;
+        self
+    }
+
+    /// Sets when compressed data is flushed to the client.
+    ///
+    /// By default it is only flushed when the response body ends. See [`FlushPolicy`] for the
+    /// alternatives.
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush = policy;
+        self
+    }
+
+    /// Adds a dictionary to compress responses against, for clients announcing it in an
+    /// `Available-Dictionary` header.
+    ///
+    /// Responses are then compressed with the `dcz` or `dcb` content coding if the client
+    /// accepts one of them, and with the regular codings otherwise. See
+    /// [`CompressionDictionary`] for more details.
+    ///
+    /// Dictionaries are kept for the lifetime of the program, so the middleware should be built
+    /// once rather than for each request.
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    pub fn dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.dictionaries
+            .update(|dictionaries| dictionaries.insert(dictionary));
+        self
+    }
+
+    /// Designates the response to requests for `path` as a dictionary for later requests to
+    /// URLs matching `match_pattern`, by adding a `Use-As-Dictionary` header to it.
+    ///
+    /// The pattern is a [URL pattern], such as `/api/*`. The response should also be registered
+    /// with [`dictionary`] so later responses can be compressed against it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `match_pattern` contains characters other than printable ASCII.
+    ///
+    /// [URL pattern]: https://urlpattern.spec.whatwg.org/
+    /// [`dictionary`]: Self::dictionary
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    pub fn use_as_dictionary(mut self, path: impl Into<String>, match_pattern: &str) -> Self {
+        self.dictionaries
+            .update(|dictionaries| dictionaries.designate(path.into(), match_pattern));
         self
     }
 
@@ -115,7 +232,10 @@ impl CompressionLayer {
         CompressionLayer {
             accept: self.accept,
             predicate,
-            quality: self.quality,
+            settings: self.settings,
+            flush: self.flush,
+            #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+            dictionaries: self.dictionaries,
         }
     }
 }
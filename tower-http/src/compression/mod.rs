@@ -1,5 +1,10 @@
 //! Middleware that compresses response bodies.
 //!
+//! Request bodies sent by clients can be compressed with [`CompressRequestLayer`], the counterpart
+//! of [`RequestDecompressionLayer`].
+//!
+//! [`RequestDecompressionLayer`]: crate::decompression::RequestDecompressionLayer
+//!
 //! # Example
 //!
 //! Example showing how to respond with the compressed contents of a file.
@@ -72,20 +77,35 @@
 pub mod predicate;
 
 mod body;
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+mod dictionary;
+mod flush;
 mod future;
 mod layer;
 mod pin_project_cfg;
+mod request;
 mod service;
 
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+pub use self::dictionary::CompressionDictionary;
+pub use self::request::body::CompressRequestBody;
+pub use self::request::future::CompressRequestFuture;
+pub use self::request::layer::CompressRequestLayer;
+pub use self::request::service::CompressRequest;
 #[doc(inline)]
 pub use self::{
     body::CompressionBody,
+    flush::FlushPolicy,
     future::ResponseFuture,
     layer::CompressionLayer,
     predicate::{DefaultPredicate, Predicate},
     service::Compression,
 };
 pub use crate::compression_utils::CompressionLevel;
+#[cfg(feature = "compression-zstd")]
+pub use crate::compression_utils::ZstdParams;
+#[cfg(feature = "compression-br")]
+pub use crate::compression_utils::{BrotliMode, BrotliParams};
 
 #[cfg(test)]
 mod tests {
@@ -94,6 +114,7 @@ mod tests {
     use super::*;
     use crate::test_helpers::{Body, WithTrailers};
     use async_compression::tokio::write::{BrotliDecoder, BrotliEncoder};
+    use bytes::Bytes;
     use flate2::read::GzDecoder;
     use http::header::{
         ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_RANGE, CONTENT_TYPE, RANGE,
@@ -434,6 +455,188 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn compress_with_per_encoding_settings() {
+        const DATA: &str = "Check per-encoding settings! Check per-encoding settings! Check per-encoding settings!";
+
+        let svc = service_fn(|_| async {
+            let resp = Response::builder()
+                .body(Body::from(DATA.as_bytes()))
+                .unwrap();
+            Ok::<_, std::io::Error>(resp)
+        });
+
+        let mut svc = Compression::new(svc)
+            .quality(CompressionLevel::Fastest)
+            .br_quality(CompressionLevel::Precise(9))
+            .br_params(BrotliParams::new().window_size(12).mode(BrotliMode::Text));
+
+        let req = Request::builder()
+            .header("accept-encoding", "br")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        let compressed_data = res.into_body().collect().await.unwrap().to_bytes();
+
+        let compressed_with_settings = {
+            use async_compression::tokio::bufread::BrotliEncoder;
+
+            let stream = Box::pin(futures_util::stream::once(async move {
+                Ok::<_, std::io::Error>(DATA.as_bytes())
+            }));
+            let reader = StreamReader::new(stream);
+            let params = async_compression::brotli::EncoderParams::default()
+                .quality(async_compression::Level::Precise(9))
+                .window_size(12)
+                .text_mode();
+            let mut enc = BrotliEncoder::with_params(reader, params);
+
+            let mut buf = Vec::new();
+            enc.read_to_end(&mut buf).await.unwrap();
+            buf
+        };
+        assert_eq!(compressed_data, compressed_with_settings.as_slice());
+
+        // other encodings still use the quality set for all of them
+        let req = Request::builder()
+            .header("accept-encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        let compressed_data = res.into_body().collect().await.unwrap().to_bytes();
+
+        let mut compressed_fastest = Vec::new();
+        flate2::read::GzEncoder::new(DATA.as_bytes(), flate2::Compression::fast())
+            .read_to_end(&mut compressed_fastest)
+            .unwrap();
+        let mut decoder = GzDecoder::new(&compressed_data[..]);
+        let mut decompressed = String::new();
+        decoder.read_to_string(&mut decompressed).unwrap();
+        assert_eq!(decompressed, DATA);
+        assert_eq!(compressed_data.len(), compressed_fastest.len());
+    }
+
+    const DICTIONARY: &str = r#"{"id": 0, "name": "", "tags": [], "description": ""}"#;
+    const DICTIONARY_HASH: [u8; 32] = [0xab; 32];
+
+    async fn dictionary_handler(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let body = if req.uri().path() == "/dictionary" {
+            DICTIONARY
+        } else {
+            r#"{"id": 42, "name": "answer", "tags": ["a", "b"], "description": "same"}"#
+        };
+        Ok(Response::new(Body::from(body)))
+    }
+
+    fn dictionary_service(
+    ) -> impl Service<Request<Body>, Response = Response<CompressionBody<Body>>, Error = Infallible>
+    {
+        Compression::new(service_fn(dictionary_handler))
+            .dictionary(CompressionDictionary::new(DICTIONARY_HASH, DICTIONARY))
+            .use_as_dictionary("/dictionary", "/items/*")
+    }
+
+    fn dictionary_request(accept_encoding: &str, hash: [u8; 32]) -> Request<Body> {
+        use base64::Engine as _;
+
+        let hash = base64::engine::general_purpose::STANDARD.encode(hash);
+        Request::builder()
+            .uri("/items/42")
+            .header(ACCEPT_ENCODING, accept_encoding)
+            .header("available-dictionary", format!(":{}:", hash))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn compress_with_zstd_dictionary() {
+        let mut svc = dictionary_service();
+        let req = dictionary_request("gzip, br, zstd, dcb;q=0.5, dcz", DICTIONARY_HASH);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+
+        assert_eq!(res.headers()[CONTENT_ENCODING], "dcz");
+        let vary = res.headers().get_all("vary").iter().collect::<Vec<_>>();
+        assert_eq!(vary, ["accept-encoding", "available-dictionary"]);
+
+        let data = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(data[..8], [0x5e, 0x2a, 0x4d, 0x18, 0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(data[8..40], DICTIONARY_HASH);
+        let mut decoder =
+            zstd::stream::read::Decoder::with_dictionary(&data[40..], DICTIONARY.as_bytes())
+                .unwrap();
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(&mut decoder, &mut decompressed).unwrap();
+        assert_eq!(
+            decompressed,
+            r#"{"id": 42, "name": "answer", "tags": ["a", "b"], "description": "same"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn compress_with_brotli_dictionary() {
+        let mut svc = dictionary_service();
+        let req = dictionary_request("br, dcb", DICTIONARY_HASH);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+
+        assert_eq!(res.headers()[CONTENT_ENCODING], "dcb");
+
+        let data = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(data[..4], [0xff, 0x44, 0x43, 0x42]);
+        assert_eq!(data[4..36], DICTIONARY_HASH);
+        let mut decoder = brotli::Decompressor::new_with_custom_dict(
+            &data[36..],
+            4096,
+            DICTIONARY.as_bytes().to_vec().into(),
+        );
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(&mut decoder, &mut decompressed).unwrap();
+        assert_eq!(
+            decompressed,
+            r#"{"id": 42, "name": "answer", "tags": ["a", "b"], "description": "same"}"#
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_without_matching_dictionary() {
+        let mut svc = dictionary_service();
+        let req = dictionary_request("gzip, dcz", [0xcd; 32]);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+
+        // clients that don't accept a dictionary coding get the regular ones
+        let req = dictionary_request("gzip", DICTIONARY_HASH);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()[CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn copies_share_dictionaries() {
+        let svc = Compression::new(service_fn(dictionary_handler))
+            .compress_when(SizeAbove::new(0))
+            .dictionary(CompressionDictionary::new(DICTIONARY_HASH, DICTIONARY));
+
+        for mut svc in [svc, svc] {
+            let req = dictionary_request("gzip, dcz", DICTIONARY_HASH);
+            let res = svc.ready().await.unwrap().call(req).await.unwrap();
+            assert_eq!(res.headers()[CONTENT_ENCODING], "dcz");
+        }
+    }
+
+    #[tokio::test]
+    async fn designates_responses_as_dictionaries() {
+        let mut svc = dictionary_service();
+        let req = Request::builder()
+            .uri("/dictionary")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()["use-as-dictionary"], r#"match="/items/*""#);
+
+        let req = dictionary_request("gzip", DICTIONARY_HASH);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert!(res.headers().get("use-as-dictionary").is_none());
+    }
+
     #[tokio::test]
     async fn should_not_compress_ranges() {
         let svc = service_fn(|_| async {
@@ -495,4 +698,144 @@ mod tests {
         assert_eq!(headers[CONTENT_ENCODING], "gzip");
         assert_eq!(decompressed, "Hello, World!");
     }
+
+    type Chunks = tokio::sync::mpsc::UnboundedSender<Result<Bytes, std::io::Error>>;
+
+    // Compress a response streamed from a channel with the given flush policy.
+    async fn streamed_response(flush: FlushPolicy) -> (Chunks, CompressionBody<Body>) {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let body = Body::from_stream(futures_util::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|chunk| (chunk, rx))
+        }));
+        let mut body = Some(body);
+        let svc = service_fn(move |_: Request<Body>| {
+            let body = body.take().unwrap();
+            async move { Ok::<_, Infallible>(Response::new(body)) }
+        });
+        let svc = Compression::new(svc)
+            .compress_when(Always)
+            .flush_policy(flush);
+
+        let req = Request::builder()
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        (tx, res.into_body())
+    }
+
+    // Decompress what has been received so far.
+    fn decompress_partial(decoder: &mut flate2::write::GzDecoder<Vec<u8>>, frame: Bytes) -> String {
+        use std::io::Write;
+
+        decoder.write_all(&frame).unwrap();
+        decoder.flush().unwrap();
+        String::from_utf8(std::mem::take(decoder.get_mut())).unwrap()
+    }
+
+    async fn next_data(body: &mut CompressionBody<Body>) -> Bytes {
+        body.frame().await.unwrap().unwrap().into_data().unwrap()
+    }
+
+    #[tokio::test]
+    async fn does_not_flush_by_default() {
+        let (tx, mut body) = streamed_response(FlushPolicy::default()).await;
+
+        // the body is pending between frames, which doesn't flush them
+        tx.send(Ok(Bytes::from("data: first\n\n"))).unwrap();
+        let pending =
+            tokio::time::timeout(std::time::Duration::from_millis(50), body.frame()).await;
+        assert!(pending.is_err());
+
+        tx.send(Ok(Bytes::from("data: second\n\n"))).unwrap();
+        drop(tx);
+        let streamed = body.collect().await.unwrap().to_bytes();
+
+        // the same output as for a body that is ready at once
+        let svc = Compression::new(service_fn(|_: Request<Body>| async {
+            Ok::<_, Infallible>(Response::new(Body::from("data: first\n\ndata: second\n\n")))
+        }))
+        .compress_when(Always);
+        let req = Request::builder()
+            .header(ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        let whole = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(streamed, whole);
+    }
+
+    #[tokio::test]
+    async fn flushes_when_body_is_idle() {
+        let (tx, mut body) = streamed_response(FlushPolicy::Idle(std::time::Duration::ZERO)).await;
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+
+        tx.send(Ok(Bytes::from("data: first\n\n"))).unwrap();
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "data: first\n\n");
+
+        tx.send(Ok(Bytes::from("data: second\n\n"))).unwrap();
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "data: second\n\n");
+
+        drop(tx);
+        let rest = body.collect().await.unwrap().to_bytes();
+        assert_eq!(decompress_partial(&mut decoder, rest), "");
+        decoder.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn flushes_after_idle_duration() {
+        let idle = std::time::Duration::from_millis(200);
+        let (tx, mut body) = streamed_response(FlushPolicy::Idle(idle)).await;
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+
+        tx.send(Ok(Bytes::from("data: first\n\n"))).unwrap();
+        let early = tokio::time::timeout(idle / 4, body.frame()).await;
+        assert!(
+            early.is_err(),
+            "flushed before the body was idle long enough"
+        );
+
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "data: first\n\n");
+    }
+
+    #[tokio::test]
+    async fn flushes_every_frame() {
+        let (tx, mut body) = streamed_response(FlushPolicy::EveryFrame).await;
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+
+        // both frames are ready at once, so the body is never idle in between
+        tx.send(Ok(Bytes::from("one "))).unwrap();
+        tx.send(Ok(Bytes::from("two "))).unwrap();
+        drop(tx);
+
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "one ");
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "two ");
+    }
+
+    #[tokio::test]
+    async fn flushes_after_bytes() {
+        let (tx, mut body) = streamed_response(FlushPolicy::AfterBytes(8)).await;
+        let mut decoder = flate2::write::GzDecoder::new(Vec::new());
+
+        tx.send(Ok(Bytes::from("one "))).unwrap();
+        tx.send(Ok(Bytes::from("two "))).unwrap();
+        tx.send(Ok(Bytes::from("three "))).unwrap();
+
+        let frame = next_data(&mut body).await;
+        assert_eq!(decompress_partial(&mut decoder, frame), "one two ");
+
+        // less than 8 bytes are pending, the body being idle doesn't flush them
+        let pending =
+            tokio::time::timeout(std::time::Duration::from_millis(50), body.frame()).await;
+        assert!(pending.is_err());
+
+        drop(tx);
+        let rest = body.collect().await.unwrap().to_bytes();
+        assert_eq!(decompress_partial(&mut decoder, rest), "three ");
+    }
 }
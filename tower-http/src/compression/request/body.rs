@@ -0,0 +1,241 @@
+use crate::compression::CompressionBody;
+use crate::BoxError;
+use bytes::{Buf, Bytes};
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    collections::VecDeque,
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// Request body of [`CompressRequest`].
+    ///
+    /// [`CompressRequest`]: super::CompressRequest
+    pub struct CompressRequestBody<B>
+    where
+        B: Body,
+    {
+        #[pin]
+        inner: CompressionBody<ReplayBody<B>>,
+    }
+}
+
+impl<B> CompressRequestBody<B>
+where
+    B: Body,
+{
+    pub(crate) fn new(inner: CompressionBody<ReplayBody<B>>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<B> Body for CompressRequestBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        self.project().inner.poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<B> fmt::Debug for CompressRequestBody<B>
+where
+    B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressRequestBody").finish()
+    }
+}
+
+pin_project! {
+    /// Uncompressed request body, which keeps what was read of it so the request can be retried
+    /// with another encoding.
+    #[project = ReplayBodyProj]
+    pub(crate) enum ReplayBody<B> {
+        Direct {
+            #[pin]
+            body: B,
+        },
+        Recording {
+            recording: Arc<Mutex<Option<Recording<B>>>>,
+        },
+        Replaying {
+            buffered: VecDeque<Bytes>,
+            trailers: Option<HeaderMap>,
+            rest: Option<Pin<Box<B>>>,
+        },
+    }
+}
+
+impl<B> ReplayBody<B> {
+    pub(crate) fn direct(body: B) -> Self {
+        Self::Direct { body }
+    }
+
+    /// Record up to `limit` bytes of the body, see [`Recording::replay`].
+    pub(crate) fn recording(body: B, limit: usize) -> (Self, Arc<Mutex<Option<Recording<B>>>>) {
+        let recording = Arc::new(Mutex::new(Some(Recording {
+            body: Box::pin(body),
+            buffered: Some(VecDeque::new()),
+            buffered_len: 0,
+            limit,
+            trailers: None,
+            done: false,
+        })));
+        let body = Self::Recording {
+            recording: recording.clone(),
+        };
+        (body, recording)
+    }
+}
+
+/// The state of a body being recorded.
+pub(crate) struct Recording<B> {
+    body: Pin<Box<B>>,
+    // `None` once more than `limit` bytes were read
+    buffered: Option<VecDeque<Bytes>>,
+    buffered_len: usize,
+    limit: usize,
+    trailers: Option<HeaderMap>,
+    done: bool,
+}
+
+impl<B> Recording<B> {
+    /// A body starting over with what was read so far, if it all fit within the limit.
+    pub(crate) fn replay(self) -> Option<ReplayBody<B>> {
+        let buffered = self.buffered?;
+        Some(ReplayBody::Replaying {
+            buffered,
+            trailers: self.trailers,
+            rest: if self.done { None } else { Some(self.body) },
+        })
+    }
+}
+
+impl<B> Body for ReplayBody<B>
+where
+    B: Body,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.project() {
+            ReplayBodyProj::Direct { body } => poll_bytes(body, cx),
+            ReplayBodyProj::Recording { recording } => {
+                let mut recording = recording.lock().unwrap();
+                // the body was taken back to retry the request
+                let recording = match &mut *recording {
+                    Some(recording) => recording,
+                    None => return Poll::Ready(None),
+                };
+
+                let frame = ready!(poll_bytes(recording.body.as_mut(), cx));
+                match &frame {
+                    Some(Ok(frame)) => {
+                        if let Some(data) = frame.data_ref() {
+                            recording.buffered_len += data.len();
+                            if recording.buffered_len > recording.limit {
+                                recording.buffered = None;
+                            } else if let Some(buffered) = &mut recording.buffered {
+                                buffered.push_back(data.clone());
+                            }
+                        } else if let Some(trailers) = frame.trailers_ref() {
+                            recording.trailers = Some(trailers.clone());
+                        }
+                    }
+                    Some(Err(_)) => recording.buffered = None,
+                    None => recording.done = true,
+                }
+                Poll::Ready(frame)
+            }
+            ReplayBodyProj::Replaying {
+                buffered,
+                trailers,
+                rest,
+            } => {
+                if let Some(data) = buffered.pop_front() {
+                    return Poll::Ready(Some(Ok(Frame::data(data))));
+                }
+                if let Some(body) = rest {
+                    match ready!(poll_bytes(body.as_mut(), cx)) {
+                        None => *rest = None,
+                        frame => return Poll::Ready(frame),
+                    }
+                }
+                Poll::Ready(
+                    trailers
+                        .take()
+                        .map(|trailers| Ok(Frame::trailers(trailers))),
+                )
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            ReplayBody::Direct { body } => body.is_end_stream(),
+            ReplayBody::Recording { .. } => false,
+            ReplayBody::Replaying {
+                buffered,
+                trailers,
+                rest,
+            } => buffered.is_empty() && trailers.is_none() && rest.is_none(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            ReplayBody::Direct { body } => body.size_hint(),
+            ReplayBody::Recording { recording } => match &*recording.lock().unwrap() {
+                Some(recording) if !recording.done => recording.body.size_hint(),
+                _ => SizeHint::with_exact(0),
+            },
+            ReplayBody::Replaying { buffered, rest, .. } => {
+                let buffered = buffered.iter().map(|data| data.len() as u64).sum::<u64>();
+                let mut hint = rest
+                    .as_ref()
+                    .map_or_else(|| SizeHint::with_exact(0), |rest| rest.size_hint());
+                if let Some(upper) = hint.upper() {
+                    hint.set_upper(upper + buffered);
+                }
+                hint.set_lower(hint.lower() + buffered);
+                hint
+            }
+        }
+    }
+}
+
+fn poll_bytes<B>(
+    body: Pin<&mut B>,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<Frame<Bytes>, B::Error>>>
+where
+    B: Body,
+{
+    body.poll_frame(cx)
+        .map_ok(|frame| frame.map_data(|mut data| data.copy_to_bytes(data.remaining())))
+}
@@ -0,0 +1,141 @@
+use super::body::{CompressRequestBody, Recording};
+use super::service::{compress_request, LearnedEncodings};
+use crate::compression_utils::{AcceptEncoding, EncoderSettings};
+use crate::content_encoding::Encoding;
+use http::{header, request, Request, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+use tower_service::Service;
+
+pin_project! {
+    /// Response future of [`CompressRequest`].
+    ///
+    /// [`CompressRequest`]: super::CompressRequest
+    pub struct CompressRequestFuture<S, B>
+    where
+        S: Service<Request<CompressRequestBody<B>>>,
+        B: Body,
+    {
+        #[pin]
+        state: State<S, B>,
+    }
+}
+
+pin_project! {
+    #[project = StateProj]
+    enum State<S, B>
+    where
+        S: Service<Request<CompressRequestBody<B>>>,
+        B: Body,
+    {
+        Called {
+            #[pin]
+            future: S::Future,
+            retry: Option<Retry<S, B>>,
+        },
+        Retrying {
+            service: S,
+            request: Option<Request<CompressRequestBody<B>>>,
+        },
+    }
+}
+
+/// What's needed to send a request again with another encoding.
+pub(super) struct Retry<S, B> {
+    pub(super) service: S,
+    // the request before it was compressed
+    pub(super) parts: request::Parts,
+    pub(super) recording: Arc<Mutex<Option<Recording<B>>>>,
+    pub(super) encoding: Encoding,
+    pub(super) accept: AcceptEncoding,
+    pub(super) settings: EncoderSettings,
+    pub(super) learned: LearnedEncodings,
+    pub(super) max_learned_encodings: usize,
+}
+
+impl<S, B> CompressRequestFuture<S, B>
+where
+    S: Service<Request<CompressRequestBody<B>>>,
+    B: Body,
+{
+    pub(super) fn new(future: S::Future, retry: Option<Retry<S, B>>) -> Self {
+        Self {
+            state: State::Called { future, retry },
+        }
+    }
+}
+
+impl<S, B, ResBody> Future for CompressRequestFuture<S, B>
+where
+    S: Service<Request<CompressRequestBody<B>>, Response = Response<ResBody>>,
+    B: Body,
+{
+    type Output = Result<Response<ResBody>, S::Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut this = self.project();
+
+        loop {
+            match this.state.as_mut().project() {
+                StateProj::Called { future, retry } => {
+                    let res = ready!(future.poll(cx))?;
+
+                    // servers list the encodings they accept when rejecting one
+                    let rejected = res.status() == StatusCode::UNSUPPORTED_MEDIA_TYPE
+                        && res.headers().contains_key(header::ACCEPT_ENCODING);
+                    let retry = match retry.take() {
+                        Some(retry) if rejected => retry,
+                        _ => return Poll::Ready(Ok(res)),
+                    };
+
+                    let encoding = Encoding::from_headers(res.headers(), retry.accept);
+                    retry.learned.learn(
+                        retry.parts.uri.authority().cloned(),
+                        encoding,
+                        retry.max_learned_encodings,
+                    );
+                    if encoding == retry.encoding {
+                        return Poll::Ready(Ok(res));
+                    }
+
+                    let body = retry.recording.lock().unwrap().take();
+                    let body = match body.and_then(Recording::replay) {
+                        Some(body) => body,
+                        None => return Poll::Ready(Ok(res)),
+                    };
+                    let request = compress_request(retry.parts, body, encoding, &retry.settings);
+                    this.state.set(State::Retrying {
+                        service: retry.service,
+                        request: Some(request),
+                    });
+                }
+                StateProj::Retrying { service, request } => {
+                    ready!(service.poll_ready(cx))?;
+                    let request = request.take().expect("future polled after completion");
+                    let future = service.call(request);
+                    this.state.set(State::Called {
+                        future,
+                        retry: None,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for CompressRequestFuture<S, B>
+where
+    S: Service<Request<CompressRequestBody<B>>>,
+    B: Body,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressRequestFuture").finish()
+    }
+}
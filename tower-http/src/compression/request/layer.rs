@@ -0,0 +1,173 @@
+use super::service::{
+    CompressRequest, LearnedEncodings, DEFAULT_MAX_LEARNED_ENCODINGS, DEFAULT_RETRY_BUFFER_SIZE,
+};
+use crate::compression::predicate::{DefaultPredicate, Predicate};
+use crate::compression::CompressionLevel;
+use crate::compression_utils::{AcceptEncoding, EncoderSettings};
+use tower_layer::Layer;
+
+/// Compresses request bodies and calls its underlying service.
+///
+/// Services created by the same layer share the encodings they learned, see
+/// [`CompressRequest::learn_accepted_encodings`].
+///
+/// See [`CompressRequest`] for more details.
+#[derive(Clone, Debug)]
+pub struct CompressRequestLayer<P = DefaultPredicate> {
+    accept: AcceptEncoding,
+    predicate: P,
+    settings: EncoderSettings,
+    learned: Option<LearnedEncodings>,
+    max_learned_encodings: usize,
+    retry_buffer_size: usize,
+}
+
+impl<S, P> Layer<S> for CompressRequestLayer<P>
+where
+    P: Predicate,
+{
+    type Service = CompressRequest<S, P>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CompressRequest {
+            inner,
+            accept: self.accept,
+            predicate: self.predicate.clone(),
+            settings: self.settings,
+            learned: self.learned.clone(),
+            max_learned_encodings: self.max_learned_encodings,
+            retry_buffer_size: self.retry_buffer_size,
+        }
+    }
+}
+
+impl Default for CompressRequestLayer {
+    fn default() -> Self {
+        Self {
+            accept: AcceptEncoding::default(),
+            predicate: DefaultPredicate::default(),
+            settings: EncoderSettings::default(),
+            learned: None,
+            max_learned_encodings: DEFAULT_MAX_LEARNED_ENCODINGS,
+            retry_buffer_size: DEFAULT_RETRY_BUFFER_SIZE,
+        }
+    }
+}
+
+impl CompressRequestLayer {
+    /// Creates a new [`CompressRequestLayer`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<P> CompressRequestLayer<P> {
+    /// Sets whether to enable the gzip encoding.
+    #[cfg(feature = "compression-gzip")]
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.accept.set_gzip(enable);
+        self
+    }
+
+    /// Sets whether to enable the Deflate encoding.
+    #[cfg(feature = "compression-deflate")]
+    pub fn deflate(mut self, enable: bool) -> Self {
+        self.accept.set_deflate(enable);
+        self
+    }
+
+    /// Sets whether to enable the Brotli encoding.
+    #[cfg(feature = "compression-br")]
+    pub fn br(mut self, enable: bool) -> Self {
+        self.accept.set_br(enable);
+        self
+    }
+
+    /// Sets whether to enable the Zstd encoding.
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd(mut self, enable: bool) -> Self {
+        self.accept.set_zstd(enable);
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.quality = quality;
+        self
+    }
+
+    /// Sets whether to learn the encodings servers accept from their `415 Unsupported Media
+    /// Type` responses, and retry the requests they rejected.
+    ///
+    /// See [`CompressRequest::learn_accepted_encodings`] for more details.
+    pub fn learn_accepted_encodings(mut self, enable: bool) -> Self {
+        self.learned = enable.then(LearnedEncodings::default);
+        self
+    }
+
+    /// Sets the maximum number of authorities whose accepted encoding is remembered.
+    ///
+    /// See [`CompressRequest::max_learned_encodings`] for more details.
+    pub fn max_learned_encodings(mut self, max: usize) -> Self {
+        self.max_learned_encodings = max;
+        self
+    }
+
+    /// Sets how many bytes of a request body are kept to retry the request with another
+    /// encoding.
+    ///
+    /// See [`CompressRequest::retry_buffer_size`] for more details.
+    pub fn retry_buffer_size(mut self, size: usize) -> Self {
+        self.retry_buffer_size = size;
+        self
+    }
+
+    /// Disables the gzip encoding.
+    ///
+    /// This method is available even if the `gzip` crate feature is disabled.
+    pub fn no_gzip(mut self) -> Self {
+        self.accept.set_gzip(false);
+        self
+    }
+
+    /// Disables the Deflate encoding.
+    ///
+    /// This method is available even if the `deflate` crate feature is disabled.
+    pub fn no_deflate(mut self) -> Self {
+        self.accept.set_deflate(false);
+        self
+    }
+
+    /// Disables the Brotli encoding.
+    ///
+    /// This method is available even if the `br` crate feature is disabled.
+    pub fn no_br(mut self) -> Self {
+        self.accept.set_br(false);
+        self
+    }
+
+    /// Disables the Zstd encoding.
+    ///
+    /// This method is available even if the `zstd` crate feature is disabled.
+    pub fn no_zstd(mut self) -> Self {
+        self.accept.set_zstd(false);
+        self
+    }
+
+    /// Replace the current compression predicate.
+    ///
+    /// See [`CompressRequest::compress_when`] for more details.
+    pub fn compress_when<C>(self, predicate: C) -> CompressRequestLayer<C>
+    where
+        C: Predicate,
+    {
+        CompressRequestLayer {
+            accept: self.accept,
+            predicate,
+            settings: self.settings,
+            learned: self.learned,
+            max_learned_encodings: self.max_learned_encodings,
+            retry_buffer_size: self.retry_buffer_size,
+        }
+    }
+}
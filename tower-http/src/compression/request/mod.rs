@@ -0,0 +1,205 @@
+pub(super) mod body;
+pub(super) mod future;
+pub(super) mod layer;
+pub(super) mod service;
+
+#[cfg(all(test, feature = "compression-full", feature = "decompression-full"))]
+mod tests {
+    use super::body::CompressRequestBody;
+    use super::service::{CompressRequest, LearnedEncodings};
+    use crate::content_encoding::Encoding;
+    use crate::decompression::{DecompressionBody, RequestDecompression};
+    use crate::test_helpers::Body;
+    use http::{header, uri::Authority, Request, Response, StatusCode};
+    use http_body_util::BodyExt;
+    use std::{
+        convert::Infallible,
+        sync::{Arc, Mutex},
+    };
+    use tower::{service_fn, Service, ServiceExt};
+
+    const DATA: &str = "Hello, World! Hello, World! Hello, World! Hello, World! Hello, World!";
+
+    #[tokio::test]
+    async fn compresses_request_body() {
+        let (server, encodings) = server(RequestDecompression::new(service_fn(echo)));
+        let mut client = CompressRequest::new(server);
+
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, DATA);
+        assert_eq!(encodings.lock().unwrap()[..], [Some("zstd".to_owned())]);
+    }
+
+    #[tokio::test]
+    async fn does_not_compress_small_bodies() {
+        let (server, encodings) = server(RequestDecompression::new(service_fn(echo)));
+        let mut client = CompressRequest::new(server);
+
+        let req = Request::builder()
+            .header(header::CONTENT_LENGTH, 5)
+            .body(Body::from("Hello"))
+            .unwrap();
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(read_body(res).await, "Hello");
+        assert_eq!(encodings.lock().unwrap()[..], [None]);
+    }
+
+    #[tokio::test]
+    async fn learns_accepted_encodings_and_retries() {
+        let (server, encodings) = server(
+            RequestDecompression::new(service_fn(echo))
+                .zstd(false)
+                .br(false),
+        );
+        let mut client = CompressRequest::new(server).learn_accepted_encodings(true);
+
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, DATA);
+
+        // later requests use the learned encoding right away
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            encodings.lock().unwrap()[..],
+            [
+                Some("zstd".to_owned()),
+                Some("gzip".to_owned()),
+                Some("gzip".to_owned())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_requests_whose_body_was_kept() {
+        let (server, encodings) = server(service_fn(gzip_only));
+        let mut client = CompressRequest::new(server).learn_accepted_encodings(true);
+
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(read_body(res).await, DATA);
+        assert_eq!(
+            encodings.lock().unwrap()[..],
+            [Some("zstd".to_owned()), Some("gzip".to_owned())]
+        );
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_requests_beyond_the_buffer_size() {
+        let (server, encodings) = server(service_fn(gzip_only));
+        let mut client = CompressRequest::new(server)
+            .learn_accepted_encodings(true)
+            .retry_buffer_size(10);
+
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+        let res = client.ready().await.unwrap().call(request()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            encodings.lock().unwrap()[..],
+            [Some("zstd".to_owned()), Some("gzip".to_owned())]
+        );
+    }
+
+    #[test]
+    fn forgets_least_recently_used_encodings() {
+        let learned = LearnedEncodings::default();
+        let authority = |name: &str| Some(name.parse::<Authority>().unwrap());
+
+        learned.learn(authority("a.example"), Encoding::Gzip, 2);
+        learned.learn(authority("b.example"), Encoding::Gzip, 2);
+        assert_eq!(
+            learned.get(authority("a.example").as_ref()),
+            Some(Encoding::Gzip)
+        );
+
+        learned.learn(authority("c.example"), Encoding::Gzip, 2);
+        assert_eq!(
+            learned.get(authority("a.example").as_ref()),
+            Some(Encoding::Gzip)
+        );
+        assert_eq!(learned.get(authority("b.example").as_ref()), None);
+        assert_eq!(
+            learned.get(authority("c.example").as_ref()),
+            Some(Encoding::Gzip)
+        );
+    }
+
+    fn request() -> Request<Body> {
+        Request::builder()
+            .uri("http://example.com/upload")
+            .body(Body::from(DATA))
+            .unwrap()
+    }
+
+    // Records the `Content-Encoding` of the requests sent to the server.
+    fn server<S, ResBody>(
+        server: S,
+    ) -> (
+        impl Service<
+                Request<CompressRequestBody<Body>>,
+                Response = Response<ResBody>,
+                Error = Infallible,
+            > + Clone,
+        Arc<Mutex<Vec<Option<String>>>>,
+    )
+    where
+        S: Service<
+                Request<CompressRequestBody<Body>>,
+                Response = Response<ResBody>,
+                Error = Infallible,
+            > + Clone,
+    {
+        let encodings = Arc::new(Mutex::new(Vec::new()));
+        let recorded = encodings.clone();
+        let svc = service_fn(move |req: Request<CompressRequestBody<Body>>| {
+            let encoding = req
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .map(|value| value.to_str().unwrap().to_owned());
+            recorded.lock().unwrap().push(encoding);
+            server.clone().oneshot(req)
+        });
+        (svc, encodings)
+    }
+
+    async fn echo(
+        req: Request<DecompressionBody<CompressRequestBody<Body>>>,
+    ) -> Result<Response<Body>, Infallible> {
+        assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        Ok(Response::new(Body::from(body)))
+    }
+
+    // Reads the whole body before rejecting encodings other than gzip.
+    async fn gzip_only(
+        req: Request<CompressRequestBody<Body>>,
+    ) -> Result<Response<Body>, Infallible> {
+        let is_gzip = req.headers()[header::CONTENT_ENCODING] == "gzip";
+        let body = req.into_body().collect().await.unwrap().to_bytes();
+        if !is_gzip {
+            let res = Response::builder()
+                .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                .header(header::ACCEPT_ENCODING, "gzip")
+                .body(Body::empty())
+                .unwrap();
+            return Ok(res);
+        }
+
+        let mut decoder = flate2::read::GzDecoder::new(&body[..]);
+        let mut decompressed = String::new();
+        std::io::Read::read_to_string(&mut decoder, &mut decompressed).unwrap();
+        Ok(Response::new(Body::from(decompressed)))
+    }
+
+    async fn read_body<B>(res: Response<B>) -> String
+    where
+        B: http_body::Body,
+        B::Error: std::fmt::Debug,
+    {
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+}
@@ -0,0 +1,387 @@
+use super::body::{CompressRequestBody, ReplayBody};
+use super::future::{CompressRequestFuture, Retry};
+use super::layer::CompressRequestLayer;
+use crate::compression::body::{BodyInner, EncodeBody};
+use crate::compression::predicate::{DefaultPredicate, Predicate};
+use crate::compression::{CompressionBody, CompressionLevel, FlushPolicy};
+use crate::compression_utils::{AcceptEncoding, EncoderSettings};
+use crate::content_encoding::{Encoding, SupportedEncodings};
+use http::{header, request, uri::Authority, Request, Response};
+use http_body::Body;
+use std::{
+    collections::HashMap,
+    mem,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+/// Compresses request bodies and calls its underlying service.
+///
+/// This is the client side counterpart of [`RequestDecompression`]. Request bodies are compressed
+/// with the most preferred enabled encoding, that is the first of Zstd, Brotli, gzip and Deflate,
+/// and sent with a `Content-Encoding` header and without `Content-Length`.
+///
+/// Servers that don't support the encoding respond with `415 Unsupported Media Type` and the
+/// encodings they do accept in an `Accept-Encoding` header. With [`learn_accepted_encodings`]
+/// enabled, such responses set the encoding used for later requests to the same authority, and
+/// the request is retried with it if its body can still be sent again.
+///
+/// Whether a request is compressed is decided by a [`Predicate`], which is shown the request as
+/// a `200 OK` response with the same headers, extensions and body. The default predicate skips
+/// small bodies, images, gRPC and event streams, like for responses.
+///
+/// See the [module docs](crate::compression) for more details.
+///
+/// [`RequestDecompression`]: crate::decompression::RequestDecompression
+/// [`learn_accepted_encodings`]: Self::learn_accepted_encodings
+#[derive(Clone, Debug)]
+pub struct CompressRequest<S, P = DefaultPredicate> {
+    pub(super) inner: S,
+    pub(super) accept: AcceptEncoding,
+    pub(super) predicate: P,
+    pub(super) settings: EncoderSettings,
+    pub(super) learned: Option<LearnedEncodings>,
+    pub(super) max_learned_encodings: usize,
+    pub(super) retry_buffer_size: usize,
+}
+
+/// The default of [`CompressRequest::retry_buffer_size`].
+pub(super) const DEFAULT_RETRY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The default of [`CompressRequest::max_learned_encodings`].
+pub(super) const DEFAULT_MAX_LEARNED_ENCODINGS: usize = 1024;
+
+impl<S> CompressRequest<S, DefaultPredicate> {
+    /// Creates a new `CompressRequest` wrapping the `service`.
+    pub fn new(service: S) -> Self {
+        Self {
+            inner: service,
+            accept: AcceptEncoding::default(),
+            predicate: DefaultPredicate::default(),
+            settings: EncoderSettings::default(),
+            learned: None,
+            max_learned_encodings: DEFAULT_MAX_LEARNED_ENCODINGS,
+            retry_buffer_size: DEFAULT_RETRY_BUFFER_SIZE,
+        }
+    }
+
+    /// Returns a new [`Layer`] that wraps services with a `CompressRequest` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> CompressRequestLayer {
+        CompressRequestLayer::new()
+    }
+}
+
+impl<S, P> CompressRequest<S, P> {
+    define_inner_service_accessors!();
+
+    /// Sets whether to enable the gzip encoding.
+    #[cfg(feature = "compression-gzip")]
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.accept.set_gzip(enable);
+        self
+    }
+
+    /// Sets whether to enable the Deflate encoding.
+    #[cfg(feature = "compression-deflate")]
+    pub fn deflate(mut self, enable: bool) -> Self {
+        self.accept.set_deflate(enable);
+        self
+    }
+
+    /// Sets whether to enable the Brotli encoding.
+    #[cfg(feature = "compression-br")]
+    pub fn br(mut self, enable: bool) -> Self {
+        self.accept.set_br(enable);
+        self
+    }
+
+    /// Sets whether to enable the Zstd encoding.
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd(mut self, enable: bool) -> Self {
+        self.accept.set_zstd(enable);
+        self
+    }
+
+    /// Sets the compression quality.
+    pub fn quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.quality = quality;
+        self
+    }
+
+    /// Sets whether to learn the encodings servers accept from their `415 Unsupported Media
+    /// Type` responses, and retry the requests they rejected.
+    ///
+    /// The learned encodings are shared by all clones of the service. Requests are only retried
+    /// if no more than [`retry_buffer_size`] bytes of their body have been read.
+    ///
+    /// Disabled by default.
+    ///
+    /// [`retry_buffer_size`]: Self::retry_buffer_size
+    pub fn learn_accepted_encodings(mut self, enable: bool) -> Self {
+        self.learned = enable.then(LearnedEncodings::default);
+        self
+    }
+
+    /// Sets the maximum number of authorities whose accepted encoding is remembered, see
+    /// [`learn_accepted_encodings`].
+    ///
+    /// When there are more, the least recently used are forgotten and requests to them start
+    /// over with the preferred encoding. Defaults to 1024.
+    ///
+    /// [`learn_accepted_encodings`]: Self::learn_accepted_encodings
+    pub fn max_learned_encodings(mut self, max: usize) -> Self {
+        self.max_learned_encodings = max;
+        self
+    }
+
+    /// Sets how many bytes of a request body are kept to retry the request with another
+    /// encoding, see [`learn_accepted_encodings`].
+    ///
+    /// Defaults to 64 KiB. With zero, requests are only retried if their body wasn't read at all.
+    ///
+    /// [`learn_accepted_encodings`]: Self::learn_accepted_encodings
+    pub fn retry_buffer_size(mut self, size: usize) -> Self {
+        self.retry_buffer_size = size;
+        self
+    }
+
+    /// Disables the gzip encoding.
+    ///
+    /// This method is available even if the `gzip` crate feature is disabled.
+    pub fn no_gzip(mut self) -> Self {
+        self.accept.set_gzip(false);
+        self
+    }
+
+    /// Disables the Deflate encoding.
+    ///
+    /// This method is available even if the `deflate` crate feature is disabled.
+    pub fn no_deflate(mut self) -> Self {
+        self.accept.set_deflate(false);
+        self
+    }
+
+    /// Disables the Brotli encoding.
+    ///
+    /// This method is available even if the `br` crate feature is disabled.
+    pub fn no_br(mut self) -> Self {
+        self.accept.set_br(false);
+        self
+    }
+
+    /// Disables the Zstd encoding.
+    ///
+    /// This method is available even if the `zstd` crate feature is disabled.
+    pub fn no_zstd(mut self) -> Self {
+        self.accept.set_zstd(false);
+        self
+    }
+
+    /// Replace the current compression predicate.
+    ///
+    /// See [`Compression::compress_when`] for more details.
+    ///
+    /// [`Compression::compress_when`]: crate::compression::Compression::compress_when
+    pub fn compress_when<C>(self, predicate: C) -> CompressRequest<S, C>
+    where
+        C: Predicate,
+    {
+        CompressRequest {
+            inner: self.inner,
+            accept: self.accept,
+            predicate,
+            settings: self.settings,
+            learned: self.learned,
+            max_learned_encodings: self.max_learned_encodings,
+            retry_buffer_size: self.retry_buffer_size,
+        }
+    }
+
+    fn encoding(&self, authority: Option<&Authority>) -> Encoding {
+        self.learned
+            .as_ref()
+            .and_then(|learned| learned.get(authority))
+            .unwrap_or_else(|| preferred_encoding(self.accept))
+    }
+}
+
+impl<ReqBody, ResBody, S, P> Service<Request<ReqBody>> for CompressRequest<S, P>
+where
+    S: Service<Request<CompressRequestBody<ReqBody>>, Response = Response<ResBody>> + Clone,
+    ReqBody: Body,
+    P: Predicate,
+{
+    type Response = Response<ResBody>;
+    type Error = S::Error;
+    type Future = CompressRequestFuture<S, ReqBody>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+
+        let (should_compress, body) = should_compress(&self.predicate, &mut parts, body);
+        // never recompress requests that are already compressed
+        let encoding = if should_compress && !parts.headers.contains_key(header::CONTENT_ENCODING) {
+            self.encoding(parts.uri.authority())
+        } else {
+            Encoding::Identity
+        };
+
+        match &self.learned {
+            Some(learned) if encoding != Encoding::Identity => {
+                let (body, recording) = ReplayBody::recording(body, self.retry_buffer_size);
+                let retry = Retry {
+                    service: self.inner.clone(),
+                    parts: clone_parts(&parts),
+                    recording,
+                    encoding,
+                    accept: self.accept,
+                    settings: self.settings,
+                    learned: learned.clone(),
+                    max_learned_encodings: self.max_learned_encodings,
+                };
+                let req = compress_request(parts, body, encoding, &self.settings);
+                CompressRequestFuture::new(self.inner.call(req), Some(retry))
+            }
+            _ => {
+                let req =
+                    compress_request(parts, ReplayBody::direct(body), encoding, &self.settings);
+                CompressRequestFuture::new(self.inner.call(req), None)
+            }
+        }
+    }
+}
+
+/// The encodings learned from the responses of servers, by authority.
+#[derive(Clone, Debug, Default)]
+pub(super) struct LearnedEncodings(Arc<Mutex<Learned>>);
+
+#[derive(Debug, Default)]
+struct Learned {
+    // the encoding and when it was last used
+    encodings: HashMap<Option<Authority>, (Encoding, u64)>,
+    last_used: u64,
+}
+
+impl LearnedEncodings {
+    pub(super) fn get(&self, authority: Option<&Authority>) -> Option<Encoding> {
+        let mut learned = self.0.lock().unwrap();
+        learned.last_used += 1;
+        let last_used = learned.last_used;
+        let (encoding, used) = learned.encodings.get_mut(&authority.cloned())?;
+        *used = last_used;
+        Some(*encoding)
+    }
+
+    /// Remember `encoding` for `authority`, forgetting the least recently used encodings beyond
+    /// `max`.
+    pub(super) fn learn(&self, authority: Option<Authority>, encoding: Encoding, max: usize) {
+        let mut learned = self.0.lock().unwrap();
+        learned.last_used += 1;
+        let last_used = learned.last_used;
+        learned.encodings.insert(authority, (encoding, last_used));
+
+        while learned.encodings.len() > max {
+            let oldest = learned
+                .encodings
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(authority, _)| authority.clone());
+            match oldest {
+                Some(authority) => learned.encodings.remove(&authority),
+                None => break,
+            };
+        }
+    }
+}
+
+/// Compress the body of a request with `encoding` and set its headers accordingly.
+pub(super) fn compress_request<B>(
+    mut parts: request::Parts,
+    body: ReplayBody<B>,
+    encoding: Encoding,
+    settings: &EncoderSettings,
+) -> Request<CompressRequestBody<B>>
+where
+    B: Body,
+{
+    let flush = FlushPolicy::default();
+    let body = match encoding {
+        #[cfg(feature = "compression-gzip")]
+        Encoding::Gzip => BodyInner::gzip(EncodeBody::new(body, settings, flush)),
+        #[cfg(feature = "compression-deflate")]
+        Encoding::Deflate => BodyInner::deflate(EncodeBody::new(body, settings, flush)),
+        #[cfg(feature = "compression-br")]
+        Encoding::Brotli => BodyInner::brotli(EncodeBody::new(body, settings, flush)),
+        #[cfg(feature = "compression-zstd")]
+        Encoding::Zstd => BodyInner::zstd(EncodeBody::new(body, settings, flush)),
+        // identity, or an encoding only known for the `fs` feature that can't be compressed with
+        _ => {
+            let body = CompressionBody::new(BodyInner::identity(body));
+            return Request::from_parts(parts, CompressRequestBody::new(body));
+        }
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts
+        .headers
+        .insert(header::CONTENT_ENCODING, encoding.into_header_value());
+
+    let body = CompressRequestBody::new(CompressionBody::new(body));
+    Request::from_parts(parts, body)
+}
+
+fn preferred_encoding(accept: AcceptEncoding) -> Encoding {
+    let encodings = [
+        #[cfg(feature = "compression-deflate")]
+        (Encoding::Deflate, accept.deflate()),
+        #[cfg(feature = "compression-gzip")]
+        (Encoding::Gzip, accept.gzip()),
+        #[cfg(feature = "compression-br")]
+        (Encoding::Brotli, accept.br()),
+        #[cfg(feature = "compression-zstd")]
+        (Encoding::Zstd, accept.zstd()),
+    ];
+    encodings
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(encoding, _)| *encoding)
+        .max()
+        .unwrap_or(Encoding::Identity)
+}
+
+// Predicates look at responses, so they are shown the request as one.
+fn should_compress<P, B>(predicate: &P, parts: &mut request::Parts, body: B) -> (bool, B)
+where
+    P: Predicate,
+    B: Body,
+{
+    let mut res = Response::new(body);
+    *res.version_mut() = parts.version;
+    *res.headers_mut() = mem::take(&mut parts.headers);
+    *res.extensions_mut() = mem::take(&mut parts.extensions);
+
+    let should_compress = predicate.should_compress(&res);
+
+    let (res_parts, body) = res.into_parts();
+    parts.headers = res_parts.headers;
+    parts.extensions = res_parts.extensions;
+    (should_compress, body)
+}
+
+fn clone_parts(parts: &request::Parts) -> request::Parts {
+    let (mut clone, ()) = Request::new(()).into_parts();
+    clone.method = parts.method.clone();
+    clone.uri = parts.uri.clone();
+    clone.version = parts.version;
+    clone.headers = parts.headers.clone();
+    clone.extensions = parts.extensions.clone();
+    clone
+}
@@ -1,7 +1,16 @@
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use super::dictionary::SharedDictionaries;
 use super::{CompressionBody, CompressionLayer, ResponseFuture};
 use crate::compression::predicate::{DefaultPredicate, Predicate};
-use crate::compression::CompressionLevel;
-use crate::{compression_utils::AcceptEncoding, content_encoding::Encoding};
+#[cfg(feature = "compression-br")]
+use crate::compression::BrotliParams;
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+use crate::compression::CompressionDictionary;
+#[cfg(feature = "compression-zstd")]
+use crate::compression::ZstdParams;
+use crate::compression::{CompressionLevel, FlushPolicy};
+use crate::compression_utils::{AcceptEncoding, EncoderSettings};
+use crate::content_encoding::Encoding;
 use http::{Request, Response};
 use http_body::Body;
 use std::task::{Context, Poll};
@@ -18,7 +27,10 @@ pub struct Compression<S, P = DefaultPredicate> {
     pub(crate) inner: S,
     pub(crate) accept: AcceptEncoding,
     pub(crate) predicate: P,
-    pub(crate) quality: CompressionLevel,
+    pub(crate) settings: EncoderSettings,
+    pub(crate) flush: FlushPolicy,
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    pub(crate) dictionaries: SharedDictionaries,
 }
 
 impl<S> Compression<S, DefaultPredicate> {
@@ -28,7 +40,10 @@ impl<S> Compression<S, DefaultPredicate> {
             inner: service,
             accept: AcceptEncoding::default(),
             predicate: DefaultPredicate::default(),
-            quality: CompressionLevel::default(),
+            settings: EncoderSettings::default(),
+            flush: FlushPolicy::default(),
+            #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+            dictionaries: SharedDictionaries::default(),
         }
     }
 }
@@ -71,9 +86,112 @@ impl<S, P> Compression<S, P> {
         self
     }
 
-    /// Sets the compression quality.
+    /// Sets the compression quality of all encodings.
+    ///
+    /// The quality of each encoding can also be set on its own, for example with
+    /// [`br_quality`](Self::br_quality).
     pub fn quality(mut self, quality: CompressionLevel) -> Self {
-        self.quality = quality;
+        self.settings.quality = quality;
+        self
+    }
+
+    /// Sets the compression quality of the gzip encoding, overriding [`quality`].
+    ///
+    /// Unlike the other encodings, gzip has no further parameters. Its memory level in particular
+    /// can't be set, as the underlying encoder doesn't support it.
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-gzip")]
+    pub fn gzip_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.gzip_quality = Some(quality);
+        self
+    }
+
+    /// Sets the compression quality of the Deflate encoding, overriding [`quality`].
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-deflate")]
+    pub fn deflate_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.deflate_quality = Some(quality);
+        self
+    }
+
+    /// Sets the compression quality of the Brotli encoding, overriding [`quality`].
+    ///
+    /// Brotli's best qualities are very slow and rarely worth it for responses that are
+    /// generated on the fly, unlike gzip's.
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-br")]
+    pub fn br_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.br_quality = Some(quality);
+        self
+    }
+
+    /// Sets the parameters of the Brotli encoder.
+    #[cfg(feature = "compression-br")]
+    pub fn br_params(mut self, params: BrotliParams) -> Self {
+        self.settings.br_params = params;
+        self
+    }
+
+    /// Sets the compression quality of the Zstd encoding, overriding [`quality`].
+    ///
+    /// [`quality`]: Self::quality
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd_quality(mut self, quality: CompressionLevel) -> Self {
+        self.settings.zstd_quality = Some(quality);
+        self
+    }
+
+    /// Sets the parameters of the Zstd encoder.
+    #[cfg(feature = "compression-zstd")]
+    pub fn zstd_params(mut self, params: ZstdParams) -> Self {
+        self.settings.zstd_params = params;
+        self
+    }
+
+    /// Sets when compressed data is flushed to the client.
+    ///
+    /// By default it is only flushed when the response body ends. See [`FlushPolicy`] for the
+    /// alternatives.
+    pub fn flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.flush = policy;
+        self
+    }
+
+    /// Adds a dictionary to compress responses against, for clients announcing it in an
+    /// `Available-Dictionary` header.
+    ///
+    /// Responses are then compressed with the `dcz` or `dcb` content coding if the client
+    /// accepts one of them, and with the regular codings otherwise. See
+    /// [`CompressionDictionary`] for more details.
+    ///
+    /// Dictionaries are kept for the lifetime of the program, so the middleware should be built
+    /// once rather than for each request.
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    pub fn dictionary(mut self, dictionary: CompressionDictionary) -> Self {
+        self.dictionaries
+            .update(|dictionaries| dictionaries.insert(dictionary));
+        self
+    }
+
+    /// Designates the response to requests for `path` as a dictionary for later requests to
+    /// URLs matching `match_pattern`, by adding a `Use-As-Dictionary` header to it.
+    ///
+    /// The pattern is a [URL pattern], such as `/api/*`. The response should also be registered
+    /// with [`dictionary`] so later responses can be compressed against it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `match_pattern` contains characters other than printable ASCII.
+    ///
+    /// [URL pattern]: https://urlpattern.spec.whatwg.org/
+    /// [`dictionary`]: Self::dictionary
+    #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+    pub fn use_as_dictionary(mut self, path: impl Into<String>, match_pattern: &str) -> Self {
+        self.dictionaries
+            .update(|dictionaries| dictionaries.designate(path.into(), match_pattern));
         self
     }
 
@@ -152,7 +270,10 @@ impl<S, P> Compression<S, P> {
             inner: self.inner,
             accept: self.accept,
             predicate,
-            quality: self.quality,
+            settings: self.settings,
+            flush: self.flush,
+            #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+            dictionaries: self.dictionaries,
         }
     }
 }
@@ -174,12 +295,20 @@ where
 
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
         let encoding = Encoding::from_headers(req.headers(), self.accept);
+        #[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+        let dictionary = self
+            .dictionaries
+            .negotiate(req.uri().path(), req.headers(), self.accept);
+        #[cfg(not(any(feature = "compression-br", feature = "compression-zstd")))]
+        let dictionary = ();
 
         ResponseFuture {
             inner: self.inner.call(req),
             encoding,
             predicate: self.predicate.clone(),
-            quality: self.quality,
+            settings: self.settings,
+            flush: self.flush,
+            dictionary,
         }
     }
 }
@@ -1,18 +1,28 @@
 //! Types used by compression and decompression middleware.
 
-use crate::{content_encoding::SupportedEncodings, BoxError};
-use bytes::{Buf, Bytes, BytesMut};
+use crate::content_encoding::SupportedEncodings;
+use bytes::Buf;
 use futures_core::Stream;
 use http::HeaderValue;
 use http_body::{Body, Frame};
 use pin_project_lite::pin_project;
 use std::{
-    io,
     pin::Pin,
-    task::{ready, Context, Poll},
+    task::{Context, Poll},
+};
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
+use {
+    crate::BoxError,
+    bytes::{Bytes, BytesMut},
+    std::{io, task::ready},
+    tokio::io::AsyncRead,
+    tokio_util::io::StreamReader,
 };
-use tokio::io::AsyncRead;
-use tokio_util::io::StreamReader;
 
 #[derive(Debug, Clone, Copy)]
 pub(crate) struct AcceptEncoding {
@@ -116,10 +126,22 @@ impl Default for AcceptEncoding {
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 /// A `Body` that has been converted into an `AsyncRead`.
 pub(crate) type AsyncReadBody<B> =
     StreamReader<StreamErrorIntoIoError<BodyIntoStream<B>, <B as Body>::Error>, <B as Body>::Data>;
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 /// Trait for applying some decorator to an `AsyncRead`
 pub(crate) trait DecorateAsyncRead {
     type Input: AsyncRead;
@@ -134,6 +156,12 @@ pub(crate) trait DecorateAsyncRead {
     fn get_pin_mut(pinned: Pin<&mut Self::Output>) -> Pin<&mut Self::Input>;
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 pin_project! {
     /// `Body` that has been decorated by an `AsyncRead`
     pub(crate) struct WrapBody<M: DecorateAsyncRead> {
@@ -148,12 +176,23 @@ pin_project! {
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 impl<M: DecorateAsyncRead> WrapBody<M> {
     const INTERNAL_BUF_CAPACITY: usize = 4096;
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 impl<M: DecorateAsyncRead> WrapBody<M> {
-    #[allow(dead_code)]
     pub(crate) fn new<B>(body: B, quality: CompressionLevel) -> Self
     where
         B: Body,
@@ -180,6 +219,12 @@ impl<M: DecorateAsyncRead> WrapBody<M> {
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 impl<B, M> Body for WrapBody<M>
 where
     B: Body,
@@ -251,6 +296,7 @@ pin_project! {
         body: B,
         yielded_all_data: bool,
         non_data_frame: Option<Frame<B::Data>>,
+        bytes_read: u64,
     }
 }
 
@@ -264,9 +310,15 @@ where
             body,
             yielded_all_data: false,
             non_data_frame: None,
+            bytes_read: 0,
         }
     }
 
+    /// The number of data bytes read from the inner body so far.
+    pub(crate) fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
     /// Get a reference to the inner body
     pub(crate) fn get_ref(&self) -> &B {
         &self.body
@@ -304,7 +356,10 @@ where
 
             match std::task::ready!(this.body.poll_frame(cx)) {
                 Some(Ok(frame)) => match frame.into_data() {
-                    Ok(data) => return Poll::Ready(Some(Ok(data))),
+                    Ok(data) => {
+                        *this.bytes_read += data.remaining() as u64;
+                        return Poll::Ready(Some(Ok(data)));
+                    }
                     Err(frame) => {
                         *this.yielded_all_data = true;
                         *this.non_data_frame = Some(frame);
@@ -354,6 +409,12 @@ where
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 pin_project! {
     pub(crate) struct StreamErrorIntoIoError<S, E> {
         #[pin]
@@ -362,6 +423,12 @@ pin_project! {
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 impl<S, E> StreamErrorIntoIoError<S, E> {
     pub(crate) fn new(inner: S) -> Self {
         Self { inner, error: None }
@@ -388,6 +455,12 @@ impl<S, E> StreamErrorIntoIoError<S, E> {
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 impl<S, T, E> Stream for StreamErrorIntoIoError<S, E>
 where
     S: Stream<Item = Result<T, E>>,
@@ -407,6 +480,12 @@ where
     }
 }
 
+#[cfg(any(
+    feature = "decompression-br",
+    feature = "decompression-deflate",
+    feature = "decompression-gzip",
+    feature = "decompression-zstd"
+))]
 pub(crate) const SENTINEL_ERROR_CODE: i32 = -837459418;
 
 /// Level of compression data should be compressed with.
@@ -455,3 +534,140 @@ impl CompressionLevel {
         }
     }
 }
+
+/// Parameters of the Brotli encoder, other than its quality.
+#[cfg(feature = "compression-br")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct BrotliParams {
+    window_size: Option<u32>,
+    mode: BrotliMode,
+}
+
+#[cfg(feature = "compression-br")]
+impl BrotliParams {
+    /// Creates parameters with the defaults of the encoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the size of the sliding window, as a power of two.
+    ///
+    /// Smaller windows use less memory, on both ends, at the cost of compression ratio. The
+    /// size is clamped to `10..=24`.
+    pub fn window_size(mut self, window_size: u32) -> Self {
+        self.window_size = Some(window_size.clamp(10, 24));
+        self
+    }
+
+    /// Sets the kind of data being compressed.
+    pub fn mode(mut self, mode: BrotliMode) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// Kind of data the Brotli encoder is tuned for.
+#[cfg(feature = "compression-br")]
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum BrotliMode {
+    /// No assumptions are made about the data.
+    #[default]
+    Generic,
+    /// The data is UTF-8 text.
+    Text,
+}
+
+/// Parameters of the Zstd encoder, other than its quality.
+#[cfg(feature = "compression-zstd")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ZstdParams {
+    window_log: Option<u32>,
+}
+
+#[cfg(feature = "compression-zstd")]
+impl ZstdParams {
+    /// Creates parameters with the defaults of the encoder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum distance of back-references, as a power of two.
+    ///
+    /// By default the window is picked from the quality, but never above 8 MiB (a window log of
+    /// 23) which is the most browsers are guaranteed to decode. The value is clamped to
+    /// `10..=30`.
+    pub fn window_log(mut self, window_log: u32) -> Self {
+        self.window_log = Some(window_log.clamp(10, 30));
+        self
+    }
+}
+
+/// Quality and parameters of each encoder.
+///
+/// The per-encoding qualities override `quality` when set.
+#[cfg(any(
+    feature = "compression-br",
+    feature = "compression-gzip",
+    feature = "compression-deflate",
+    feature = "compression-zstd"
+))]
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct EncoderSettings {
+    pub(crate) quality: CompressionLevel,
+    #[cfg(feature = "compression-gzip")]
+    pub(crate) gzip_quality: Option<CompressionLevel>,
+    #[cfg(feature = "compression-deflate")]
+    pub(crate) deflate_quality: Option<CompressionLevel>,
+    #[cfg(feature = "compression-br")]
+    pub(crate) br_quality: Option<CompressionLevel>,
+    #[cfg(feature = "compression-br")]
+    pub(crate) br_params: BrotliParams,
+    #[cfg(feature = "compression-zstd")]
+    pub(crate) zstd_quality: Option<CompressionLevel>,
+    #[cfg(feature = "compression-zstd")]
+    pub(crate) zstd_params: ZstdParams,
+}
+
+#[cfg(any(
+    feature = "compression-br",
+    feature = "compression-gzip",
+    feature = "compression-deflate",
+    feature = "compression-zstd"
+))]
+impl EncoderSettings {
+    #[cfg(feature = "compression-gzip")]
+    pub(crate) fn gzip_quality(&self) -> CompressionLevel {
+        self.gzip_quality.unwrap_or(self.quality)
+    }
+
+    #[cfg(feature = "compression-deflate")]
+    pub(crate) fn deflate_quality(&self) -> CompressionLevel {
+        self.deflate_quality.unwrap_or(self.quality)
+    }
+
+    #[cfg(feature = "compression-br")]
+    pub(crate) fn br_quality(&self) -> CompressionLevel {
+        self.br_quality.unwrap_or(self.quality)
+    }
+
+    #[cfg(feature = "compression-br")]
+    pub(crate) fn br_window_size(&self) -> Option<u32> {
+        self.br_params.window_size
+    }
+
+    #[cfg(feature = "compression-br")]
+    pub(crate) fn br_mode(&self) -> BrotliMode {
+        self.br_params.mode
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    pub(crate) fn zstd_quality(&self) -> CompressionLevel {
+        self.zstd_quality.unwrap_or(self.quality)
+    }
+
+    #[cfg(feature = "compression-zstd")]
+    pub(crate) fn zstd_window_log(&self) -> Option<u32> {
+        self.zstd_params.window_log
+    }
+}
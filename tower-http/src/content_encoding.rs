@@ -6,7 +6,7 @@ pub(crate) trait SupportedEncodings: Copy {
 }
 
 // This enum's variants are ordered from least to most preferred.
-#[derive(Copy, Clone, Debug, Ord, PartialOrd, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, PartialEq, Eq, Hash)]
 pub(crate) enum Encoding {
     #[allow(dead_code)]
     Identity,
@@ -22,7 +22,7 @@ pub(crate) enum Encoding {
 
 impl Encoding {
     #[allow(dead_code)]
-    fn to_str(self) -> &'static str {
+    pub(crate) fn to_str(self) -> &'static str {
         match self {
             #[cfg(any(feature = "fs", feature = "compression-gzip"))]
             Encoding::Gzip => "gzip",
@@ -47,7 +47,13 @@ impl Encoding {
         }
     }
 
-    #[allow(dead_code)]
+    #[cfg(any(
+        feature = "compression-gzip",
+        feature = "compression-br",
+        feature = "compression-deflate",
+        feature = "compression-zstd",
+        feature = "fs",
+    ))]
     pub(crate) fn into_header_value(self) -> http::HeaderValue {
         http::HeaderValue::from_static(self.to_str())
     }
@@ -217,18 +223,29 @@ pub(crate) fn encodings<'a>(
     headers: &'a http::HeaderMap,
     supported_encoding: impl SupportedEncodings + 'a,
 ) -> impl Iterator<Item = (Encoding, QValue)> + 'a {
+    accepted_codings(headers).filter_map(move |(coding, qval)| {
+        // ignore unknown encodings
+        Encoding::parse(coding, supported_encoding).map(|encoding| (encoding, qval))
+    })
+}
+
+#[cfg(any(
+    feature = "compression-gzip",
+    feature = "compression-br",
+    feature = "compression-zstd",
+    feature = "compression-deflate",
+    feature = "fs",
+))]
+// The content codings of the `Accept-Encoding` headers, with their q-values.
+fn accepted_codings(headers: &http::HeaderMap) -> impl Iterator<Item = (&str, QValue)> {
     headers
         .get_all(http::header::ACCEPT_ENCODING)
         .iter()
         .filter_map(|hval| hval.to_str().ok())
         .flat_map(|s| s.split(','))
-        .filter_map(move |v| {
+        .filter_map(|v| {
             let mut v = v.splitn(2, ';');
-
-            let encoding = match Encoding::parse(v.next().unwrap().trim(), supported_encoding) {
-                Some(encoding) => encoding,
-                None => return None, // ignore unknown encodings
-            };
+            let coding = v.next().unwrap().trim();
 
             let qval = if let Some(qval) = v.next() {
                 QValue::parse(qval.trim())?
@@ -236,10 +253,71 @@ pub(crate) fn encodings<'a>(
                 QValue::one()
             };
 
-            Some((encoding, qval))
+            Some((coding, qval))
         })
 }
 
+/// Content codings of Compression Dictionary Transport, which compress against a dictionary the
+/// client already has.
+///
+/// See <https://www.rfc-editor.org/rfc/rfc9842>.
+// This enum's variants are ordered from least to most preferred.
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, PartialEq, Eq, Hash)]
+pub(crate) enum DictionaryEncoding {
+    #[cfg(feature = "compression-br")]
+    Brotli,
+    #[cfg(feature = "compression-zstd")]
+    Zstd,
+}
+
+#[cfg(any(feature = "compression-br", feature = "compression-zstd"))]
+impl DictionaryEncoding {
+    pub(crate) fn to_str(self) -> &'static str {
+        match self {
+            #[cfg(feature = "compression-br")]
+            DictionaryEncoding::Brotli => "dcb",
+            #[cfg(feature = "compression-zstd")]
+            DictionaryEncoding::Zstd => "dcz",
+        }
+    }
+
+    pub(crate) fn into_header_value(self) -> http::HeaderValue {
+        http::HeaderValue::from_static(self.to_str())
+    }
+
+    fn parse(s: &str, supported_encoding: impl SupportedEncodings) -> Option<Self> {
+        #[cfg(feature = "compression-br")]
+        if s.eq_ignore_ascii_case("dcb") && supported_encoding.br() {
+            return Some(DictionaryEncoding::Brotli);
+        }
+
+        #[cfg(feature = "compression-zstd")]
+        if s.eq_ignore_ascii_case("dcz") && supported_encoding.zstd() {
+            return Some(DictionaryEncoding::Zstd);
+        }
+
+        None
+    }
+
+    /// The preferred dictionary coding accepted by the client, if any.
+    ///
+    /// The dictionary codings are only supported along with the corresponding regular ones.
+    pub(crate) fn from_headers(
+        headers: &http::HeaderMap,
+        supported_encoding: impl SupportedEncodings,
+    ) -> Option<Self> {
+        accepted_codings(headers)
+            .filter_map(|(coding, qval)| {
+                DictionaryEncoding::parse(coding, supported_encoding)
+                    .map(|encoding| (encoding, qval))
+            })
+            .filter(|(_, qvalue)| qvalue.0 > 0)
+            .max_by_key(|&(encoding, qvalue)| (qvalue, encoding))
+            .map(|(encoding, _)| encoding)
+    }
+}
+
 #[cfg(all(
     test,
     feature = "compression-gzip",
@@ -602,4 +680,30 @@ mod tests {
         let encoding = Encoding::from_headers(&headers, SupportedEncodingsAll);
         assert_eq!(Encoding::Identity, encoding);
     }
+
+    #[test]
+    fn accept_encoding_header_dictionary_encodings() {
+        let mut headers = http::HeaderMap::new();
+        headers.append(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("gzip, br, zstd, dcb, dcz;q=0.5"),
+        );
+        assert_eq!(
+            Some(DictionaryEncoding::Brotli),
+            DictionaryEncoding::from_headers(&headers, SupportedEncodingsAll)
+        );
+        assert_eq!(
+            Encoding::Zstd,
+            Encoding::from_headers(&headers, SupportedEncodingsAll)
+        );
+
+        headers.insert(
+            http::header::ACCEPT_ENCODING,
+            http::HeaderValue::from_static("gzip, br, dcb;q=0"),
+        );
+        assert_eq!(
+            None,
+            DictionaryEncoding::from_headers(&headers, SupportedEncodingsAll)
+        );
+    }
 }
@@ -1,5 +1,6 @@
 #![allow(unused_imports)]
 
+use super::stacked::{Coding, StackedDecoders};
 use crate::compression_utils::CompressionLevel;
 use crate::{
     compression_utils::{AsyncReadBody, BodyIntoStream, DecorateAsyncRead, WrapBody},
@@ -19,9 +20,13 @@ use http_body::{Body, SizeHint};
 use pin_project_lite::pin_project;
 use std::task::Context;
 use std::{
-    io,
+    fmt, io,
     marker::PhantomData,
     pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     task::{ready, Poll},
 };
 use tokio_util::io::StreamReader;
@@ -37,6 +42,12 @@ pin_project! {
     {
         #[pin]
         pub(crate) inner: BodyInner<B>,
+        stacked: StackedDecoders,
+        limits: DecompressionLimits,
+        decompressed: u64,
+        // set once a limit was exceeded, to be seen by whoever is waiting for that
+        exceeded: Option<Arc<AtomicBool>>,
+        failed: bool,
     }
 }
 
@@ -45,11 +56,9 @@ where
     B: Body + Default,
 {
     fn default() -> Self {
-        Self {
-            inner: BodyInner::Identity {
-                inner: B::default(),
-            },
-        }
+        Self::new(BodyInner::Identity {
+            inner: B::default(),
+        })
     }
 }
 
@@ -58,7 +67,48 @@ where
     B: Body,
 {
     pub(crate) fn new(inner: BodyInner<B>) -> Self {
-        Self { inner }
+        Self {
+            inner,
+            stacked: StackedDecoders::new(&[]),
+            limits: DecompressionLimits::default(),
+            decompressed: 0,
+            exceeded: None,
+            failed: false,
+        }
+    }
+
+    /// Decode `body` from `codings`, in the order they were applied.
+    pub(crate) fn decode(body: B, codings: &[Coding]) -> Self {
+        let (last, stacked) = match codings.split_last() {
+            Some(split) => split,
+            None => return Self::new(BodyInner::identity(body)),
+        };
+
+        let inner = match last {
+            #[cfg(feature = "decompression-gzip")]
+            Coding::Gzip => BodyInner::gzip(WrapBody::new(body, CompressionLevel::default())),
+            #[cfg(feature = "decompression-deflate")]
+            Coding::Deflate => BodyInner::deflate(WrapBody::new(body, CompressionLevel::default())),
+            #[cfg(feature = "decompression-br")]
+            Coding::Brotli => BodyInner::brotli(WrapBody::new(body, CompressionLevel::default())),
+            #[cfg(feature = "decompression-zstd")]
+            Coding::Zstd => BodyInner::zstd(WrapBody::new(body, CompressionLevel::default())),
+        };
+        Self {
+            stacked: StackedDecoders::new(stacked),
+            ..Self::new(inner)
+        }
+    }
+
+    /// Fail the body once it decompresses to more than `limits` allow.
+    pub(crate) fn with_limits(
+        mut self,
+        limits: DecompressionLimits,
+        exceeded: Option<Arc<AtomicBool>>,
+    ) -> Self {
+        self.limits = limits;
+        self.exceeded = exceeded;
+        self
     }
 
     /// Get a reference to the inner body
@@ -203,6 +253,115 @@ where
     }
 }
 
+impl<B> DecompressionBody<B>
+where
+    B: Body,
+{
+    /// The number of compressed bytes read so far, or `None` if the body isn't decompressed.
+    fn compressed_len(&self) -> Option<u64> {
+        match &self.inner {
+            #[cfg(feature = "decompression-gzip")]
+            BodyInner::Gzip { inner } => {
+                Some(inner.read.get_ref().get_ref().get_ref().bytes_read())
+            }
+            #[cfg(feature = "decompression-deflate")]
+            BodyInner::Deflate { inner } => {
+                Some(inner.read.get_ref().get_ref().get_ref().bytes_read())
+            }
+            #[cfg(feature = "decompression-br")]
+            BodyInner::Brotli { inner } => {
+                Some(inner.read.get_ref().get_ref().get_ref().bytes_read())
+            }
+            #[cfg(feature = "decompression-zstd")]
+            BodyInner::Zstd { inner } => {
+                Some(inner.read.get_ref().get_ref().get_ref().bytes_read())
+            }
+            BodyInner::Identity { .. } => None,
+
+            #[cfg(not(feature = "decompression-gzip"))]
+            BodyInner::Gzip { inner } => match inner.0 {},
+            #[cfg(not(feature = "decompression-deflate"))]
+            BodyInner::Deflate { inner } => match inner.0 {},
+            #[cfg(not(feature = "decompression-br"))]
+            BodyInner::Brotli { inner } => match inner.0 {},
+            #[cfg(not(feature = "decompression-zstd"))]
+            BodyInner::Zstd { inner } => match inner.0 {},
+        }
+    }
+}
+
+/// Limits on how much bodies may be decompressed to.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct DecompressionLimits {
+    pub(crate) max_size: Option<usize>,
+    pub(crate) max_ratio: Option<u32>,
+}
+
+impl DecompressionLimits {
+    pub(crate) fn is_unlimited(&self) -> bool {
+        self.max_size.is_none() && self.max_ratio.is_none()
+    }
+
+    /// The limit exceeded by decompressing `compressed` bytes to `decompressed` bytes, if any.
+    fn check(&self, decompressed: u64, compressed: u64) -> Option<DecompressionLimitError> {
+        if let Some(max_size) = self.max_size {
+            if decompressed > max_size as u64 {
+                return Some(DecompressionLimitError {
+                    kind: LimitKind::Size,
+                });
+            }
+        }
+        if let Some(max_ratio) = self.max_ratio {
+            if decompressed > compressed.saturating_mul(max_ratio.into()) {
+                return Some(DecompressionLimitError {
+                    kind: LimitKind::Ratio,
+                });
+            }
+        }
+        None
+    }
+}
+
+/// Error for [`DecompressionBody`] when the body decompresses to more than allowed.
+///
+/// See [`Decompression::max_decompressed_size`] and [`Decompression::max_expansion_ratio`].
+///
+/// [`Decompression::max_decompressed_size`]: super::Decompression::max_decompressed_size
+/// [`Decompression::max_expansion_ratio`]: super::Decompression::max_expansion_ratio
+#[derive(Debug)]
+pub struct DecompressionLimitError {
+    kind: LimitKind,
+}
+
+#[derive(Debug)]
+enum LimitKind {
+    Size,
+    Ratio,
+}
+
+impl DecompressionLimitError {
+    /// Returns `true` if the body exceeded the maximum decompressed size.
+    pub fn is_size_exceeded(&self) -> bool {
+        matches!(self.kind, LimitKind::Size)
+    }
+
+    /// Returns `true` if the body exceeded the maximum expansion ratio.
+    pub fn is_ratio_exceeded(&self) -> bool {
+        matches!(self.kind, LimitKind::Ratio)
+    }
+}
+
+impl std::error::Error for DecompressionLimitError {}
+
+impl fmt::Display for DecompressionLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            LimitKind::Size => write!(f, "decompressed body exceeded the maximum size"),
+            LimitKind::Ratio => write!(f, "decompressed body exceeded the maximum expansion ratio"),
+        }
+    }
+}
+
 #[cfg(any(
     not(feature = "decompression-gzip"),
     not(feature = "decompression-deflate"),
@@ -295,10 +454,76 @@ where
     type Error = BoxError;
 
     fn poll_frame(
-        self: Pin<&mut Self>,
+        mut self: Pin<&mut Self>,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
-        match self.project().inner.project() {
+        if self.failed {
+            return Poll::Ready(None);
+        }
+
+        let frame = ready!(self.as_mut().poll_inner(cx));
+        if self.limits.is_unlimited() {
+            return Poll::Ready(frame);
+        }
+
+        let compressed = match self.compressed_len() {
+            Some(compressed) => compressed,
+            None => return Poll::Ready(frame),
+        };
+        let this = self.project();
+        if let Some(Ok(frame)) = &frame {
+            *this.decompressed += frame.data_ref().map_or(0, Bytes::len) as u64;
+        }
+        match this.limits.check(*this.decompressed, compressed) {
+            Some(err) => {
+                *this.failed = true;
+                if let Some(exceeded) = this.exceeded {
+                    exceeded.store(true, Ordering::SeqCst);
+                }
+                Poll::Ready(Some(Err(err.into())))
+            }
+            None => Poll::Ready(frame),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self.inner {
+            BodyInner::Identity { ref inner } => inner.size_hint(),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+impl<B> DecompressionBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    fn poll_inner(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, BoxError>>> {
+        let this = self.project();
+        if this.stacked.is_empty() {
+            return this.inner.poll_frame(cx);
+        }
+
+        let mut inner = this.inner;
+        this.stacked
+            .poll_frame(cx, |cx| inner.as_mut().poll_frame(cx))
+    }
+}
+
+impl<B> BodyInner<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, BoxError>>> {
+        match self.project() {
             #[cfg(feature = "decompression-gzip")]
             BodyInnerProj::Gzip { inner } => inner.poll_frame(cx),
             #[cfg(feature = "decompression-deflate")]
@@ -326,13 +551,6 @@ where
             BodyInnerProj::Zstd { inner } => match inner.0 {},
         }
     }
-
-    fn size_hint(&self) -> SizeHint {
-        match self.inner {
-            BodyInner::Identity { ref inner } => inner.size_hint(),
-            _ => SizeHint::default(),
-        }
-    }
 }
 
 #[cfg(feature = "decompression-gzip")]
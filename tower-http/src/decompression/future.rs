@@ -1,8 +1,9 @@
-#![allow(unused_imports)]
-
-use super::{body::BodyInner, DecompressionBody};
-use crate::compression_utils::{AcceptEncoding, CompressionLevel, WrapBody};
-use crate::content_encoding::SupportedEncodings;
+use super::{
+    body::{BodyInner, DecompressionLimits},
+    stacked::parse_codings,
+    DecompressionBody,
+};
+use crate::compression_utils::AcceptEncoding;
 use http::{header, Response};
 use http_body::Body;
 use pin_project_lite::pin_project;
@@ -21,6 +22,8 @@ pin_project! {
         #[pin]
         pub(crate) inner: F,
         pub(crate) accept: AcceptEncoding,
+        pub(crate) limits: DecompressionLimits,
+        pub(crate) max_depth: usize,
     }
 }
 
@@ -31,50 +34,21 @@ where
 {
     type Output = Result<Response<DecompressionBody<B>>, E>;
 
-    #[allow(unreachable_code, unused_mut, unused_variables)]
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let res = ready!(self.as_mut().project().inner.poll(cx)?);
         let (mut parts, body) = res.into_parts();
 
-        let res =
-            if let header::Entry::Occupied(entry) = parts.headers.entry(header::CONTENT_ENCODING) {
-                let body = match entry.get().as_bytes() {
-                    #[cfg(feature = "decompression-gzip")]
-                    b"gzip" if self.accept.gzip() => DecompressionBody::new(BodyInner::gzip(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    #[cfg(feature = "decompression-deflate")]
-                    b"deflate" if self.accept.deflate() => DecompressionBody::new(
-                        BodyInner::deflate(WrapBody::new(body, CompressionLevel::default())),
-                    ),
-
-                    #[cfg(feature = "decompression-br")]
-                    b"br" if self.accept.br() => DecompressionBody::new(BodyInner::brotli(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    #[cfg(feature = "decompression-zstd")]
-                    b"zstd" if self.accept.zstd() => DecompressionBody::new(BodyInner::zstd(
-                        WrapBody::new(body, CompressionLevel::default()),
-                    )),
-
-                    _ => {
-                        return Poll::Ready(Ok(Response::from_parts(
-                            parts,
-                            DecompressionBody::new(BodyInner::identity(body)),
-                        )))
-                    }
-                };
-
-                entry.remove();
+        // bodies with codings that can't all be decoded are passed through as they are
+        let codings = parse_codings(&parts.headers, self.accept, self.max_depth);
+        let body = match codings {
+            Some(codings) if !codings.is_empty() => {
+                parts.headers.remove(header::CONTENT_ENCODING);
                 parts.headers.remove(header::CONTENT_LENGTH);
+                DecompressionBody::decode(body, &codings).with_limits(self.limits, None)
+            }
+            _ => DecompressionBody::new(BodyInner::identity(body)),
+        };
 
-                Response::from_parts(parts, body)
-            } else {
-                Response::from_parts(parts, DecompressionBody::new(BodyInner::identity(body)))
-            };
-
-        Poll::Ready(Ok(res))
+        Poll::Ready(Ok(Response::from_parts(parts, body)))
     }
 }
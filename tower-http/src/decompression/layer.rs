@@ -1,3 +1,5 @@
+use super::body::DecompressionLimits;
+use super::stacked::DEFAULT_MAX_DEPTH;
 use super::Decompression;
 use crate::compression_utils::AcceptEncoding;
 use tower_layer::Layer;
@@ -8,9 +10,21 @@ use tower_layer::Layer;
 /// bodies based on the `Content-Encoding` header.
 ///
 /// See the [module docs](crate::decompression) for more details.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct DecompressionLayer {
     accept: AcceptEncoding,
+    limits: DecompressionLimits,
+    max_depth: usize,
+}
+
+impl Default for DecompressionLayer {
+    fn default() -> Self {
+        Self {
+            accept: AcceptEncoding::default(),
+            limits: DecompressionLimits::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
 }
 
 impl<S> Layer<S> for DecompressionLayer {
@@ -20,6 +34,8 @@ impl<S> Layer<S> for DecompressionLayer {
         Decompression {
             inner: service,
             accept: self.accept,
+            limits: self.limits,
+            max_depth: self.max_depth,
         }
     }
 }
@@ -58,6 +74,31 @@ impl DecompressionLayer {
         self
     }
 
+    /// Sets the maximum number of bytes response bodies may be decompressed to.
+    ///
+    /// See [`Decompression::max_decompressed_size`] for more details.
+    pub fn max_decompressed_size(mut self, limit: usize) -> Self {
+        self.limits.max_size = Some(limit);
+        self
+    }
+
+    /// Sets the maximum ratio of the decompressed size of response bodies to their compressed
+    /// size.
+    ///
+    /// See [`Decompression::max_expansion_ratio`] for more details.
+    pub fn max_expansion_ratio(mut self, ratio: u32) -> Self {
+        self.limits.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets the maximum number of content codings response bodies are decoded from.
+    ///
+    /// See [`Decompression::max_encoding_depth`] for more details.
+    pub fn max_encoding_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
     /// Disables the gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
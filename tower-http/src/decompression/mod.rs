@@ -97,9 +97,12 @@ mod body;
 mod future;
 mod layer;
 mod service;
+mod stacked;
 
 pub use self::{
-    body::DecompressionBody, future::ResponseFuture, layer::DecompressionLayer,
+    body::{DecompressionBody, DecompressionLimitError},
+    future::ResponseFuture,
+    layer::DecompressionLayer,
     service::Decompression,
 };
 
@@ -184,6 +187,103 @@ mod tests {
         Ok(res)
     }
 
+    #[tokio::test]
+    async fn decompress_stacked_encodings() {
+        let mut client = Decompression::new(service_fn(handle_deflate_gzip));
+
+        let req = Request::new(Body::empty());
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+        assert!(!res.headers().contains_key("content-encoding"));
+
+        let collected = res.into_body().collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["foo"], "bar");
+        assert_eq!(collected.to_bytes(), "Hello, World!");
+    }
+
+    #[tokio::test]
+    async fn passes_through_encodings_beyond_max_depth() {
+        let mut client = Decompression::new(service_fn(handle_deflate_gzip)).max_encoding_depth(1);
+
+        let req = Request::new(Body::empty());
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(res.headers()["content-encoding"], "deflate, gzip");
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, deflate_gzip(b"Hello, World!"));
+    }
+
+    async fn handle_deflate_gzip(
+        _req: Request<Body>,
+    ) -> Result<Response<WithTrailers<Body>>, Infallible> {
+        let mut trailers = HeaderMap::new();
+        trailers.insert(HeaderName::from_static("foo"), "bar".parse().unwrap());
+        let body = Body::from(deflate_gzip(b"Hello, World!")).with_trailers(trailers);
+
+        let mut res = Response::new(body);
+        res.headers_mut()
+            .insert("content-encoding", "deflate, gzip".parse().unwrap());
+        Ok(res)
+    }
+
+    fn deflate_gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(data).unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&deflated).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn limits_decompressed_size() {
+        let mut client =
+            Decompression::new(service_fn(handle_zeros)).max_decompressed_size(64 * 1024);
+
+        let req = Request::new(Body::empty());
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+
+        let err = res.into_body().collect().await.unwrap_err();
+        let err = err.downcast_ref::<DecompressionLimitError>().unwrap();
+        assert!(err.is_size_exceeded());
+    }
+
+    #[tokio::test]
+    async fn limits_expansion_ratio() {
+        let mut client = Decompression::new(service_fn(handle_zeros)).max_expansion_ratio(100);
+
+        let req = Request::new(Body::empty());
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+
+        let err = res.into_body().collect().await.unwrap_err();
+        let err = err.downcast_ref::<DecompressionLimitError>().unwrap();
+        assert!(err.is_ratio_exceeded());
+    }
+
+    #[tokio::test]
+    async fn bodies_within_limits_are_decompressed() {
+        let mut client = Decompression::new(service_fn(handle_zeros))
+            .max_decompressed_size(1024 * 1024)
+            .max_expansion_ratio(10_000);
+
+        let req = Request::new(Body::empty());
+        let res = client.ready().await.unwrap().call(req).await.unwrap();
+
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.len(), 1024 * 1024);
+    }
+
+    // A megabyte of zeros, compressed to about a kilobyte.
+    async fn handle_zeros(_req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let mut encoder = GzEncoder::new(Vec::new(), Default::default());
+        encoder.write_all(&[0; 1024 * 1024]).unwrap();
+
+        let mut res = Response::new(Body::from(encoder.finish().unwrap()));
+        res.headers_mut()
+            .insert("content-encoding", "gzip".parse().unwrap());
+        Ok(res)
+    }
+
     #[allow(dead_code)]
     async fn is_compatible_with_hyper() {
         let client =
@@ -9,8 +9,10 @@ use http_body_util::Empty;
 use pin_project_lite::pin_project;
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::task::Context;
-use std::task::Poll;
+use std::task::{ready, Poll};
 
 pin_project! {
     #[derive(Debug)]
@@ -35,7 +37,8 @@ pin_project! {
     {
         Inner {
             #[pin]
-            fut: F
+            fut: F,
+            exceeded: Option<Arc<AtomicBool>>,
         },
         Unsupported {
             #[pin]
@@ -57,9 +60,9 @@ where
     }
 
     #[must_use]
-    pub(super) fn inner(fut: F) -> Self {
+    pub(super) fn inner(fut: F, exceeded: Option<Arc<AtomicBool>>) -> Self {
         Self {
-            kind: Kind::Inner { fut },
+            kind: Kind::Inner { fut, exceeded },
         }
     }
 }
@@ -75,9 +78,24 @@ where
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.project().kind.project() {
-            StateProj::Inner { fut } => fut.poll(cx).map_ok(|res| {
-                res.map(|body| UnsyncBoxBody::new(body.map_err(Into::into).boxed_unsync()))
-            }),
+            StateProj::Inner { fut, exceeded } => {
+                let res = ready!(fut.poll(cx))?;
+                let exceeded = exceeded
+                    .as_ref()
+                    .map_or(false, |exceeded| exceeded.load(Ordering::SeqCst));
+                if exceeded {
+                    let res = Response::builder()
+                        .status(StatusCode::PAYLOAD_TOO_LARGE)
+                        .body(UnsyncBoxBody::new(
+                            Empty::new().map_err(Into::into).boxed_unsync(),
+                        ))
+                        .unwrap();
+                    return Poll::Ready(Ok(res));
+                }
+                Poll::Ready(Ok(res.map(|body| {
+                    UnsyncBoxBody::new(body.map_err(Into::into).boxed_unsync())
+                })))
+            }
             StateProj::Unsupported { accept } => {
                 let res = Response::builder()
                     .header(
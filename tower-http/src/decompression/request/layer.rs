@@ -1,5 +1,7 @@
 use super::service::RequestDecompression;
 use crate::compression_utils::AcceptEncoding;
+use crate::decompression::body::DecompressionLimits;
+use crate::decompression::stacked::DEFAULT_MAX_DEPTH;
 use tower_layer::Layer;
 
 /// Decompresses request bodies and calls its underlying service.
@@ -13,10 +15,23 @@ use tower_layer::Layer;
 /// This is disabled by default.
 ///
 /// See the [module docs](crate::decompression) for more details.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Clone)]
 pub struct RequestDecompressionLayer {
     accept: AcceptEncoding,
     pass_through_unaccepted: bool,
+    limits: DecompressionLimits,
+    max_depth: usize,
+}
+
+impl Default for RequestDecompressionLayer {
+    fn default() -> Self {
+        Self {
+            accept: AcceptEncoding::default(),
+            pass_through_unaccepted: false,
+            limits: DecompressionLimits::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
 }
 
 impl<S> Layer<S> for RequestDecompressionLayer {
@@ -27,6 +42,8 @@ impl<S> Layer<S> for RequestDecompressionLayer {
             inner: service,
             accept: self.accept,
             pass_through_unaccepted: self.pass_through_unaccepted,
+            limits: self.limits,
+            max_depth: self.max_depth,
         }
     }
 }
@@ -65,6 +82,31 @@ impl RequestDecompressionLayer {
         self
     }
 
+    /// Sets the maximum number of bytes request bodies may be decompressed to.
+    ///
+    /// See [`RequestDecompression::max_decompressed_size`] for more details.
+    pub fn max_decompressed_size(mut self, limit: usize) -> Self {
+        self.limits.max_size = Some(limit);
+        self
+    }
+
+    /// Sets the maximum ratio of the decompressed size of request bodies to their compressed
+    /// size.
+    ///
+    /// See [`RequestDecompression::max_expansion_ratio`] for more details.
+    pub fn max_expansion_ratio(mut self, ratio: u32) -> Self {
+        self.limits.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets the maximum number of content codings request bodies are decoded from.
+    ///
+    /// See [`RequestDecompression::max_encoding_depth`] for more details.
+    pub fn max_encoding_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
     /// Disables support for gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
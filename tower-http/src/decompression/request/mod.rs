@@ -5,7 +5,7 @@ pub(super) mod service;
 #[cfg(test)]
 mod tests {
     use super::service::RequestDecompression;
-    use crate::decompression::DecompressionBody;
+    use crate::decompression::{DecompressionBody, DecompressionLimitError};
     use crate::test_helpers::Body;
     use flate2::{write::GzEncoder, Compression};
     use http::{header, Request, Response, StatusCode};
@@ -44,6 +44,49 @@ mod tests {
         let _ = svc.ready().await.unwrap().call(req).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn decompress_stacked_encodings() {
+        let req = request_deflate_gzip();
+        let mut svc = RequestDecompression::new(service_fn(assert_request_is_decompressed));
+        let _ = svc.ready().await.unwrap().call(req).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn too_many_encodings_returns_unsupported_media_type() {
+        let req = request_deflate_gzip();
+        let mut svc =
+            RequestDecompression::new(service_fn(should_not_be_called)).max_encoding_depth(1);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, res.status());
+    }
+
+    #[tokio::test]
+    async fn payload_too_large_when_limit_is_exceeded() {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 1024 * 1024]).unwrap();
+        let req = Request::builder()
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(encoder.finish().unwrap()))
+            .unwrap();
+
+        let mut svc = RequestDecompression::new(service_fn(assert_limit_is_exceeded))
+            .max_decompressed_size(64 * 1024);
+        let res = svc.ready().await.unwrap().call(req).await.unwrap();
+        assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, res.status());
+    }
+
+    async fn assert_limit_is_exceeded(
+        req: Request<DecompressionBody<Body>>,
+    ) -> Result<Response<Body>, Infallible> {
+        let err = req.into_body().collect().await.unwrap_err();
+        assert!(err.is::<DecompressionLimitError>());
+
+        Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::empty())
+            .unwrap())
+    }
+
     async fn assert_request_is_decompressed(
         req: Request<DecompressionBody<Body>>,
     ) -> Result<Response<Body>, Infallible> {
@@ -84,6 +127,22 @@ mod tests {
             .unwrap()
     }
 
+    // The codings are listed in separate headers.
+    fn request_deflate_gzip() -> Request<Body> {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Hello?").unwrap();
+        let deflated = encoder.finish().unwrap();
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&deflated).unwrap();
+        let body = encoder.finish().unwrap();
+        Request::builder()
+            .header(header::CONTENT_ENCODING, "deflate")
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::from(body))
+            .unwrap()
+    }
+
     async fn read_body(body: &mut DecompressionBody<Body>) -> Vec<u8> {
         body.collect().await.unwrap().to_bytes().to_vec()
     }
@@ -1,25 +1,22 @@
 use super::future::RequestDecompressionFuture as ResponseFuture;
 use super::layer::RequestDecompressionLayer;
 use crate::body::UnsyncBoxBody;
-use crate::compression_utils::CompressionLevel;
 use crate::{
-    compression_utils::AcceptEncoding, decompression::body::BodyInner,
-    decompression::DecompressionBody, BoxError,
+    compression_utils::AcceptEncoding,
+    decompression::body::DecompressionLimits,
+    decompression::stacked::{parse_codings, DEFAULT_MAX_DEPTH},
+    decompression::DecompressionBody,
+    BoxError,
 };
 use bytes::Buf;
 use http::{header, Request, Response};
 use http_body::Body;
-use std::task::{Context, Poll};
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    task::{Context, Poll},
+};
 use tower_service::Service;
 
-#[cfg(any(
-    feature = "decompression-gzip",
-    feature = "decompression-deflate",
-    feature = "decompression-br",
-    feature = "decompression-zstd",
-))]
-use crate::content_encoding::SupportedEncodings;
-
 /// Decompresses request bodies and calls its underlying service.
 ///
 /// Transparently decompresses request bodies based on the `Content-Encoding` header.
@@ -36,6 +33,8 @@ pub struct RequestDecompression<S> {
     pub(super) inner: S,
     pub(super) accept: AcceptEncoding,
     pub(super) pass_through_unaccepted: bool,
+    pub(super) limits: DecompressionLimits,
+    pub(super) max_depth: usize,
 }
 
 impl<S, ReqBody, ResBody, D> Service<Request<ReqBody>> for RequestDecompression<S>
@@ -57,55 +56,28 @@ where
     fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
         let (mut parts, body) = req.into_parts();
 
-        let body =
-            if let header::Entry::Occupied(entry) = parts.headers.entry(header::CONTENT_ENCODING) {
-                match entry.get().as_bytes() {
-                    #[cfg(feature = "decompression-gzip")]
-                    b"gzip" if self.accept.gzip() => {
-                        entry.remove();
-                        parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::gzip(crate::compression_utils::WrapBody::new(
-                            body,
-                            CompressionLevel::default(),
-                        ))
-                    }
-                    #[cfg(feature = "decompression-deflate")]
-                    b"deflate" if self.accept.deflate() => {
-                        entry.remove();
-                        parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::deflate(crate::compression_utils::WrapBody::new(
-                            body,
-                            CompressionLevel::default(),
-                        ))
-                    }
-                    #[cfg(feature = "decompression-br")]
-                    b"br" if self.accept.br() => {
-                        entry.remove();
-                        parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::brotli(crate::compression_utils::WrapBody::new(
-                            body,
-                            CompressionLevel::default(),
-                        ))
-                    }
-                    #[cfg(feature = "decompression-zstd")]
-                    b"zstd" if self.accept.zstd() => {
-                        entry.remove();
-                        parts.headers.remove(header::CONTENT_LENGTH);
-                        BodyInner::zstd(crate::compression_utils::WrapBody::new(
-                            body,
-                            CompressionLevel::default(),
-                        ))
-                    }
-                    b"identity" => BodyInner::identity(body),
-                    _ if self.pass_through_unaccepted => BodyInner::identity(body),
-                    _ => return ResponseFuture::unsupported_encoding(self.accept),
-                }
-            } else {
-                BodyInner::identity(body)
-            };
-        let body = DecompressionBody::new(body);
+        let codings = match parse_codings(&parts.headers, self.accept, self.max_depth) {
+            Some(codings) => codings,
+            None if self.pass_through_unaccepted => Vec::new(),
+            None => return ResponseFuture::unsupported_encoding(self.accept),
+        };
+        if !codings.is_empty() {
+            parts.headers.remove(header::CONTENT_ENCODING);
+            parts.headers.remove(header::CONTENT_LENGTH);
+        }
+        let mut body = DecompressionBody::decode(body, &codings);
+
+        // the response is replaced if the body turns out to decompress to too much
+        let exceeded = if self.limits.is_unlimited() {
+            None
+        } else {
+            let exceeded = Arc::new(AtomicBool::new(false));
+            body = body.with_limits(self.limits, Some(exceeded.clone()));
+            Some(exceeded)
+        };
+
         let req = Request::from_parts(parts, body);
-        ResponseFuture::inner(self.inner.call(req))
+        ResponseFuture::inner(self.inner.call(req), exceeded)
     }
 }
 
@@ -116,6 +88,8 @@ impl<S> RequestDecompression<S> {
             inner: service,
             accept: AcceptEncoding::default(),
             pass_through_unaccepted: false,
+            limits: DecompressionLimits::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
@@ -164,6 +138,49 @@ impl<S> RequestDecompression<S> {
         self
     }
 
+    /// Sets the maximum number of bytes request bodies may be decompressed to.
+    ///
+    /// Reading a body that decompresses to more fails with a [`DecompressionLimitError`] as soon
+    /// as the limit is crossed, and the response of the inner service is replaced with `413
+    /// Payload Too Large`. Bodies that aren't compressed are not limited, see
+    /// [`RequestBodyLimit`] for those.
+    ///
+    /// By default bodies aren't limited.
+    ///
+    /// [`DecompressionLimitError`]: crate::decompression::DecompressionLimitError
+    /// [`RequestBodyLimit`]: crate::limit::RequestBodyLimit
+    pub fn max_decompressed_size(mut self, limit: usize) -> Self {
+        self.limits.max_size = Some(limit);
+        self
+    }
+
+    /// Sets the maximum ratio of the decompressed size of request bodies to their compressed
+    /// size.
+    ///
+    /// The ratio is checked against the compressed bytes read so far. Reading a body that
+    /// exceeds it fails with a [`DecompressionLimitError`], and the response of the inner service
+    /// is replaced with `413 Payload Too Large`.
+    ///
+    /// By default bodies aren't limited.
+    ///
+    /// [`DecompressionLimitError`]: crate::decompression::DecompressionLimitError
+    pub fn max_expansion_ratio(mut self, ratio: u32) -> Self {
+        self.limits.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets the maximum number of content codings request bodies are decoded from.
+    ///
+    /// Bodies with stacked codings, like `Content-Encoding: gzip, br`, are decoded in the reverse
+    /// order the codings were applied. Requests with more codings are treated like those with an
+    /// unaccepted encoding.
+    ///
+    /// Defaults to 2.
+    pub fn max_encoding_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
     /// Disables support for gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
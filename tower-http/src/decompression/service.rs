@@ -1,4 +1,7 @@
-use super::{DecompressionBody, DecompressionLayer, ResponseFuture};
+use super::{
+    body::DecompressionLimits, stacked::DEFAULT_MAX_DEPTH, DecompressionBody, DecompressionLayer,
+    ResponseFuture,
+};
 use crate::compression_utils::AcceptEncoding;
 use http::{
     header::{self, ACCEPT_ENCODING},
@@ -18,6 +21,8 @@ use tower_service::Service;
 pub struct Decompression<S> {
     pub(crate) inner: S,
     pub(crate) accept: AcceptEncoding,
+    pub(crate) limits: DecompressionLimits,
+    pub(crate) max_depth: usize,
 }
 
 impl<S> Decompression<S> {
@@ -26,6 +31,8 @@ impl<S> Decompression<S> {
         Self {
             inner: service,
             accept: AcceptEncoding::default(),
+            limits: DecompressionLimits::default(),
+            max_depth: DEFAULT_MAX_DEPTH,
         }
     }
 
@@ -66,6 +73,44 @@ impl<S> Decompression<S> {
         self
     }
 
+    /// Sets the maximum number of bytes response bodies may be decompressed to.
+    ///
+    /// Reading a body that decompresses to more fails with a [`DecompressionLimitError`] as soon
+    /// as the limit is crossed. Bodies that aren't compressed are not limited.
+    ///
+    /// By default bodies aren't limited.
+    ///
+    /// [`DecompressionLimitError`]: super::DecompressionLimitError
+    pub fn max_decompressed_size(mut self, limit: usize) -> Self {
+        self.limits.max_size = Some(limit);
+        self
+    }
+
+    /// Sets the maximum ratio of the decompressed size of response bodies to their compressed
+    /// size.
+    ///
+    /// The ratio is checked against the compressed bytes read so far, and reading a body that
+    /// exceeds it fails with a [`DecompressionLimitError`].
+    ///
+    /// By default bodies aren't limited.
+    ///
+    /// [`DecompressionLimitError`]: super::DecompressionLimitError
+    pub fn max_expansion_ratio(mut self, ratio: u32) -> Self {
+        self.limits.max_ratio = Some(ratio);
+        self
+    }
+
+    /// Sets the maximum number of content codings response bodies are decoded from.
+    ///
+    /// Bodies with stacked codings, like `Content-Encoding: gzip, br`, are decoded in the reverse
+    /// order the codings were applied. Bodies with more codings are passed through undecoded.
+    ///
+    /// Defaults to 2.
+    pub fn max_encoding_depth(mut self, depth: usize) -> Self {
+        self.max_depth = depth;
+        self
+    }
+
     /// Disables the gzip encoding.
     ///
     /// This method is available even if the `gzip` crate feature is disabled.
@@ -122,6 +167,8 @@ where
         ResponseFuture {
             inner: self.inner.call(req),
             accept: self.accept,
+            limits: self.limits,
+            max_depth: self.max_depth,
         }
     }
 }
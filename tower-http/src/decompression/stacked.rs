@@ -0,0 +1,320 @@
+//! Decoding bodies with more than one content coding, like `Content-Encoding: gzip, br`.
+//!
+//! The coding applied last is decoded by [`BodyInner`] directly from the body. The ones before it
+//! are decoded here, each reading the output of the decoder below it.
+//!
+//! [`BodyInner`]: super::body::BodyInner
+
+use crate::{content_encoding::SupportedEncodings, BoxError};
+#[cfg(feature = "decompression-br")]
+use async_compression::tokio::bufread::BrotliDecoder;
+#[cfg(feature = "decompression-gzip")]
+use async_compression::tokio::bufread::GzipDecoder;
+#[cfg(feature = "decompression-deflate")]
+use async_compression::tokio::bufread::ZlibDecoder;
+#[cfg(feature = "decompression-zstd")]
+use async_compression::tokio::bufread::ZstdDecoder;
+use bytes::{Buf, Bytes, BytesMut};
+use http::{header, HeaderMap};
+use http_body::Frame;
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+/// The default maximum number of content codings a body is decoded from.
+pub(crate) const DEFAULT_MAX_DEPTH: usize = 2;
+
+/// A content coding that can be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Coding {
+    #[cfg(feature = "decompression-gzip")]
+    Gzip,
+    #[cfg(feature = "decompression-deflate")]
+    Deflate,
+    #[cfg(feature = "decompression-br")]
+    Brotli,
+    #[cfg(feature = "decompression-zstd")]
+    Zstd,
+}
+
+impl Coding {
+    fn parse(s: &str, _accept: impl SupportedEncodings) -> Option<Self> {
+        #[cfg(feature = "decompression-gzip")]
+        if (s.eq_ignore_ascii_case("gzip") || s.eq_ignore_ascii_case("x-gzip")) && _accept.gzip() {
+            return Some(Coding::Gzip);
+        }
+
+        #[cfg(feature = "decompression-deflate")]
+        if s.eq_ignore_ascii_case("deflate") && _accept.deflate() {
+            return Some(Coding::Deflate);
+        }
+
+        #[cfg(feature = "decompression-br")]
+        if s.eq_ignore_ascii_case("br") && _accept.br() {
+            return Some(Coding::Brotli);
+        }
+
+        #[cfg(feature = "decompression-zstd")]
+        if s.eq_ignore_ascii_case("zstd") && _accept.zstd() {
+            return Some(Coding::Zstd);
+        }
+
+        None
+    }
+}
+
+/// The codings of all `Content-Encoding` headers, in the order they were applied.
+///
+/// Returns `None` if a coding isn't accepted or there are more than `max_depth`. `identity` is
+/// skipped so an empty list means the body isn't encoded.
+pub(crate) fn parse_codings(
+    headers: &HeaderMap,
+    accept: impl SupportedEncodings,
+    max_depth: usize,
+) -> Option<Vec<Coding>> {
+    let mut codings = Vec::new();
+    for value in headers.get_all(header::CONTENT_ENCODING) {
+        for coding in value.to_str().ok()?.split(',') {
+            let coding = coding.trim();
+            if coding.is_empty() || coding.eq_ignore_ascii_case("identity") {
+                continue;
+            }
+            codings.push(Coding::parse(coding, accept)?);
+        }
+    }
+
+    if codings.len() > max_depth {
+        return None;
+    }
+    Some(codings)
+}
+
+/// The decoders of the codings applied before the last one.
+pub(crate) struct StackedDecoders {
+    // the decoder reading from the body comes first
+    layers: Vec<Decoder>,
+    trailers: Option<HeaderMap>,
+    body_done: bool,
+}
+
+impl StackedDecoders {
+    /// Decoders for `codings`, in the order they were applied.
+    pub(crate) fn new(codings: &[Coding]) -> Self {
+        Self {
+            layers: codings
+                .iter()
+                .rev()
+                .map(|&coding| Decoder::new(coding))
+                .collect(),
+            trailers: None,
+            body_done: false,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Poll the next decoded frame, reading from `body` as needed.
+    pub(crate) fn poll_frame<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        mut body: F,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>>
+    where
+        F: FnMut(&mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, BoxError>>>,
+    {
+        let Self {
+            layers,
+            trailers,
+            body_done,
+        } = self;
+        let mut source = |cx: &mut Context<'_>| {
+            if *body_done {
+                return Poll::Ready(None);
+            }
+            // the trailers end the data of the body and are sent once everything was decoded
+            match ready!(body(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => return Poll::Ready(Some(Ok(data))),
+                    Err(frame) => *trailers = frame.into_trailers().ok(),
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => {}
+            }
+            *body_done = true;
+            Poll::Ready(None)
+        };
+
+        match ready!(poll_layers(layers, cx, &mut source)) {
+            Some(Ok(data)) => return Poll::Ready(Some(Ok(Frame::data(data)))),
+            Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+            None => {}
+        }
+
+        // decoders can finish before the end of their input, which is skipped to reach the
+        // trailers
+        while let Some(data) = ready!(source(cx)) {
+            data?;
+        }
+        Poll::Ready(
+            trailers
+                .take()
+                .map(|trailers| Ok(Frame::trailers(trailers))),
+        )
+    }
+}
+
+/// Poll the decoded data of the last of `layers`.
+fn poll_layers(
+    layers: &mut [Decoder],
+    cx: &mut Context<'_>,
+    source: &mut dyn FnMut(&mut Context<'_>) -> Poll<Option<Result<Bytes, BoxError>>>,
+) -> Poll<Option<Result<Bytes, BoxError>>> {
+    let (layer, below) = match layers.split_last_mut() {
+        Some(split) => split,
+        None => return source(cx),
+    };
+
+    loop {
+        if let Poll::Ready(data) = layer.poll_data(cx) {
+            return Poll::Ready(data.map_err(Into::into).transpose());
+        }
+        // decoders only wait for their input, which is read from the layer below
+        match ready!(poll_layers(below, cx, source)) {
+            Some(Ok(data)) => layer.input().push(data),
+            Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+            None => layer.input().finish(),
+        }
+    }
+}
+
+enum Decoder {
+    #[cfg(feature = "decompression-gzip")]
+    Gzip(GzipDecoder<Input>, BytesMut),
+    #[cfg(feature = "decompression-deflate")]
+    Deflate(ZlibDecoder<Input>, BytesMut),
+    #[cfg(feature = "decompression-br")]
+    Brotli(BrotliDecoder<Input>, BytesMut),
+    #[cfg(feature = "decompression-zstd")]
+    Zstd(ZstdDecoder<Input>, BytesMut),
+}
+
+impl Decoder {
+    const BUF_CAPACITY: usize = 4096;
+
+    fn new(coding: Coding) -> Self {
+        let buf = BytesMut::with_capacity(Self::BUF_CAPACITY);
+        match coding {
+            #[cfg(feature = "decompression-gzip")]
+            Coding::Gzip => {
+                let mut decoder = GzipDecoder::new(Input::default());
+                decoder.multiple_members(true);
+                Decoder::Gzip(decoder, buf)
+            }
+            #[cfg(feature = "decompression-deflate")]
+            Coding::Deflate => Decoder::Deflate(ZlibDecoder::new(Input::default()), buf),
+            #[cfg(feature = "decompression-br")]
+            Coding::Brotli => Decoder::Brotli(BrotliDecoder::new(Input::default()), buf),
+            #[cfg(feature = "decompression-zstd")]
+            Coding::Zstd => Decoder::Zstd(ZstdDecoder::new(Input::default()), buf),
+        }
+    }
+
+    fn input(&mut self) -> &mut Input {
+        match self {
+            #[cfg(feature = "decompression-gzip")]
+            Decoder::Gzip(decoder, _) => decoder.get_mut(),
+            #[cfg(feature = "decompression-deflate")]
+            Decoder::Deflate(decoder, _) => decoder.get_mut(),
+            #[cfg(feature = "decompression-br")]
+            Decoder::Brotli(decoder, _) => decoder.get_mut(),
+            #[cfg(feature = "decompression-zstd")]
+            Decoder::Zstd(decoder, _) => decoder.get_mut(),
+        }
+    }
+
+    /// Poll decoded data, which is pending only while waiting for more input.
+    fn poll_data(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<Option<Bytes>>> {
+        match self {
+            #[cfg(feature = "decompression-gzip")]
+            Decoder::Gzip(decoder, buf) => poll_decoded(decoder, buf, cx),
+            #[cfg(feature = "decompression-deflate")]
+            Decoder::Deflate(decoder, buf) => poll_decoded(decoder, buf, cx),
+            #[cfg(feature = "decompression-br")]
+            Decoder::Brotli(decoder, buf) => poll_decoded(decoder, buf, cx),
+            #[cfg(feature = "decompression-zstd")]
+            Decoder::Zstd(decoder, buf) => poll_decoded(decoder, buf, cx),
+        }
+    }
+}
+
+fn poll_decoded<R>(
+    decoder: &mut R,
+    buf: &mut BytesMut,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<Option<Bytes>>>
+where
+    R: AsyncRead + Unpin,
+{
+    if buf.capacity() == 0 {
+        buf.reserve(Decoder::BUF_CAPACITY);
+    }
+
+    match ready!(tokio_util::io::poll_read_buf(Pin::new(decoder), cx, buf))? {
+        0 => Poll::Ready(Ok(None)),
+        _ => Poll::Ready(Ok(Some(buf.split().freeze()))),
+    }
+}
+
+/// The input of a decoder, pushed to it from the layer below.
+///
+/// Reading it is pending while it's empty, without registering the waker, as the decoder reading
+/// it is then given more input before being polled again.
+#[derive(Default)]
+struct Input {
+    buf: Bytes,
+    done: bool,
+}
+
+impl Input {
+    fn push(&mut self, data: Bytes) {
+        self.buf = data;
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+    }
+}
+
+impl AsyncRead for Input {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let data = ready!(Pin::new(&mut *this).poll_fill_buf(cx))?;
+        let len = data.len().min(buf.remaining());
+        buf.put_slice(&data[..len]);
+        this.buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncBufRead for Input {
+    fn poll_fill_buf(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.buf.is_empty() && !this.done {
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(&this.buf[..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().buf.advance(amt);
+    }
+}
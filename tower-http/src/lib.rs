@@ -294,6 +294,9 @@ mod compression_utils;
 ))]
 pub use compression_utils::CompressionLevel;
 
+#[cfg(any(feature = "fs", feature = "range"))]
+mod range_utils;
+
 #[cfg(feature = "map-response-body")]
 pub mod map_response_body;
 
@@ -330,6 +333,9 @@ pub mod timeout;
 #[cfg(feature = "normalize-path")]
 pub mod normalize_path;
 
+#[cfg(feature = "range")]
+pub mod range;
+
 pub mod classify;
 pub mod services;
 
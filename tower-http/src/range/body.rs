@@ -0,0 +1,129 @@
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// Response body for [`Range`].
+    ///
+    /// [`Range`]: super::Range
+    pub struct ResponseBody<B> {
+        #[pin]
+        inner: ResponseBodyInner<B>,
+    }
+}
+
+impl<B> ResponseBody<B> {
+    pub(crate) fn full(body: B) -> Self {
+        Self {
+            inner: ResponseBodyInner::Full { body },
+        }
+    }
+
+    pub(crate) fn range(body: B, skip: u64, remaining: u64) -> Self {
+        Self {
+            inner: ResponseBodyInner::Range {
+                body,
+                skip,
+                remaining,
+            },
+        }
+    }
+
+    pub(crate) fn unsatisfiable() -> Self {
+        Self {
+            inner: ResponseBodyInner::Unsatisfiable,
+        }
+    }
+}
+
+pin_project! {
+    #[project = BodyProj]
+    enum ResponseBodyInner<B> {
+        Full {
+            #[pin]
+            body: B,
+        },
+        Range {
+            #[pin]
+            body: B,
+            skip: u64,
+            remaining: u64,
+        },
+        Unsatisfiable,
+    }
+}
+
+impl<B> Body for ResponseBody<B>
+where
+    B: Body<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let (mut body, skip, remaining) = match self.project().inner.project() {
+            BodyProj::Full { body } => return body.poll_frame(cx),
+            BodyProj::Range {
+                body,
+                skip,
+                remaining,
+            } => (body, skip, remaining),
+            BodyProj::Unsatisfiable => return Poll::Ready(None),
+        };
+
+        loop {
+            if *remaining == 0 {
+                return Poll::Ready(None);
+            }
+
+            let mut data = match ready!(body.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => data,
+                    // trailers don't apply to a part of the representation
+                    Err(_) => continue,
+                },
+                Some(Err(err)) => return Poll::Ready(Some(Err(err))),
+                None => return Poll::Ready(None),
+            };
+
+            let len = data.len() as u64;
+            if *skip >= len {
+                *skip -= len;
+                continue;
+            }
+            let mut data = data.split_off(*skip as usize);
+            *skip = 0;
+            data.truncate((*remaining).min(data.len() as u64) as usize);
+            *remaining -= data.len() as u64;
+
+            if !data.is_empty() {
+                return Poll::Ready(Some(Ok(Frame::data(data))));
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            ResponseBodyInner::Full { body } => body.is_end_stream(),
+            ResponseBodyInner::Range {
+                body, remaining, ..
+            } => *remaining == 0 || body.is_end_stream(),
+            ResponseBodyInner::Unsatisfiable => true,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            ResponseBodyInner::Full { body } => body.size_hint(),
+            ResponseBodyInner::Range { remaining, .. } => SizeHint::with_exact(*remaining),
+            ResponseBodyInner::Unsatisfiable => SizeHint::with_exact(0),
+        }
+    }
+}
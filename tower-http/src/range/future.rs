@@ -0,0 +1,140 @@
+use super::ResponseBody;
+use crate::range_utils::{if_range_passes, parse_entity_tag, parse_range};
+use bytes::Bytes;
+use http::{header, HeaderValue, Response, StatusCode};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// Response future for [`Range`].
+    ///
+    /// [`Range`]: super::Range
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        range: Option<HeaderValue>,
+        if_range: Option<HeaderValue>,
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(crate) fn new(inner: F, range: Option<HeaderValue>, if_range: Option<HeaderValue>) -> Self {
+        Self {
+            inner,
+            range,
+            if_range,
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body<Data = Bytes>,
+{
+    type Output = Result<Response<ResponseBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(apply_range(
+            res,
+            this.range.take(),
+            this.if_range.take(),
+        )))
+    }
+}
+
+fn apply_range<B>(
+    mut res: Response<B>,
+    range: Option<HeaderValue>,
+    if_range: Option<HeaderValue>,
+) -> Response<ResponseBody<B>>
+where
+    B: Body<Data = Bytes>,
+{
+    let size = match representation_size(&res) {
+        Some(size) => size,
+        None => return res.map(ResponseBody::full),
+    };
+
+    let headers = res.headers_mut();
+    if headers.get(header::ACCEPT_RANGES).is_some() {
+        // the inner service handles ranges itself, or opted out with `Accept-Ranges: none`
+        return res.map(ResponseBody::full);
+    }
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let last_modified = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|value| httpdate::parse_http_date(value.to_str().ok()?).ok())
+        .map(Into::into);
+    // weak entity tags can't be used with `If-Range`
+    let etag = headers
+        .get(header::ETAG)
+        .and_then(|value| parse_entity_tag(value.to_str().ok()?))
+        .filter(|(weak, _)| !weak)
+        .map(|(_, opaque)| opaque);
+
+    let range = range
+        .filter(|_| if_range_passes(if_range.as_ref(), last_modified, etag))
+        .and_then(|range| range.to_str().ok().map(ToOwned::to_owned));
+
+    match parse_range(range.as_deref(), size) {
+        Some(Ok(ranges)) if ranges.len() == 1 => {
+            let range = &ranges[0];
+            let len = range.end() - range.start() + 1;
+            let headers = res.headers_mut();
+            headers.insert(
+                header::CONTENT_RANGE,
+                content_range(format!("bytes {}-{}/{}", range.start(), range.end(), size)),
+            );
+            headers.insert(header::CONTENT_LENGTH, len.into());
+            *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let start = *range.start();
+            res.map(|body| ResponseBody::range(body, start, len))
+        }
+        Some(Err(_)) => {
+            let headers = res.headers_mut();
+            headers.insert(
+                header::CONTENT_RANGE,
+                content_range(format!("bytes */{}", size)),
+            );
+            headers.insert(header::CONTENT_LENGTH, HeaderValue::from_static("0"));
+            *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            res.map(|_| ResponseBody::unsatisfiable())
+        }
+        // several ranges can't be served from a body that can only be read once, the whole
+        // representation is sent instead
+        Some(Ok(_)) | None => res.map(ResponseBody::full),
+    }
+}
+
+// The size of the representation, if the response can be served partially: it has to be a
+// successful response with a known length and a validator.
+fn representation_size<B>(res: &Response<B>) -> Option<u64>
+where
+    B: Body,
+{
+    let headers = res.headers();
+    if res.status() != StatusCode::OK
+        || headers.contains_key(header::CONTENT_RANGE)
+        || !(headers.contains_key(header::ETAG) || headers.contains_key(header::LAST_MODIFIED))
+    {
+        return None;
+    }
+
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok())
+        .or_else(|| res.body().size_hint().exact())
+}
+
+fn content_range(value: String) -> HeaderValue {
+    HeaderValue::from_str(&value).expect("content range is a valid header value")
+}
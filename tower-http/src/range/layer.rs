@@ -0,0 +1,28 @@
+use super::Range;
+use tower_layer::Layer;
+
+/// Layer that applies the [`Range`] middleware that answers `Range` requests for responses
+/// with a known length and a validator.
+///
+/// See the [module docs](crate::range) for an example.
+///
+/// [`Range`]: super::Range
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RangeLayer {
+    _priv: (),
+}
+
+impl RangeLayer {
+    /// Create a new `RangeLayer`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<S> Layer<S> for RangeLayer {
+    type Service = Range<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Range::new(inner)
+    }
+}
@@ -0,0 +1,198 @@
+//! Middleware that answers range requests for arbitrary response bodies.
+//!
+//! [`ServeDir`] and [`ServeFile`] answer range requests themselves. This middleware does the
+//! same for other services, like handlers serving large blobs from memory or object storage: it
+//! honours the `Range` and `If-Range` headers of `GET` requests by slicing the response body,
+//! and answers with `206 Partial Content` and a `Content-Range` header, or `416 Range Not
+//! Satisfiable` for ranges outside of the body.
+//!
+//! Only `200 OK` responses with a known length, from a `Content-Length` header or the size hint
+//! of the body, and a validator, an `ETag` or `Last-Modified` header, are served partially. They
+//! are also sent with `Accept-Ranges: bytes`. Responses that already have an `Accept-Ranges`
+//! header are left alone, so services can handle ranges themselves or opt out with
+//! `Accept-Ranges: none`.
+//!
+//! The body of the response is read up to the end of the requested range, skipping the data
+//! before it, so services that can produce a part of a body cheaply should still handle ranges
+//! themselves. Requests for several ranges are answered with the whole body.
+//!
+//! [`ServeDir`]: crate::services::ServeDir
+//! [`ServeFile`]: crate::services::ServeFile
+//!
+//! # Example
+//!
+//! ```
+//! use bytes::Bytes;
+//! use http::{header, Request, Response, StatusCode};
+//! use http_body_util::{BodyExt, Full};
+//! use std::convert::Infallible;
+//! use tower::{ServiceBuilder, ServiceExt};
+//! use tower_http::range::RangeLayer;
+//!
+//! async fn handle(_req: Request<Full<Bytes>>) -> Result<Response<Full<Bytes>>, Infallible> {
+//!     let res = Response::builder()
+//!         .header(header::ETAG, "\"v1\"")
+//!         .body(Full::from("Hello, World!"))
+//!         .unwrap();
+//!     Ok(res)
+//! }
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+//! let svc = ServiceBuilder::new().layer(RangeLayer::new()).service_fn(handle);
+//!
+//! let req = Request::builder()
+//!     .header(header::RANGE, "bytes=7-11")
+//!     .body(Full::default())
+//!     .unwrap();
+//! let res = svc.oneshot(req).await?;
+//!
+//! assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+//! assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 7-11/13");
+//! assert_eq!(res.into_body().collect().await?.to_bytes(), "World");
+//! # Ok(())
+//! # }
+//! ```
+
+mod body;
+mod future;
+mod layer;
+mod service;
+
+pub use self::{body::ResponseBody, future::ResponseFuture, layer::RangeLayer, service::Range};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{to_bytes, Body};
+    use bytes::Bytes;
+    use futures_util::stream;
+    use http::{header, Method, Request, Response, StatusCode};
+    use http_body::Frame;
+    use std::convert::Infallible;
+    use tower::{service_fn, ServiceBuilder, ServiceExt};
+
+    const ETAG: &str = "\"v1\"";
+
+    async fn send(res: Response<Body>, req: Request<Body>) -> Response<ResponseBody<Body>> {
+        let mut res = Some(res);
+        let svc = ServiceBuilder::new()
+            .layer(RangeLayer::new())
+            .service(service_fn(move |_req: Request<Body>| {
+                let res = res.take().unwrap();
+                async move { Ok::<_, Infallible>(res) }
+            }));
+        svc.oneshot(req).await.unwrap()
+    }
+
+    fn blob() -> Response<Body> {
+        Response::builder()
+            .header(header::ETAG, ETAG)
+            .header(header::CONTENT_LENGTH, "26")
+            .body(Body::from("abcdefghijklmnopqrstuvwxyz"))
+            .unwrap()
+    }
+
+    fn get(range: &str) -> Request<Body> {
+        Request::builder()
+            .header(header::RANGE, range)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn single_range() {
+        let res = send(blob(), get("bytes=2-5")).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 2-5/26");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "4");
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(res.headers()[header::ETAG], ETAG);
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "cdef");
+
+        let res = send(blob(), get("bytes=-3")).await;
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 23-25/26");
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "xyz");
+    }
+
+    #[tokio::test]
+    async fn range_across_chunks() {
+        let chunks = ["abc", "def", "ghi", "jkl"]
+            .iter()
+            .map(|chunk| Ok::<_, Infallible>(Frame::data(Bytes::from(*chunk))));
+        let body = Body::new(http_body_util::StreamBody::new(stream::iter(chunks)));
+        let res = Response::builder()
+            .header(header::LAST_MODIFIED, "Fri, 09 Aug 1996 14:21:40 GMT")
+            .header(header::CONTENT_LENGTH, "12")
+            .body(body)
+            .unwrap();
+
+        let res = send(res, get("bytes=4-9")).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), "efghij");
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_range() {
+        let res = send(blob(), get("bytes=30-")).await;
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */26");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "0");
+        assert!(to_bytes(res.into_body()).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn if_range() {
+        let mut req = get("bytes=2-5");
+        req.headers_mut()
+            .insert(header::IF_RANGE, ETAG.parse().unwrap());
+        let res = send(blob(), req).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+
+        let mut req = get("bytes=2-5");
+        req.headers_mut()
+            .insert(header::IF_RANGE, "\"v0\"".parse().unwrap());
+        let res = send(blob(), req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            "abcdefghijklmnopqrstuvwxyz"
+        );
+    }
+
+    #[tokio::test]
+    async fn ignored_responses() {
+        // without a validator
+        let res = Response::builder()
+            .header(header::CONTENT_LENGTH, "26")
+            .body(Body::from("abcdefghijklmnopqrstuvwxyz"))
+            .unwrap();
+        let res = send(res, get("bytes=2-5")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::ACCEPT_RANGES).is_none());
+
+        // not successful
+        let mut res = blob();
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        let res = send(res, get("bytes=2-5")).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        // opted out
+        let mut res = blob();
+        res.headers_mut()
+            .insert(header::ACCEPT_RANGES, "none".parse().unwrap());
+        let res = send(res, get("bytes=2-5")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        // several ranges
+        let res = send(blob(), get("bytes=0-1,4-5")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::ACCEPT_RANGES], "bytes");
+
+        // not a `GET` request
+        let mut req = get("bytes=2-5");
+        *req.method_mut() = Method::POST;
+        let res = send(blob(), req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
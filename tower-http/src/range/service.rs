@@ -0,0 +1,60 @@
+use super::{RangeLayer, ResponseBody, ResponseFuture};
+use bytes::Bytes;
+use http::{header, Method, Request, Response};
+use http_body::Body;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// Middleware that answers `Range` requests for responses with a known length and a
+/// validator, by slicing the response body.
+///
+/// See the [module docs](crate::range) for an example.
+#[derive(Clone, Copy, Debug)]
+pub struct Range<S> {
+    inner: S,
+}
+
+impl<S> Range<S> {
+    /// Create a new `Range`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    define_inner_service_accessors!();
+
+    /// Returns a new [`Layer`] that wraps services with a `Range` middleware.
+    ///
+    /// [`Layer`]: tower_layer::Layer
+    pub fn layer() -> RangeLayer {
+        RangeLayer::new()
+    }
+}
+
+impl<ReqBody, ResBody, S> Service<Request<ReqBody>> for Range<S>
+where
+    ResBody: Body<Data = Bytes>,
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<ResponseBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        // the semantics of `Range` are only defined for `GET`
+        let (range, if_range) = if req.method() == Method::GET {
+            (
+                req.headers().get(header::RANGE).cloned(),
+                req.headers().get(header::IF_RANGE).cloned(),
+            )
+        } else {
+            (None, None)
+        };
+
+        ResponseFuture::new(self.inner.call(req), range, if_range)
+    }
+}
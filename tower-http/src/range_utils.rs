@@ -0,0 +1,125 @@
+//! Parsing of the `Range` and `If-Range` headers, shared by [`ServeDir`] and [`RangeLayer`].
+//!
+//! [`ServeDir`]: crate::services::ServeDir
+//! [`RangeLayer`]: crate::range::RangeLayer
+
+use http::HeaderValue;
+use http_range_header::RangeUnsatisfiableError;
+use httpdate::HttpDate;
+use std::ops::RangeInclusive;
+
+/// Parse a `Range` header value and validate it against the size of the representation.
+pub(crate) fn parse_range(
+    maybe_range_ref: Option<&str>,
+    size: u64,
+) -> Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>> {
+    maybe_range_ref.map(|header_value| {
+        http_range_header::parse_range_header(header_value)
+            .and_then(|first_pass| first_pass.validate(size))
+    })
+}
+
+/// A `Range` header must be ignored, and the full representation served, if the validator in an
+/// `If-Range` header doesn't match the current representation. Validators that can't be parsed
+/// never match.
+///
+/// `etag` is the opaque part of the current entity tag, without the quotes.
+pub(crate) fn if_range_passes(
+    if_range: Option<&HeaderValue>,
+    last_modified: Option<HttpDate>,
+    etag: Option<&str>,
+) -> bool {
+    if_range.map_or(true, |value| {
+        IfRange::from_header_value(value)
+            .map_or(false, |if_range| if_range.matches(last_modified, etag))
+    })
+}
+
+/// Parse a single entity tag, returning whether it is weak and its opaque part.
+pub(crate) fn parse_entity_tag(value: &str) -> Option<(bool, &str)> {
+    let (weak, quoted) = match value.strip_prefix("W/") {
+        Some(quoted) => (true, quoted),
+        None => (false, value),
+    };
+    let opaque = quoted.strip_prefix('"')?.strip_suffix('"')?;
+    if opaque.contains('"') {
+        return None;
+    }
+    Some((weak, opaque))
+}
+
+struct IfRange(Validator);
+
+enum Validator {
+    Date(HttpDate),
+    EntityTag { weak: bool, opaque: String },
+}
+
+impl IfRange {
+    /// Check if the validator matches the current representation, in which case the `Range`
+    /// header should be honoured.
+    ///
+    /// Dates must match exactly and entity tags are compared using the strong comparison
+    /// function, so weak entity tags never match.
+    fn matches(&self, last_modified: Option<HttpDate>, etag: Option<&str>) -> bool {
+        match &self.0 {
+            Validator::Date(date) => last_modified == Some(*date),
+            Validator::EntityTag { weak, opaque } => {
+                etag.map_or(false, |etag| !weak && opaque == etag)
+            }
+        }
+    }
+
+    /// Convert a header value into a IfRange, invalid values return `None`
+    fn from_header_value(value: &HeaderValue) -> Option<IfRange> {
+        let str_value = value.to_str().ok()?.trim();
+        if str_value.starts_with('"') || str_value.starts_with("W/") {
+            parse_entity_tag(str_value).map(|(weak, opaque)| {
+                IfRange(Validator::EntityTag {
+                    weak,
+                    opaque: opaque.to_owned(),
+                })
+            })
+        } else {
+            httpdate::parse_http_date(str_value)
+                .ok()
+                .map(|time| IfRange(Validator::Date(time.into())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_if_range() {
+        let if_range = IfRange::from_header_value(&HeaderValue::from_static("\"a\"")).unwrap();
+        assert!(if_range.matches(None, Some("a")));
+        assert!(!if_range.matches(None, Some("b")));
+
+        let if_range = IfRange::from_header_value(&HeaderValue::from_static("W/\"a\"")).unwrap();
+        assert!(!if_range.matches(None, Some("a")));
+
+        let if_range =
+            IfRange::from_header_value(&HeaderValue::from_static("Fri, 09 Aug 1996 14:21:40 GMT"))
+                .unwrap();
+        let last_modified = httpdate::parse_http_date("Fri, 09 Aug 1996 14:21:40 GMT").unwrap();
+        assert!(if_range.matches(Some(last_modified.into()), None));
+        assert!(!if_range.matches(None, Some("a")));
+
+        assert!(IfRange::from_header_value(&HeaderValue::from_static("\"a\", \"b\"")).is_none());
+        assert!(IfRange::from_header_value(&HeaderValue::from_static("garbage")).is_none());
+    }
+
+    #[test]
+    fn parse_ranges() {
+        assert_eq!(
+            parse_range(Some("bytes=2-5"), 10).unwrap().unwrap(),
+            [2..=5]
+        );
+        assert_eq!(parse_range(Some("bytes=-3"), 10).unwrap().unwrap(), [7..=9]);
+        assert!(parse_range(Some("bytes=20-"), 10).unwrap().is_err());
+        assert!(parse_range(None, 10).is_none());
+    }
+}
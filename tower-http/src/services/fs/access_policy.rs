@@ -0,0 +1,108 @@
+//! Policies restricting which files [`ServeDir`] serves.
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use super::glob::Glob;
+
+/// How [`ServeDir`] handles requests for paths with a component starting with `.`, like `.env`
+/// or `.git/config`.
+///
+/// See [`ServeDir::dot_files`].
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeDir::dot_files`]: super::ServeDir::dot_files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DotFiles {
+    /// Serve dotfiles like any other file.
+    #[default]
+    Allow,
+    /// Respond with `404 Not Found`, or call the fallback, as if the file didn't exist.
+    NotFound,
+    /// Respond with `403 Forbidden`.
+    Forbidden,
+}
+
+/// How [`ServeDir`] handles symbolic links.
+///
+/// See [`ServeDir::symlinks`].
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeDir::symlinks`]: super::ServeDir::symlinks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Symlinks {
+    /// Follow symbolic links, wherever they point to.
+    #[default]
+    Follow,
+    /// Follow symbolic links that point to files within the served directory.
+    FollowWithinRoot,
+    /// Never serve files through symbolic links.
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Access {
+    Allowed,
+    NotFound,
+    Forbidden,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(super) struct AccessPolicy {
+    pub(super) dot_files: DotFiles,
+    pub(super) symlinks: Symlinks,
+    pub(super) allow: Vec<Glob>,
+    pub(super) deny: Vec<Glob>,
+}
+
+impl AccessPolicy {
+    // Check a path relative to the served directory, using `/` as the separator.
+    //
+    // The allow list only applies to files, so requests for directories can still be redirected
+    // or answered with their `index.html`.
+    pub(super) fn check(&self, path: &str, is_file: bool) -> Access {
+        let is_dot_file = path.split('/').any(|component| component.starts_with('.'));
+        if is_dot_file {
+            match self.dot_files {
+                DotFiles::Allow => {}
+                DotFiles::NotFound => return Access::NotFound,
+                DotFiles::Forbidden => return Access::Forbidden,
+            }
+        }
+
+        if self.deny.iter().any(|glob| glob.matches(path)) {
+            return Access::NotFound;
+        }
+
+        if is_file && !self.allow.is_empty() && !self.allow.iter().any(|glob| glob.matches(path)) {
+            return Access::NotFound;
+        }
+
+        Access::Allowed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_dot_files_and_globs() {
+        let policy = AccessPolicy {
+            dot_files: DotFiles::Forbidden,
+            deny: vec![Glob::new("*.map")],
+            allow: vec![Glob::new("assets/**"), Glob::new("*.html")],
+            ..Default::default()
+        };
+
+        assert_eq!(policy.check("index.html", true), Access::Allowed);
+        assert_eq!(policy.check("assets/app.js", true), Access::Allowed);
+        assert_eq!(policy.check("assets", false), Access::Allowed);
+        assert_eq!(policy.check("assets/app.js.map", true), Access::NotFound);
+        assert_eq!(policy.check("Cargo.toml", true), Access::NotFound);
+        assert_eq!(policy.check(".env", true), Access::Forbidden);
+        assert_eq!(policy.check(".git/config", true), Access::Forbidden);
+        assert_eq!(policy.check("assets/.git", false), Access::Forbidden);
+    }
+}
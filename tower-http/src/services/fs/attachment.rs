@@ -0,0 +1,122 @@
+//! Serving files as downloads with `Content-Disposition: attachment`.
+
+use http::HeaderValue;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// Characters that are escaped in `filename*` parameters, everything but `attr-char` from
+// RFC 5987.
+const ATTR_CHAR: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'#')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'+')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'^')
+    .remove(b'_')
+    .remove(b'`')
+    .remove(b'|')
+    .remove(b'~');
+
+/// Serve a file as a download, with a `Content-Disposition: attachment` header.
+///
+/// Can be used with [`ServeFile::attachment`] and [`ServeDir::attachment`] to serve all files
+/// as downloads, or inserted into the extensions of a request to serve a single response as a
+/// download:
+///
+/// ```
+/// use http::Request;
+/// use tower_http::services::fs::Attachment;
+///
+/// let mut req = Request::new(());
+/// req.extensions_mut().insert(Attachment::with_filename("Quarterly report.pdf"));
+/// ```
+///
+/// File names that aren't plain ASCII are sent in a `filename*` parameter as described in
+/// [RFC 6266] and [RFC 5987], along with an ASCII approximation in a `filename` parameter for
+/// older clients.
+///
+/// [`ServeFile::attachment`]: super::ServeFile::attachment
+/// [`ServeDir::attachment`]: super::ServeDir::attachment
+/// [RFC 6266]: https://www.rfc-editor.org/rfc/rfc6266
+/// [RFC 5987]: https://www.rfc-editor.org/rfc/rfc5987
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Attachment {
+    filename: Option<String>,
+}
+
+impl Attachment {
+    /// Serve the file as a download named like the served file.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serve the file as a download named `filename`, rather than the name of the served file.
+    pub fn with_filename(filename: impl Into<String>) -> Self {
+        Self {
+            filename: Some(filename.into()),
+        }
+    }
+
+    pub(super) fn header_value(&self, served_file_name: Option<&str>) -> HeaderValue {
+        match self.filename.as_deref().or(served_file_name) {
+            Some(filename) => content_disposition(filename),
+            None => HeaderValue::from_static("attachment"),
+        }
+    }
+}
+
+fn content_disposition(filename: &str) -> HeaderValue {
+    let is_plain = filename
+        .chars()
+        .all(|c| c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\');
+
+    let value = if is_plain {
+        format!("attachment; filename=\"{}\"", filename)
+    } else {
+        let fallback: String = filename
+            .chars()
+            .map(|c| {
+                if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!(
+            "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+            fallback,
+            utf8_percent_encode(filename, ATTR_CHAR)
+        )
+    };
+
+    HeaderValue::from_str(&value).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_values() {
+        assert_eq!(Attachment::new().header_value(None), "attachment");
+        assert_eq!(
+            Attachment::new().header_value(Some("report.pdf")),
+            "attachment; filename=\"report.pdf\""
+        );
+        assert_eq!(
+            Attachment::with_filename("filename with space.txt").header_value(Some("report.pdf")),
+            "attachment; filename=\"filename with space.txt\""
+        );
+        assert_eq!(
+            Attachment::new().header_value(Some("你好世界.txt")),
+            "attachment; filename=\"____.txt\"; filename*=UTF-8''%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt"
+        );
+        assert_eq!(
+            Attachment::new().header_value(Some("say \"hi\".txt")),
+            "attachment; filename=\"say _hi_.txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! `Cache-Control` policies for [`ServeDir`].
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use super::glob::Glob;
+use http::HeaderValue;
+
+/// Policy deciding the `Cache-Control` header [`ServeDir`] sends for each file.
+///
+/// For every served file the first of these that applies decides the header:
+///
+/// 1. The rules added with [`CachePolicy::path`] and [`CachePolicy::extension`], in the order
+///    they were added.
+/// 2. HTML documents get [`CachePolicy::html`], `no-cache` by default, so browsers revalidate
+///    them and pick up new deployments.
+/// 3. Files with a content hash in their name, like `app.3f9a1c.js`, `app-3f9a1c.js` or
+///    `index-BXk3a9Zq.js`, get [`CachePolicy::fingerprinted`],
+///    `public, max-age=31536000, immutable` by default. Their contents never change so they
+///    can be cached forever.
+/// 4. All other files get [`CachePolicy::other_files`], no header by default.
+///
+/// Paths are matched relative to the served directory. The header is sent with `304 Not
+/// Modified` responses as well.
+///
+/// # Example
+///
+/// ```
+/// use http::HeaderValue;
+/// use tower_http::services::{fs::CachePolicy, ServeDir};
+///
+/// let policy = CachePolicy::new()
+///     .path("/downloads/**", HeaderValue::from_static("no-store"))
+///     .extension("woff2", HeaderValue::from_static("public, max-age=604800"))
+///     .other_files(HeaderValue::from_static("public, max-age=300"));
+///
+/// let service = ServeDir::new("dist").cache_policy(policy);
+/// ```
+///
+/// [`ServeDir`]: super::ServeDir
+#[derive(Clone, Debug)]
+pub struct CachePolicy {
+    rules: Vec<(Glob, HeaderValue)>,
+    html: Option<HeaderValue>,
+    fingerprinted: Option<HeaderValue>,
+    other_files: Option<HeaderValue>,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CachePolicy {
+    /// Create a new [`CachePolicy`] that revalidates HTML documents and caches fingerprinted
+    /// files forever.
+    pub fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            html: Some(HeaderValue::from_static("no-cache")),
+            fingerprinted: Some(HeaderValue::from_static(
+                "public, max-age=31536000, immutable",
+            )),
+            other_files: None,
+        }
+    }
+
+    /// Send `value` for files whose path matches the glob `pattern`.
+    ///
+    /// `*` matches any characters except `/`, `**` matches any characters including `/` and `?`
+    /// matches a single character except `/`. Patterns without a `/` are matched against the
+    /// file name, so `*.json` matches JSON files in any directory.
+    pub fn path(mut self, pattern: &str, value: HeaderValue) -> Self {
+        self.rules.push((Glob::new(pattern), value));
+        self
+    }
+
+    /// Send `value` for files with the extension `extension`, for example `"css"`.
+    pub fn extension(self, extension: &str, value: HeaderValue) -> Self {
+        let pattern = format!("*.{}", extension.trim_start_matches('.'));
+        self.path(&pattern, value)
+    }
+
+    /// Set the value sent for HTML documents.
+    ///
+    /// Defaults to `no-cache`.
+    pub fn html(mut self, value: HeaderValue) -> Self {
+        self.html = Some(value);
+        self
+    }
+
+    /// Set the value sent for files with a content hash in their name.
+    ///
+    /// Defaults to `public, max-age=31536000, immutable`.
+    pub fn fingerprinted(mut self, value: HeaderValue) -> Self {
+        self.fingerprinted = Some(value);
+        self
+    }
+
+    /// Disable detecting files with a content hash in their name.
+    pub fn no_fingerprint_detection(mut self) -> Self {
+        self.fingerprinted = None;
+        self
+    }
+
+    /// Set the value sent for files that no other part of the policy applies to.
+    ///
+    /// Defaults to not sending a `Cache-Control` header.
+    pub fn other_files(mut self, value: HeaderValue) -> Self {
+        self.other_files = Some(value);
+        self
+    }
+
+    pub(super) fn cache_control(&self, path: &str, mime: &HeaderValue) -> Option<HeaderValue> {
+        if let Some((_, value)) = self.rules.iter().find(|(glob, _)| glob.matches(path)) {
+            return Some(value.clone());
+        }
+
+        let is_html = mime
+            .to_str()
+            .ok()
+            .and_then(|mime| mime.split(';').next())
+            .map_or(false, |mime| mime.trim().eq_ignore_ascii_case("text/html"));
+        if is_html {
+            if let Some(value) = &self.html {
+                return Some(value.clone());
+            }
+        } else if let Some(value) = &self.fingerprinted {
+            let file_name = path.rsplit('/').next().unwrap_or(path);
+            if is_fingerprinted(file_name) {
+                return Some(value.clone());
+            }
+        }
+
+        self.other_files.clone()
+    }
+}
+
+// Whether the file name contains a content hash, as the last `.` or `-` separated part before
+// the extension. Hashes are either at least 6 hex digits or, like the base64 hashes some bundlers
+// use, at least 8 alphanumeric characters with a digit and an uppercase letter.
+fn is_fingerprinted(file_name: &str) -> bool {
+    let stem = match file_name.rsplit_once('.') {
+        Some((stem, _extension)) if !stem.is_empty() => stem,
+        _ => return false,
+    };
+    let hash = match stem.rfind(['.', '-']) {
+        Some(index) if index > 0 => &stem[index + 1..],
+        _ => return false,
+    };
+
+    let has_digit = hash.bytes().any(|b| b.is_ascii_digit());
+    let is_hex = hash.len() >= 6 && hash.bytes().all(|b| b.is_ascii_hexdigit());
+    let is_base64 = hash.len() >= 8
+        && hash.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+        && hash.bytes().any(|b| b.is_ascii_uppercase());
+
+    has_digit && (is_hex || is_base64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_fingerprinted_file_names() {
+        for name in [
+            "app.3f9a1c.js",
+            "app-3f9a1c.js",
+            "index-BXk3a9Zq.js",
+            "style.0123456789abcdef.css",
+        ] {
+            assert!(is_fingerprinted(name), "{}", name);
+        }
+
+        for name in [
+            "app.js",
+            "jquery-3.7.1.js",
+            "my-component.js",
+            "app.decade.js",
+            "api-v2beta.js",
+            ".3f9a1c.js",
+            "3f9a1c.js",
+            "README",
+        ] {
+            assert!(!is_fingerprinted(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,574 @@
+//! Cache of files compressed on the fly by [`ServeDir`].
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use super::{file_system::BoxFileRead, FileMetadata, FileSystem};
+use crate::content_encoding::{encodings, Encoding, SupportedEncodings};
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue};
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::SystemTime,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::OnceCell,
+};
+
+const DEFAULT_MAX_FILE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// A bounded cache of compressed variants of the files served by [`ServeDir`].
+///
+/// When a client accepts an encoding for which no precompressed file exists (see
+/// [`ServeDir::precompressed_gzip`] and friends), the file is compressed once, stored in the
+/// cache and served from there for subsequent requests. Compressed variants are keyed by the
+/// path, modification time and size of the file, so changed files are compressed again.
+/// Responses from the cache have a correct `Content-Length` and support range requests, like
+/// precompressed files.
+///
+/// The cache holds at most `max_size` bytes of compressed data. When it is full the least
+/// recently used variants are evicted. Files larger than [`CompressionCache::max_file_size`]
+/// and files that are already compressed, like images, are served uncompressed.
+///
+/// Only the encodings whose `compression-*` feature is enabled are used.
+///
+/// The cache can be cloned cheaply and shared between services.
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::{fs::CompressionCache, ServeDir};
+///
+/// // keep up to 64 MiB of compressed files in memory
+/// let service = ServeDir::new("assets")
+///     .compression_cache(CompressionCache::in_memory(64 * 1024 * 1024));
+/// ```
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeDir::precompressed_gzip`]: super::ServeDir::precompressed_gzip
+#[derive(Clone)]
+pub struct CompressionCache {
+    storage: Storage,
+    max_size: u64,
+    max_file_size: u64,
+    encodings: CacheEncodings,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Clone, Debug)]
+enum Storage {
+    Memory,
+    Directory(PathBuf),
+}
+
+#[derive(Clone, Copy, Debug)]
+struct CacheEncodings {
+    gzip: bool,
+    deflate: bool,
+    br: bool,
+    zstd: bool,
+}
+
+impl SupportedEncodings for CacheEncodings {
+    fn gzip(&self) -> bool {
+        self.gzip
+    }
+
+    fn deflate(&self) -> bool {
+        self.deflate
+    }
+
+    fn br(&self) -> bool {
+        self.br
+    }
+
+    fn zstd(&self) -> bool {
+        self.zstd
+    }
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<Key, Entry>,
+    // files being compressed, shared by concurrent requests for the same variant
+    pending: HashMap<Key, Arc<OnceCell<Bytes>>>,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    size: u64,
+    encoding: Encoding,
+}
+
+impl Key {
+    fn new(path: &Path, metadata: &FileMetadata, encoding: Encoding) -> Self {
+        Self {
+            path: path.to_owned(),
+            modified: metadata.modified(),
+            size: metadata.size(),
+            encoding,
+        }
+    }
+}
+
+struct Entry {
+    data: Stored,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Clone)]
+enum Stored {
+    Memory(Bytes),
+    File(PathBuf),
+}
+
+impl CompressionCache {
+    /// Create a cache that keeps up to `max_size` bytes of compressed files in memory.
+    pub fn in_memory(max_size: u64) -> Self {
+        Self::new(Storage::Memory, max_size)
+    }
+
+    /// Create a cache that stores up to `max_size` bytes of compressed files in the directory
+    /// `path`.
+    ///
+    /// The directory must exist and should be dedicated to the cache. Which files are in the
+    /// cache is only tracked in memory, so the cache starts out empty when the process restarts
+    /// and files left over from earlier processes are overwritten.
+    pub fn in_directory(path: impl Into<PathBuf>, max_size: u64) -> Self {
+        Self::new(Storage::Directory(path.into()), max_size)
+    }
+
+    fn new(storage: Storage, max_size: u64) -> Self {
+        Self {
+            storage,
+            max_size,
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            encodings: CacheEncodings {
+                gzip: cfg!(feature = "compression-gzip"),
+                deflate: cfg!(feature = "compression-deflate"),
+                br: cfg!(feature = "compression-br"),
+                zstd: cfg!(feature = "compression-zstd"),
+            },
+            state: Default::default(),
+        }
+    }
+
+    /// Set the size of the largest file, in bytes, that is compressed.
+    ///
+    /// Files are read into memory and compressed on a blocking thread, so this bounds the memory
+    /// and work needed to compress a single file. Defaults to 4 MiB.
+    pub fn max_file_size(mut self, max_file_size: u64) -> Self {
+        self.max_file_size = max_file_size;
+        self
+    }
+
+    /// Sets whether to compress with gzip.
+    ///
+    /// Defaults to `true` if the `compression-gzip` feature is enabled.
+    pub fn gzip(mut self, enable: bool) -> Self {
+        self.encodings.gzip = enable && cfg!(feature = "compression-gzip");
+        self
+    }
+
+    /// Sets whether to compress with deflate.
+    ///
+    /// Defaults to `true` if the `compression-deflate` feature is enabled.
+    pub fn deflate(mut self, enable: bool) -> Self {
+        self.encodings.deflate = enable && cfg!(feature = "compression-deflate");
+        self
+    }
+
+    /// Sets whether to compress with brotli.
+    ///
+    /// Defaults to `true` if the `compression-br` feature is enabled.
+    pub fn br(mut self, enable: bool) -> Self {
+        self.encodings.br = enable && cfg!(feature = "compression-br");
+        self
+    }
+
+    /// Sets whether to compress with zstd.
+    ///
+    /// Defaults to `true` if the `compression-zstd` feature is enabled.
+    pub fn zstd(mut self, enable: bool) -> Self {
+        self.encodings.zstd = enable && cfg!(feature = "compression-zstd");
+        self
+    }
+
+    // The encoding to serve the file with from the cache, if it is worth compressing and the
+    // client accepts any of the encodings of the cache.
+    pub(super) fn encoding_for(
+        &self,
+        headers: &HeaderMap,
+        mime: &HeaderValue,
+        metadata: &FileMetadata,
+    ) -> Option<Encoding> {
+        if metadata.size() > self.max_file_size
+            || metadata.size() > self.max_size
+            || !is_compressible(mime)
+        {
+            return None;
+        }
+
+        Encoding::preferred_encoding(encodings(headers, self.encodings))
+            .filter(|encoding| *encoding != Encoding::Identity)
+    }
+
+    // The size of the compressed variant of the file at `path`, if it is cached.
+    pub(super) fn cached_size(
+        &self,
+        path: &Path,
+        metadata: &FileMetadata,
+        encoding: Encoding,
+    ) -> Option<u64> {
+        self.get(&Key::new(path, metadata, encoding))
+            .map(|(_, size)| size)
+    }
+
+    // Get the compressed variant of the file at `path`, compressing it if it isn't cached yet.
+    // Returns the compressed file and its size.
+    pub(super) async fn compressed<FS: FileSystem>(
+        &self,
+        fs: &FS,
+        path: &Path,
+        metadata: &FileMetadata,
+        encoding: Encoding,
+    ) -> io::Result<(BoxFileRead, u64)> {
+        let key = Key::new(path, metadata, encoding);
+
+        if let Some((stored, size)) = self.get(&key) {
+            match stored {
+                Stored::Memory(bytes) => return Ok((Box::new(io::Cursor::new(bytes)), size)),
+                Stored::File(cache_path) => match tokio::fs::File::open(&cache_path).await {
+                    Ok(file) => return Ok((Box::new(file), size)),
+                    // removed from the cache directory, compress it again
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => self.remove(&key),
+                    Err(err) => return Err(err),
+                },
+            }
+        }
+
+        // concurrent misses wait for the first one instead of compressing the file again
+        let pending = self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let compressed = pending
+            .get_or_try_init(|| self.compress(fs, path, key.clone()))
+            .await
+            .cloned();
+        {
+            let mut state = self.state.lock().unwrap();
+            if matches!(state.pending.get(&key), Some(other) if Arc::ptr_eq(other, &pending)) {
+                state.pending.remove(&key);
+            }
+        }
+        let compressed = compressed?;
+        let size = compressed.len() as u64;
+
+        Ok((Box::new(io::Cursor::new(compressed)), size))
+    }
+
+    // Compress the file at `path` and add it to the cache.
+    async fn compress<FS: FileSystem>(&self, fs: &FS, path: &Path, key: Key) -> io::Result<Bytes> {
+        let (mut file, _) = fs.open(path).await?;
+        let mut uncompressed = Vec::with_capacity(key.size as usize);
+        file.read_to_end(&mut uncompressed).await?;
+
+        // compressing takes long enough to stall the runtime, in particular with brotli
+        let encoding = key.encoding;
+        let handle = tokio::runtime::Handle::current();
+        let compressed =
+            tokio::task::spawn_blocking(move || handle.block_on(compress(&uncompressed, encoding)))
+                .await
+                .map_err(|err| io::Error::new(io::ErrorKind::Other, err))??;
+        let compressed = Bytes::from(compressed);
+        let size = compressed.len() as u64;
+
+        let stored = match &self.storage {
+            Storage::Memory => Stored::Memory(compressed.clone()),
+            Storage::Directory(dir) => {
+                let cache_path = dir.join(cache_file_name(encoding));
+                tokio::fs::write(&cache_path, &compressed).await?;
+                Stored::File(cache_path)
+            }
+        };
+        self.insert(key, stored, size).await;
+
+        Ok(compressed)
+    }
+
+    fn get(&self, key: &Key) -> Option<(Stored, u64)> {
+        let mut state = self.state.lock().unwrap();
+        state.last_used += 1;
+        let last_used = state.last_used;
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = last_used;
+        Some((entry.data.clone(), entry.size))
+    }
+
+    fn remove(&self, key: &Key) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(entry) = state.entries.remove(key) {
+            state.size -= entry.size;
+        }
+    }
+
+    async fn insert(&self, key: Key, data: Stored, size: u64) {
+        let evicted = {
+            let mut state = self.state.lock().unwrap();
+            state.last_used += 1;
+            let entry = Entry {
+                data,
+                size,
+                last_used: state.last_used,
+            };
+            state.size += size;
+            let mut evicted = Vec::new();
+            if let Some(previous) = state.entries.insert(key, entry) {
+                state.size -= previous.size;
+                evicted.push(previous.data);
+            }
+
+            while state.size > self.max_size {
+                let oldest = state
+                    .entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                let entry = match oldest.and_then(|key| state.entries.remove(&key)) {
+                    Some(entry) => entry,
+                    None => break,
+                };
+                state.size -= entry.size;
+                evicted.push(entry.data);
+            }
+            evicted
+        };
+
+        for data in evicted {
+            if let Stored::File(path) = data {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        }
+    }
+}
+
+impl fmt::Debug for CompressionCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressionCache")
+            .field("storage", &self.storage)
+            .field("max_size", &self.max_size)
+            .field("max_file_size", &self.max_file_size)
+            .field("encodings", &self.encodings)
+            .finish()
+    }
+}
+
+// Unique names for the files in the cache directory, also among processes sharing it.
+fn cache_file_name(encoding: Encoding) -> String {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{}.{}", std::process::id(), id, encoding.to_str())
+}
+
+// Like the default predicate of the compression middleware, skip types that are already
+// compressed or are streamed.
+fn is_compressible(mime: &HeaderValue) -> bool {
+    let mime = match mime.to_str() {
+        Ok(mime) => mime.split(';').next().unwrap_or_default().trim(),
+        Err(_) => return false,
+    };
+
+    let already_compressed = (mime.starts_with("image/") && mime != "image/svg+xml")
+        || mime.starts_with("audio/")
+        || mime.starts_with("video/")
+        || matches!(
+            mime,
+            "application/zip"
+                | "application/gzip"
+                | "application/zstd"
+                | "application/x-7z-compressed"
+                | "application/x-bzip2"
+                | "application/x-rar-compressed"
+                | "font/woff"
+                | "font/woff2"
+        );
+
+    !already_compressed && mime != "text/event-stream" && !mime.starts_with("application/grpc")
+}
+
+async fn compress(data: &[u8], encoding: Encoding) -> io::Result<Vec<u8>> {
+    let mut encoder = encoder(data, encoding).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Other,
+            "encoding not supported by the compression cache",
+        )
+    })?;
+    let mut compressed = Vec::new();
+    encoder.read_to_end(&mut compressed).await?;
+    Ok(compressed)
+}
+
+fn encoder<'a>(_data: &'a [u8], encoding: Encoding) -> Option<Pin<Box<dyn AsyncRead + Send + 'a>>> {
+    match encoding {
+        #[cfg(feature = "compression-gzip")]
+        Encoding::Gzip => Some(Box::pin(
+            async_compression::tokio::bufread::GzipEncoder::new(_data),
+        )),
+        #[cfg(feature = "compression-deflate")]
+        Encoding::Deflate => Some(Box::pin(
+            async_compression::tokio::bufread::ZlibEncoder::new(_data),
+        )),
+        #[cfg(feature = "compression-br")]
+        Encoding::Brotli => Some(Box::pin(
+            async_compression::tokio::bufread::BrotliEncoder::new(_data),
+        )),
+        #[cfg(feature = "compression-zstd")]
+        Encoding::Zstd => Some(Box::pin(
+            async_compression::tokio::bufread::ZstdEncoder::new(_data),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressible_types() {
+        assert!(is_compressible(&HeaderValue::from_static("text/html")));
+        assert!(is_compressible(&HeaderValue::from_static(
+            "application/json; charset=utf-8"
+        )));
+        assert!(is_compressible(&HeaderValue::from_static("image/svg+xml")));
+        assert!(!is_compressible(&HeaderValue::from_static("image/png")));
+        assert!(!is_compressible(&HeaderValue::from_static("video/mp4")));
+        assert!(!is_compressible(&HeaderValue::from_static("font/woff2")));
+        assert!(!is_compressible(&HeaderValue::from_static(
+            "text/event-stream"
+        )));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn evicts_least_recently_used() {
+        use crate::services::fs::TokioFileSystem;
+
+        let fs = TokioFileSystem::new();
+        let cache = CompressionCache::in_memory(60);
+        let compress = |path: &'static str| {
+            let cache = cache.clone();
+            async move {
+                let path = Path::new(path);
+                let metadata = fs.metadata(path).await.unwrap();
+                cache
+                    .compressed(&fs, path, &metadata, Encoding::Gzip)
+                    .await
+                    .unwrap()
+                    .1
+            }
+        };
+
+        let first = compress("../test-files/index.html").await;
+        assert_eq!(compress("../test-files/index.html").await, first);
+        assert_eq!(cache.state.lock().unwrap().entries.len(), 1);
+
+        compress("../test-files/precompressed.txt").await;
+        let state = cache.state.lock().unwrap();
+        assert_eq!(state.entries.len(), 1);
+        assert!(state.size <= 60);
+        assert!(state
+            .entries
+            .keys()
+            .all(|key| key.path.ends_with("precompressed.txt")));
+    }
+
+    #[cfg(feature = "compression-gzip")]
+    #[tokio::test]
+    async fn compresses_concurrent_misses_once() {
+        use crate::services::fs::{FileSystemFuture, TokioFileSystem};
+        use std::sync::atomic::AtomicUsize;
+
+        #[derive(Clone, Default)]
+        struct CountingFileSystem {
+            inner: TokioFileSystem,
+            opened: Arc<AtomicUsize>,
+        }
+
+        impl FileSystem for CountingFileSystem {
+            type File = tokio::fs::File;
+
+            fn open<'a>(
+                &'a self,
+                path: &'a Path,
+            ) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+                self.opened.fetch_add(1, Ordering::SeqCst);
+                self.inner.open(path)
+            }
+
+            fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+                self.inner.metadata(path)
+            }
+        }
+
+        let fs = CountingFileSystem::default();
+        let cache = CompressionCache::in_memory(1024);
+        let path = Path::new("../test-files/precompressed.txt");
+        let metadata = fs.metadata(path).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            cache.compressed(&fs, path, &metadata, Encoding::Gzip),
+            cache.compressed(&fs, path, &metadata, Encoding::Gzip),
+        );
+        assert_eq!(first.unwrap().1, second.unwrap().1);
+        assert_eq!(fs.opened.load(Ordering::SeqCst), 1);
+        assert!(cache.state.lock().unwrap().pending.is_empty());
+    }
+
+    #[tokio::test]
+    async fn removes_replaced_files() {
+        let dir = std::env::temp_dir().join(format!(
+            "tower-http-compression-cache-{}",
+            uuid::Uuid::new_v4()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cache = CompressionCache::in_directory(&dir, 1024);
+        let key = Key::new(
+            Path::new("index.html"),
+            &FileMetadata::file(1),
+            Encoding::Gzip,
+        );
+
+        let first = dir.join("first");
+        let second = dir.join("second");
+        std::fs::write(&first, "first").unwrap();
+        std::fs::write(&second, "second").unwrap();
+        cache
+            .insert(key.clone(), Stored::File(first.clone()), 5)
+            .await;
+        cache.insert(key, Stored::File(second.clone()), 6).await;
+
+        assert!(!first.exists());
+        assert!(second.exists());
+        assert_eq!(cache.state.lock().unwrap().size, 6);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
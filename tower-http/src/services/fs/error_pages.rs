@@ -0,0 +1,150 @@
+//! Custom bodies for the error responses of [`ServeDir`].
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use super::FileSystem;
+use bytes::Bytes;
+use http::{HeaderValue, Request, StatusCode};
+use std::{collections::HashMap, fmt, path::Path, sync::Arc};
+use tokio::io::AsyncReadExt;
+
+type Render =
+    Arc<dyn Fn(StatusCode, &Request<()>) -> Option<(HeaderValue, Bytes)> + Send + Sync + 'static>;
+
+/// Bodies for the error responses of [`ServeDir`], like `404 Not Found` or `416 Range Not
+/// Satisfiable`, which are empty by default.
+///
+/// Pages are either files served from the [`ServeDir`]'s directory, or rendered by a function,
+/// for example to produce `application/problem+json` documents. Files take precedence over the
+/// function. The status code and headers of the response, like `Allow` or `Content-Range`, are
+/// kept.
+///
+/// Only responses produced by [`ServeDir`] itself get error pages. Requests for files that
+/// don't exist are still passed to the fallback if one is set, and responses of the fallback
+/// are never replaced.
+///
+/// See [`ServeDir::error_pages`].
+///
+/// # Example
+///
+/// ```
+/// use bytes::Bytes;
+/// use http::{HeaderValue, StatusCode};
+/// use tower_http::services::{fs::ErrorPages, ServeDir};
+///
+/// let error_pages = ErrorPages::new()
+///     // relative to the served directory
+///     .file(StatusCode::NOT_FOUND, "404.html")
+///     .render(|status, req| {
+///         let body = format!(
+///             r#"{{"status":{},"instance":"{}"}}"#,
+///             status.as_u16(),
+///             req.uri().path(),
+///         );
+///         Some((
+///             HeaderValue::from_static("application/problem+json"),
+///             Bytes::from(body),
+///         ))
+///     });
+///
+/// let service = ServeDir::new("assets").error_pages(error_pages);
+/// ```
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeDir::error_pages`]: super::ServeDir::error_pages
+#[derive(Clone, Default)]
+pub struct ErrorPages {
+    files: HashMap<StatusCode, String>,
+    render: Option<Render>,
+}
+
+impl ErrorPages {
+    /// Create a new [`ErrorPages`] without any pages.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer responses with `status` with the file at `path`, relative to the served
+    /// directory.
+    ///
+    /// The `Content-Type` is guessed from the extension of `path`. If the file can't be read the
+    /// response is sent with the page from [`ErrorPages::render`], or an empty body.
+    pub fn file(mut self, status: StatusCode, path: impl Into<String>) -> Self {
+        self.files.insert(status, path.into());
+        self
+    }
+
+    /// Render the bodies of error responses without a [`ErrorPages::file`] with `render`.
+    ///
+    /// `render` is called with the status code and the method, URI and headers of the request,
+    /// and returns the `Content-Type` and body of the page, or `None` to send an empty body.
+    pub fn render<F>(mut self, render: F) -> Self
+    where
+        F: Fn(StatusCode, &Request<()>) -> Option<(HeaderValue, Bytes)> + Send + Sync + 'static,
+    {
+        self.render = Some(Arc::new(render));
+        self
+    }
+
+    pub(super) fn handles(&self, status: StatusCode) -> bool {
+        status.is_client_error() && (self.render.is_some() || self.files.contains_key(&status))
+    }
+
+    // The `Content-Type` and body of the error page for `status`, if any.
+    pub(super) async fn page<FS: FileSystem>(
+        &self,
+        fs: &FS,
+        base: &Path,
+        status: StatusCode,
+        req: &Request<()>,
+    ) -> Option<(HeaderValue, Bytes)> {
+        if let Some(path) = self.files.get(&status) {
+            let path = base.join(path.trim_start_matches('/'));
+            if let Some(body) = read(fs, &path).await {
+                let mime = mime_guess::from_path(&path)
+                    .first_raw()
+                    .map(HeaderValue::from_static)
+                    .unwrap_or_else(|| HeaderValue::from_static("application/octet-stream"));
+                return Some((mime, body));
+            }
+        }
+
+        self.render.as_ref().and_then(|render| render(status, req))
+    }
+}
+
+impl fmt::Debug for ErrorPages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorPages")
+            .field("files", &self.files)
+            .field("render", &self.render.is_some())
+            .finish()
+    }
+}
+
+async fn read<FS: FileSystem>(fs: &FS, path: &Path) -> Option<Bytes> {
+    let (mut file, meta) = fs.open(path).await.ok()?;
+    if meta.is_dir() {
+        return None;
+    }
+    let mut body = Vec::with_capacity(meta.size() as usize);
+    file.read_to_end(&mut body).await.ok()?;
+    Some(Bytes::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_client_errors_with_a_page() {
+        let pages = ErrorPages::new().file(StatusCode::NOT_FOUND, "404.html");
+        assert!(pages.handles(StatusCode::NOT_FOUND));
+        assert!(!pages.handles(StatusCode::FORBIDDEN));
+
+        let pages = pages.render(|_, _| None);
+        assert!(pages.handles(StatusCode::FORBIDDEN));
+        assert!(!pages.handles(StatusCode::OK));
+        assert!(!pages.handles(StatusCode::NOT_MODIFIED));
+    }
+}
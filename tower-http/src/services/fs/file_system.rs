@@ -0,0 +1,312 @@
+//! Abstraction over the file system [`ServeDir`] serves files from.
+//!
+//! [`ServeDir`]: super::ServeDir
+
+use std::{
+    any::Any,
+    fmt,
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    time::SystemTime,
+};
+use tokio::io::{AsyncRead, AsyncSeek};
+
+/// Future returned by the methods of [`FileSystem`].
+pub type FileSystemFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + Send + 'a>>;
+
+/// A file system that [`ServeDir`] can serve files from.
+///
+/// [`ServeDir`] only ever reads from the file system, so implementations only need to be able to
+/// open files and look up metadata. Files are read sequentially and seeked when only parts of
+/// them are requested, so range requests, precompressed variants and conditional requests work
+/// the same regardless of where the files are stored.
+///
+/// The paths passed to the file system are the base path given to [`ServeDir::new`] joined with
+/// the validated request path. They never contain `..` segments.
+///
+/// Errors with the kinds [`io::ErrorKind::NotFound`] and [`io::ErrorKind::PermissionDenied`]
+/// result in a `404 Not Found` response (or a call to the fallback), any other error results in
+/// a `500 Internal Server Error` response.
+///
+/// # Example
+///
+/// Serving files from an in-memory map:
+///
+/// ```
+/// use std::{collections::HashMap, io, io::Cursor, path::{Component, Path, PathBuf}, sync::Arc};
+/// use tower_http::services::{
+///     fs::{FileMetadata, FileSystem, FileSystemFuture},
+///     ServeDir,
+/// };
+///
+/// #[derive(Clone)]
+/// struct InMemory(Arc<HashMap<PathBuf, &'static [u8]>>);
+///
+/// impl InMemory {
+///     fn get(&self, path: &Path) -> io::Result<&'static [u8]> {
+///         // skip the `.` that `ServeDir::new` prepends to the base path
+///         let path: PathBuf = path
+///             .components()
+///             .filter(|component| !matches!(component, Component::CurDir))
+///             .collect();
+///         self.0.get(&path).copied().ok_or_else(|| io::ErrorKind::NotFound.into())
+///     }
+/// }
+///
+/// impl FileSystem for InMemory {
+///     type File = Cursor<&'static [u8]>;
+///
+///     fn open<'a>(
+///         &'a self,
+///         path: &'a Path,
+///     ) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+///         Box::pin(async move {
+///             let contents = self.get(path)?;
+///             Ok((Cursor::new(contents), FileMetadata::file(contents.len() as u64)))
+///         })
+///     }
+///
+///     fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+///         Box::pin(async move {
+///             let contents = self.get(path)?;
+///             Ok(FileMetadata::file(contents.len() as u64))
+///         })
+///     }
+/// }
+///
+/// let files = HashMap::from([(PathBuf::from("hello.txt"), &b"Hello, World!"[..])]);
+///
+/// let service = ServeDir::new("").file_system(InMemory(Arc::new(files)));
+/// ```
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeDir::new`]: super::ServeDir::new
+pub trait FileSystem: Clone + Send + Sync + 'static {
+    /// An open file.
+    ///
+    /// Files are read from the current position and seeked to serve ranges.
+    type File: AsyncRead + AsyncSeek + Send + Unpin + 'static;
+
+    /// Open the file at `path` for reading and get its metadata.
+    fn open<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, (Self::File, FileMetadata)>;
+
+    /// Get the metadata of the file or directory at `path`, without opening it.
+    fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata>;
+
+    /// List the entries of the directory at `path`.
+    ///
+    /// This is only used to render directory listings, see [`ServeDir::directory_listing`].
+    /// The default implementation returns an error with the kind [`io::ErrorKind::Unsupported`],
+    /// which results in a `404 Not Found` response.
+    ///
+    /// [`ServeDir::directory_listing`]: super::ServeDir::directory_listing
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, Vec<DirEntry>> {
+        let _ = path;
+        Box::pin(async move { Err(io::ErrorKind::Unsupported.into()) })
+    }
+
+    /// Get the canonical form of `path`, with all symbolic links resolved.
+    ///
+    /// This is only used to enforce [`ServeDir::symlinks`]. The default implementation, for file
+    /// systems without symbolic links, returns `path` unchanged.
+    ///
+    /// [`ServeDir::symlinks`]: super::ServeDir::symlinks
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, PathBuf> {
+        Box::pin(async move { Ok(path.to_owned()) })
+    }
+}
+
+/// An entry in a directory of a [`FileSystem`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DirEntry {
+    name: String,
+    metadata: FileMetadata,
+}
+
+impl DirEntry {
+    /// Create a new [`DirEntry`] from the file name of the entry and its metadata.
+    pub fn new(name: impl Into<String>, metadata: FileMetadata) -> Self {
+        Self {
+            name: name.into(),
+            metadata,
+        }
+    }
+
+    /// The file name of the entry, without the path of its directory.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The metadata of the entry.
+    pub fn metadata(&self) -> &FileMetadata {
+        &self.metadata
+    }
+}
+
+/// Metadata about a file or directory in a [`FileSystem`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileMetadata {
+    size: u64,
+    modified: Option<SystemTime>,
+    is_dir: bool,
+    inode: Option<u64>,
+}
+
+impl FileMetadata {
+    /// Create the metadata of a file with the given size in bytes.
+    pub fn file(size: u64) -> Self {
+        Self {
+            size,
+            modified: None,
+            is_dir: false,
+            inode: None,
+        }
+    }
+
+    /// Create the metadata of a directory.
+    pub fn directory() -> Self {
+        Self {
+            size: 0,
+            modified: None,
+            is_dir: true,
+            inode: None,
+        }
+    }
+
+    /// Set the last modification time.
+    ///
+    /// This is used for the `Last-Modified` and `ETag` headers. Without it no `Last-Modified`
+    /// header is sent and no `ETag` is generated.
+    pub fn with_modified(mut self, modified: SystemTime) -> Self {
+        self.modified = Some(modified);
+        self
+    }
+
+    /// Set a number that identifies the file within the file system, like an inode number.
+    ///
+    /// This is included in the `ETag` so files that are replaced with a different file of the
+    /// same size and modification time get a different `ETag`.
+    pub fn with_inode(mut self, inode: u64) -> Self {
+        self.inode = Some(inode);
+        self
+    }
+
+    /// The size of the file in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The last modification time, if known.
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+
+    /// Whether this is the metadata of a directory.
+    pub fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    /// The number identifying the file within the file system, if known.
+    pub fn inode(&self) -> Option<u64> {
+        self.inode
+    }
+}
+
+impl From<std::fs::Metadata> for FileMetadata {
+    fn from(metadata: std::fs::Metadata) -> Self {
+        #[cfg(unix)]
+        let inode = Some(std::os::unix::fs::MetadataExt::ino(&metadata));
+        #[cfg(not(unix))]
+        let inode = None;
+
+        Self {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+            is_dir: metadata.is_dir(),
+            inode,
+        }
+    }
+}
+
+/// The default [`FileSystem`], which serves files from disk using [`tokio::fs`].
+#[derive(Clone, Copy, Default)]
+pub struct TokioFileSystem {
+    _priv: (),
+}
+
+impl TokioFileSystem {
+    /// Create a new [`TokioFileSystem`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl fmt::Debug for TokioFileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokioFileSystem").finish()
+    }
+}
+
+impl FileSystem for TokioFileSystem {
+    type File = tokio::fs::File;
+
+    fn open<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+        Box::pin(async move {
+            let file = tokio::fs::File::open(path).await?;
+            let metadata = file.metadata().await?;
+            Ok((file, metadata.into()))
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+        Box::pin(async move { tokio::fs::metadata(path).await.map(Into::into) })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, Vec<DirEntry>> {
+        Box::pin(async move {
+            let mut read_dir = tokio::fs::read_dir(path).await?;
+            let mut entries = Vec::new();
+            while let Some(entry) = read_dir.next_entry().await? {
+                // follow symlinks, like opening the entry would
+                let metadata = match tokio::fs::metadata(entry.path()).await {
+                    Ok(metadata) => metadata,
+                    Err(_) => entry.metadata().await?,
+                };
+                let name = entry.file_name().to_string_lossy().into_owned();
+                entries.push(DirEntry::new(name, metadata.into()));
+            }
+            Ok(entries)
+        })
+    }
+
+    fn canonicalize<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, PathBuf> {
+        Box::pin(tokio::fs::canonicalize(path))
+    }
+}
+
+// Object safe combination of the traits required of `FileSystem::File`, so the opened file can be
+// carried around without making the response future generic over the file system.
+pub(super) trait FileRead: AsyncRead + AsyncSeek + Send + Unpin {
+    // Recover the file if it was opened by `TokioFileSystem`, so its handle can be passed on to
+    // the server in the response body.
+    fn into_tokio_file(self: Box<Self>) -> Result<tokio::fs::File, Box<dyn FileRead>>;
+}
+
+impl<T> FileRead for T
+where
+    T: AsyncRead + AsyncSeek + Send + Unpin + 'static,
+{
+    fn into_tokio_file(self: Box<Self>) -> Result<tokio::fs::File, Box<dyn FileRead>> {
+        if !(&*self as &dyn Any).is::<tokio::fs::File>() {
+            return Err(self);
+        }
+        let file: Box<dyn Any> = self;
+        Ok(*file
+            .downcast()
+            .unwrap_or_else(|_| unreachable!("checked to be a `tokio::fs::File`")))
+    }
+}
+
+pub(super) type BoxFileRead = Box<dyn FileRead>;
@@ -0,0 +1,174 @@
+//! Minimal glob patterns for matching paths of served files.
+
+use std::fmt;
+
+// A glob pattern matched against paths relative to the served directory, using `/` as the
+// separator and without a leading `/`.
+//
+// - `*` matches any number of characters except `/`.
+// - `**` matches any number of characters including `/`, so `**/` matches any number of
+//   directories, including none.
+// - `?` matches a single character except `/`.
+// - Any other character matches itself.
+//
+// Patterns without a `/` are matched against the file name only, so `*.html` matches HTML files
+// in any directory.
+#[derive(Clone)]
+pub(super) struct Glob {
+    pattern: String,
+    tokens: Vec<Token>,
+    file_name_only: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    // `?`
+    AnyChar,
+    // `*`
+    Star,
+    // `**`
+    AnyPath,
+    // The start of `**/`, which is followed by `AnyPath` and `Char('/')` and lets both be
+    // skipped, as `**/` also matches nothing.
+    AnyDirectories,
+}
+
+impl Glob {
+    pub(super) fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.trim_start_matches('/').chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.next_if_eq(&'*').is_some() => {
+                    if chars.next_if_eq(&'/').is_some() {
+                        tokens.extend([Token::AnyDirectories, Token::AnyPath, Token::Char('/')]);
+                    } else {
+                        tokens.push(Token::AnyPath);
+                    }
+                }
+                '*' => tokens.push(Token::Star),
+                '?' => tokens.push(Token::AnyChar),
+                c => tokens.push(Token::Char(c)),
+            }
+        }
+
+        Self {
+            pattern: pattern.to_owned(),
+            tokens,
+            file_name_only: !pattern.contains('/'),
+        }
+    }
+
+    pub(super) fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches('/');
+        let path = if self.file_name_only {
+            path.rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+        glob_match(&self.tokens, path)
+    }
+}
+
+impl fmt::Debug for Glob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Glob").field(&self.pattern).finish()
+    }
+}
+
+// Matches by tracking every number of tokens that the path read so far can be matched by, which
+// takes time proportional to the length of the path times the number of tokens.
+fn glob_match(tokens: &[Token], path: &str) -> bool {
+    // `states[n]` is whether the first `n` tokens can match the path read so far
+    let mut buf = vec![false; 2 * (tokens.len() + 1)];
+    let (mut states, mut next) = buf.split_at_mut(tokens.len() + 1);
+    states[0] = true;
+    skip_empty_matches(tokens, states);
+
+    for c in path.chars() {
+        next.iter_mut().for_each(|state| *state = false);
+        for (index, token) in tokens.iter().enumerate() {
+            if !states[index] {
+                continue;
+            }
+            match token {
+                Token::Char(expected) if *expected == c => next[index + 1] = true,
+                Token::AnyChar if c != '/' => next[index + 1] = true,
+                Token::Star if c != '/' => next[index] = true,
+                Token::AnyPath => next[index] = true,
+                _ => {}
+            }
+        }
+        skip_empty_matches(tokens, next);
+        std::mem::swap(&mut states, &mut next);
+    }
+
+    states[tokens.len()]
+}
+
+// Add the states reached by stars matching nothing.
+fn skip_empty_matches(tokens: &[Token], states: &mut [bool]) {
+    for (index, token) in tokens.iter().enumerate() {
+        if !states[index] {
+            continue;
+        }
+        match token {
+            Token::Star | Token::AnyPath => states[index + 1] = true,
+            Token::AnyDirectories => {
+                states[index + 1] = true;
+                states[index + 3] = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_file_names_in_any_directory() {
+        let glob = Glob::new("*.html");
+        assert!(glob.matches("index.html"));
+        assert!(glob.matches("/docs/guide/index.html"));
+        assert!(!glob.matches("index.html.gz"));
+        assert!(!glob.matches("html"));
+    }
+
+    #[test]
+    fn matches_paths() {
+        let glob = Glob::new("/assets/*.js");
+        assert!(glob.matches("assets/app.js"));
+        assert!(!glob.matches("assets/vendor/app.js"));
+        assert!(!glob.matches("app.js"));
+
+        let glob = Glob::new("assets/**/*.js");
+        assert!(glob.matches("assets/app.js"));
+        assert!(glob.matches("assets/vendor/lib/app.js"));
+        assert!(!glob.matches("other/app.js"));
+
+        let glob = Glob::new("**/secret?.txt");
+        assert!(glob.matches("secret1.txt"));
+        assert!(glob.matches("a/b/secret2.txt"));
+        assert!(!glob.matches("a/secret.txt"));
+        assert!(!glob.matches("a/secret/.txt"));
+
+        let glob = Glob::new("**/");
+        assert!(glob.matches(""));
+        assert!(glob.matches("a/b/"));
+        assert!(!glob.matches("a/b"));
+    }
+
+    #[test]
+    fn matches_long_paths_against_many_stars() {
+        let glob = Glob::new("**a*a*a**a*a*a**a*a*a**/b");
+        let path = "a".repeat(10_000);
+        assert!(!glob.matches(&path));
+        assert!(glob.matches(&format!("{}/b", path)));
+
+        let glob = Glob::new("*a*a*a*a*a*a*a*a*b");
+        assert!(!glob.matches(&path));
+    }
+}
@@ -12,20 +12,43 @@ use std::{
 use tokio::io::{AsyncRead, AsyncReadExt, Take};
 use tokio_util::io::ReaderStream;
 
+mod access_policy;
+mod attachment;
+mod cache_policy;
+mod compression_cache;
+mod error_pages;
+mod file_system;
+mod glob;
+#[cfg(feature = "fs-archive")]
+mod serve_archive;
 mod serve_dir;
+mod serve_embedded;
 mod serve_file;
+mod serve_hosts;
 
 pub use self::{
+    access_policy::{DotFiles, Symlinks},
+    attachment::Attachment,
+    cache_policy::CachePolicy,
+    compression_cache::CompressionCache,
+    error_pages::ErrorPages,
+    file_system::{DirEntry, FileMetadata, FileSystem, FileSystemFuture, TokioFileSystem},
     serve_dir::{
         future::ResponseFuture as ServeFileSystemResponseFuture,
         DefaultServeDirFallback,
+        FileBody,
         // The response body and future are used for both ServeDir and ServeFile
         ResponseBody as ServeFileSystemResponseBody,
         ServeDir,
     },
+    serve_embedded::ServeEmbedded,
     serve_file::ServeFile,
+    serve_hosts::{ResponseFuture as ServeHostsResponseFuture, ServeHosts},
 };
 
+#[cfg(feature = "fs-archive")]
+pub use self::serve_archive::ServeArchive;
+
 pin_project! {
     // NOTE: This could potentially be upstreamed to `http-body`.
     /// Adapter that turns an [`impl AsyncRead`][tokio::io::AsyncRead] to an [`impl Body`][http_body::Body].
@@ -40,14 +63,6 @@ impl<T> AsyncReadBody<T>
 where
     T: AsyncRead,
 {
-    /// Create a new [`AsyncReadBody`] wrapping the given reader,
-    /// with a specific read buffer capacity
-    fn with_capacity(read: T, capacity: usize) -> Self {
-        Self {
-            reader: ReaderStream::with_capacity(read, capacity),
-        }
-    }
-
     fn with_capacity_limited(
         read: T,
         capacity: usize,
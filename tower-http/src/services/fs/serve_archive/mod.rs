@@ -0,0 +1,904 @@
+//! Service that serves files out of zip and tar archives.
+
+use super::{
+    file_system::DirEntry,
+    serve_dir::{future::ResponseFuture, InfallibleResponseFuture, ResponseBody},
+    serve_embedded::normalize,
+    Attachment, CachePolicy, CompressionCache, DefaultServeDirFallback, DotFiles, ErrorPages,
+    FileMetadata, FileSystem, FileSystemFuture, ServeDir,
+};
+use async_compression::tokio::bufread::DeflateDecoder;
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use mime::Mime;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt,
+    io::{self, SeekFrom},
+    path::{Component, Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::SystemTime,
+};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncSeekExt, BufReader, ReadBuf};
+use tower_service::Service;
+
+mod tar;
+mod zip;
+
+/// Service that serves the entries of a zip or tar archive.
+///
+/// The archive is indexed once when the service is created, entries are then read from the
+/// archive on disk for each request and are otherwise served exactly like [`ServeDir`] serves
+/// files from a directory: the `Content-Type` is guessed from the file extension, the
+/// `Last-Modified` header and `ETag` come from the modification time of the entry, conditional
+/// requests are supported and requests for directories are redirected or answered with their
+/// `index.html`.
+///
+/// Entries stored without compression, like all entries of tar archives, are streamed straight
+/// from the archive and support range requests. Deflated zip entries are decompressed while they
+/// are streamed, range requests for them decompress and skip the data before the range.
+/// Compressed tar archives (`.tar.gz`), zip64 archives and encrypted zip entries aren't
+/// supported.
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::ServeArchive;
+///
+/// # fn run() -> std::io::Result<()> {
+/// let service = ServeArchive::open("docs.zip")?.index_files(["index.html", "index.htm"]);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServeArchive<F = DefaultServeDirFallback>(ServeDir<F, ArchiveFileSystem>);
+
+impl ServeArchive<DefaultServeDirFallback> {
+    /// Create a new [`ServeArchive`] serving the entries of the archive at `path`.
+    ///
+    /// Zip archives are recognized by their signature, any other file is read as a tar archive.
+    /// This reads the index of the archive with blocking I/O and fails if the file can't be read
+    /// or isn't a valid archive.
+    pub fn open<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let fs = ArchiveFileSystem::open(path.as_ref())?;
+        Ok(Self(ServeDir::new("").file_system(fs)))
+    }
+}
+
+impl<F> ServeArchive<F> {
+    /// If the requested path is a directory append `index.html`.
+    ///
+    /// See [`ServeDir::append_index_html_on_directories`] for more details.
+    pub fn append_index_html_on_directories(self, append: bool) -> Self {
+        Self(self.0.append_index_html_on_directories(append))
+    }
+
+    /// Set the names of the documents served for requests for directories, in order of
+    /// preference.
+    ///
+    /// See [`ServeDir::index_files`] for more details.
+    pub fn index_files<I>(self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self(self.0.index_files(names))
+    }
+
+    /// Set the status code of the redirects from directories requested without a trailing
+    /// slash, or disable them with `None`.
+    ///
+    /// See [`ServeDir::trailing_slash_redirect`] for more details.
+    pub fn trailing_slash_redirect(self, status: Option<StatusCode>) -> Self {
+        Self(self.0.trailing_slash_redirect(status))
+    }
+
+    /// Serve requests for paths that don't exist from the file with the same path and an
+    /// `.html` extension.
+    ///
+    /// See [`ServeDir::clean_urls`] for more details.
+    pub fn clean_urls(self, enabled: bool) -> Self {
+        Self(self.0.clean_urls(enabled))
+    }
+
+    /// Render a listing of the entries of directories that have no `index.html`.
+    ///
+    /// See [`ServeDir::directory_listing`] for more details.
+    pub fn directory_listing(self, enabled: bool) -> Self {
+        Self(self.0.directory_listing(enabled))
+    }
+
+    /// Serve a single page application, answering navigations to paths that aren't in the archive
+    /// with the document at `index`.
+    ///
+    /// See [`ServeDir::single_page_app`] for more details.
+    pub fn single_page_app(self, index: &str) -> Self {
+        Self(self.0.single_page_app(index))
+    }
+
+    /// Set the policy deciding the `Cache-Control` header sent for each file.
+    ///
+    /// See [`ServeDir::cache_policy`] for more details.
+    pub fn cache_policy(self, policy: CachePolicy) -> Self {
+        Self(self.0.cache_policy(policy))
+    }
+
+    /// Set how requests for paths with a component starting with `.` are handled.
+    ///
+    /// See [`ServeDir::dot_files`] for more details.
+    pub fn dot_files(self, dot_files: DotFiles) -> Self {
+        Self(self.0.dot_files(dot_files))
+    }
+
+    /// Only serve files whose path matches the glob `pattern`.
+    ///
+    /// See [`ServeDir::allow_path`] for more details.
+    pub fn allow_path(self, pattern: &str) -> Self {
+        Self(self.0.allow_path(pattern))
+    }
+
+    /// Never serve files or directories whose path matches the glob `pattern`.
+    ///
+    /// See [`ServeDir::deny_path`] for more details.
+    pub fn deny_path(self, pattern: &str) -> Self {
+        Self(self.0.deny_path(pattern))
+    }
+
+    /// Serve files with the extension `extension` with the `Content-Type` `mime`.
+    ///
+    /// See [`ServeDir::mime_override`] for more details.
+    pub fn mime_override(self, extension: &str, mime: &Mime) -> Self {
+        Self(self.0.mime_override(extension, mime))
+    }
+
+    /// Set the `Content-Type` of files without an extension.
+    ///
+    /// See [`ServeDir::extensionless_mime`] for more details.
+    pub fn extensionless_mime(self, mime: &Mime) -> Self {
+        Self(self.0.extensionless_mime(mime))
+    }
+
+    /// Append `; charset=utf-8` to the `Content-Type` of textual files.
+    ///
+    /// See [`ServeDir::charset_utf8`] for more details.
+    pub fn charset_utf8(self, enabled: bool) -> Self {
+        Self(self.0.charset_utf8(enabled))
+    }
+
+    /// Compress files on the fly and keep the compressed files in `cache`.
+    ///
+    /// See [`ServeDir::compression_cache`] for more details.
+    pub fn compression_cache(self, cache: CompressionCache) -> Self {
+        Self(self.0.compression_cache(cache))
+    }
+
+    /// Serve all files as downloads, with a `Content-Disposition: attachment` header.
+    ///
+    /// See [`ServeDir::attachment`] for more details.
+    pub fn attachment(self, attachment: Attachment) -> Self {
+        Self(self.0.attachment(attachment))
+    }
+
+    /// Serve files as downloads if the request has the query parameter `name`.
+    ///
+    /// See [`ServeDir::download_query_param`] for more details.
+    pub fn download_query_param(self, name: impl Into<String>) -> Self {
+        Self(self.0.download_query_param(name))
+    }
+
+    /// Send the error responses of the service with the bodies from `error_pages`.
+    ///
+    /// See [`ServeDir::error_pages`] for more details.
+    pub fn error_pages(self, error_pages: ErrorPages) -> Self {
+        Self(self.0.error_pages(error_pages))
+    }
+
+    /// Set a specific read buffer chunk size.
+    ///
+    /// The default capacity is 64kb.
+    pub fn with_buf_chunk_size(self, chunk_size: usize) -> Self {
+        Self(self.0.with_buf_chunk_size(chunk_size))
+    }
+
+    /// Set the maximum number of ranges served for a single request.
+    ///
+    /// See [`ServeDir::max_ranges`] for more details.
+    pub fn max_ranges(self, max_ranges: usize) -> Self {
+        Self(self.0.max_ranges(max_ranges))
+    }
+
+    /// Informs the service that it should also look for a precompressed gzip
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the gzip encoding will receive the entry `foo.txt.gz` instead of `foo.txt`.
+    /// If the precompressed entry is not in the archive, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_gzip(self) -> Self {
+        Self(self.0.precompressed_gzip())
+    }
+
+    /// Informs the service that it should also look for a precompressed brotli
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the brotli encoding will receive the entry `foo.txt.br` instead of `foo.txt`.
+    /// If the precompressed entry is not in the archive, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_br(self) -> Self {
+        Self(self.0.precompressed_br())
+    }
+
+    /// Informs the service that it should also look for a precompressed deflate
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the deflate encoding will receive the entry `foo.txt.zz` instead of `foo.txt`.
+    /// If the precompressed entry is not in the archive, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_deflate(self) -> Self {
+        Self(self.0.precompressed_deflate())
+    }
+
+    /// Informs the service that it should also look for a precompressed zstd
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the zstd encoding will receive the entry `foo.txt.zst` instead of `foo.txt`.
+    /// If the precompressed entry is not in the archive, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_zstd(self) -> Self {
+        Self(self.0.precompressed_zstd())
+    }
+
+    /// Set the fallback service.
+    ///
+    /// See [`ServeDir::fallback`] for more details.
+    pub fn fallback<F2>(self, new_fallback: F2) -> ServeArchive<F2> {
+        ServeArchive(self.0.fallback(new_fallback))
+    }
+
+    /// Set the fallback service and override the fallback's status code to `404 Not Found`.
+    ///
+    /// See [`ServeDir::not_found_service`] for more details.
+    pub fn not_found_service<F2>(
+        self,
+        new_fallback: F2,
+    ) -> ServeArchive<crate::set_status::SetStatus<F2>> {
+        ServeArchive(self.0.not_found_service(new_fallback))
+    }
+
+    /// Customize whether or not to call the fallback for requests that aren't `GET` or `HEAD`.
+    ///
+    /// Defaults to not calling the fallback and instead returning `405 Method Not Allowed`.
+    pub fn call_fallback_on_method_not_allowed(self, call_fallback: bool) -> Self {
+        Self(self.0.call_fallback_on_method_not_allowed(call_fallback))
+    }
+
+    /// Call the service and get a future that contains any `std::io::Error` that might have
+    /// happened.
+    ///
+    /// See [`ServeDir::try_call`] for more details.
+    pub fn try_call<ReqBody, FResBody>(
+        &mut self,
+        req: Request<ReqBody>,
+    ) -> ResponseFuture<ReqBody, F>
+    where
+        F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
+        F::Future: Send + 'static,
+        FResBody: http_body::Body<Data = Bytes> + Send + 'static,
+        FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.0.try_call(req)
+    }
+}
+
+impl<ReqBody, F, FResBody> Service<Request<ReqBody>> for ServeArchive<F>
+where
+    F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
+    F::Future: Send + 'static,
+    FResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Future = InfallibleResponseFuture<ReqBody, F>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        self.0.call(req)
+    }
+}
+
+// An entry of an archive, as found in its index.
+pub(super) enum IndexEntry {
+    File(String, ArchiveEntry),
+    Directory(String),
+}
+
+// Where the data of a file is in the archive.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct ArchiveEntry {
+    pub(super) offset: u64,
+    // the size of the data in the archive, before decompression
+    pub(super) size: u64,
+    pub(super) compression: Compression,
+    pub(super) modified: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Compression {
+    Stored,
+    Deflated { uncompressed_size: u64 },
+}
+
+impl ArchiveEntry {
+    fn metadata(&self) -> FileMetadata {
+        let size = match self.compression {
+            Compression::Stored => self.size,
+            Compression::Deflated { uncompressed_size } => uncompressed_size,
+        };
+        // the offset tells apart entries with the same size and modification time
+        let metadata = FileMetadata::file(size).with_inode(self.offset);
+        match self.modified {
+            Some(modified) => metadata.with_modified(modified),
+            None => metadata,
+        }
+    }
+}
+
+// A `FileSystem` backed by the index of an archive.
+#[derive(Clone)]
+pub(crate) struct ArchiveFileSystem {
+    path: Arc<PathBuf>,
+    files: Arc<HashMap<String, ArchiveEntry>>,
+    directories: Arc<HashSet<String>>,
+}
+
+impl ArchiveFileSystem {
+    fn open(path: &Path) -> io::Result<Self> {
+        let mut file = std::fs::File::open(path)?;
+        let index = if zip::is_zip(&mut file)? {
+            zip::read_index(&mut file)?
+        } else {
+            tar::read_index(&mut file)?
+        };
+
+        let mut files = HashMap::with_capacity(index.len());
+        let mut directories = HashSet::new();
+        directories.insert(String::new());
+
+        for entry in index {
+            let (name, entry) = match entry {
+                IndexEntry::File(name, entry) => (name, Some(entry)),
+                IndexEntry::Directory(name) => (name, None),
+            };
+            // entries outside of the root of the archive are never served
+            if Path::new(&name)
+                .components()
+                .any(|component| component == Component::ParentDir)
+            {
+                continue;
+            }
+            let name = normalize(Path::new(&name));
+
+            let mut parent = name.as_str();
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                directories.insert(dir.to_owned());
+                parent = dir;
+            }
+
+            match entry {
+                Some(entry) => {
+                    files.insert(name, entry);
+                }
+                None => {
+                    directories.insert(name);
+                }
+            }
+        }
+
+        Ok(Self {
+            path: Arc::new(path.to_owned()),
+            files: Arc::new(files),
+            directories: Arc::new(directories),
+        })
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<Option<ArchiveEntry>> {
+        let path = normalize(path);
+
+        if let Some(entry) = self.files.get(&path) {
+            Ok(Some(*entry))
+        } else if self.directories.contains(&path) {
+            Ok(None)
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+
+    async fn read_entry(&self, entry: ArchiveEntry) -> io::Result<ArchiveFile> {
+        let mut file = tokio::fs::File::open(&*self.path).await?;
+        file.seek(SeekFrom::Start(entry.offset)).await?;
+
+        let reader = EntryReader {
+            file,
+            start: entry.offset,
+            len: entry.size,
+            position: 0,
+            seeking: false,
+        };
+        match entry.compression {
+            Compression::Stored => Ok(ArchiveFile::Stored(reader)),
+            Compression::Deflated { uncompressed_size } => Ok(ArchiveFile::Inflated(
+                InflatedReader::new(reader, uncompressed_size),
+            )),
+        }
+    }
+}
+
+impl fmt::Debug for ArchiveFileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArchiveFileSystem")
+            .field("path", &self.path)
+            .field("files", &self.files.keys())
+            .finish()
+    }
+}
+
+impl FileSystem for ArchiveFileSystem {
+    type File = ArchiveFile;
+
+    fn open<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+        Box::pin(async move {
+            match self.lookup(path)? {
+                Some(entry) => Ok((self.read_entry(entry).await?, entry.metadata())),
+                // mirror the error opening a directory for reading gives on most platforms
+                None => Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "cannot open a directory",
+                )),
+            }
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+        Box::pin(async move {
+            Ok(self
+                .lookup(path)?
+                .map_or_else(FileMetadata::directory, |entry| entry.metadata()))
+        })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, Vec<DirEntry>> {
+        Box::pin(async move {
+            let dir = normalize(path);
+            if !self.directories.contains(&dir) {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            let child_name = |child: &str| -> Option<String> {
+                let name = if dir.is_empty() {
+                    child
+                } else {
+                    child.strip_prefix(&dir)?.strip_prefix('/')?
+                };
+                (!name.is_empty() && !name.contains('/')).then(|| name.to_owned())
+            };
+
+            let mut entries = Vec::new();
+            for (child, entry) in self.files.iter() {
+                if let Some(name) = child_name(child) {
+                    entries.push(DirEntry::new(name, entry.metadata()));
+                }
+            }
+            for child in self.directories.iter() {
+                if let Some(name) = child_name(child) {
+                    entries.push(DirEntry::new(name, FileMetadata::directory()));
+                }
+            }
+            Ok(entries)
+        })
+    }
+}
+
+// An open entry of an archive.
+#[derive(Debug)]
+pub(crate) enum ArchiveFile {
+    Stored(EntryReader),
+    Inflated(InflatedReader),
+}
+
+impl AsyncRead for ArchiveFile {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Stored(reader) => Pin::new(reader).poll_read(cx, buf),
+            Self::Inflated(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncSeek for ArchiveFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        match self.get_mut() {
+            Self::Stored(reader) => Pin::new(reader).start_seek(position),
+            Self::Inflated(reader) => Pin::new(reader).start_seek(position),
+        }
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        match self.get_mut() {
+            Self::Stored(reader) => Pin::new(reader).poll_complete(cx),
+            Self::Inflated(reader) => Pin::new(reader).poll_complete(cx),
+        }
+    }
+}
+
+// Reads the `len` bytes of a stored entry starting at `start` in the archive, with positions
+// relative to the start of the entry.
+#[derive(Debug)]
+pub(crate) struct EntryReader {
+    file: tokio::fs::File,
+    start: u64,
+    len: u64,
+    position: u64,
+    seeking: bool,
+}
+
+impl AsyncRead for EntryReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let remaining = this.len - this.position;
+        if remaining == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let filled = if buf.remaining() as u64 <= remaining {
+            let before = buf.filled().len();
+            std::task::ready!(Pin::new(&mut this.file).poll_read(cx, buf))?;
+            buf.filled().len() - before
+        } else {
+            // don't read past the end of the entry
+            let mut limited = vec![0; remaining as usize];
+            let mut limited = ReadBuf::new(&mut limited);
+            std::task::ready!(Pin::new(&mut this.file).poll_read(cx, &mut limited))?;
+            buf.put_slice(limited.filled());
+            limited.filled().len()
+        };
+        this.position += filled as u64;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for EntryReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => checked_add(this.len, offset),
+            SeekFrom::Current(offset) => checked_add(this.position, offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        Pin::new(&mut this.file).start_seek(SeekFrom::Start(this.start + position))?;
+        this.seeking = true;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        if !this.seeking {
+            return Poll::Ready(Ok(this.position));
+        }
+        let position = std::task::ready!(Pin::new(&mut this.file).poll_complete(cx))?;
+        this.seeking = false;
+        // seeking past the end is allowed, reads then return nothing
+        this.position = (position - this.start).min(this.len);
+        Poll::Ready(Ok(this.position))
+    }
+}
+
+// Decompresses a deflated entry while it's read, with positions in the decompressed data.
+//
+// Seeking forwards decompresses and skips the data before the new position, seeking backwards
+// starts over from the beginning of the entry.
+#[derive(Debug)]
+pub(crate) struct InflatedReader {
+    // only `None` while being replaced when seeking backwards
+    decoder: Option<DeflateDecoder<BufReader<EntryReader>>>,
+    // the size of the decompressed data, from the index of the archive
+    len: u64,
+    position: u64,
+    skip_to: Option<u64>,
+    rewinding: bool,
+}
+
+impl InflatedReader {
+    fn new(reader: EntryReader, len: u64) -> Self {
+        Self {
+            decoder: Some(DeflateDecoder::new(BufReader::new(reader))),
+            len,
+            position: 0,
+            skip_to: None,
+            rewinding: false,
+        }
+    }
+
+    fn decoder(&mut self) -> &mut DeflateDecoder<BufReader<EntryReader>> {
+        self.decoder.as_mut().expect("decoder is always replaced")
+    }
+}
+
+impl AsyncRead for InflatedReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        let before = buf.filled().len();
+        ready!(Pin::new(this.decoder()).poll_read(cx, buf))?;
+        let filled = buf.filled().len() - before;
+        this.position += filled as u64;
+
+        // the index decides the `Content-Length`, so the data must not end early or run past it
+        if this.position > this.len || (filled == 0 && this.position != this.len) {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "size of the decompressed zip entry doesn't match its index",
+            )));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncSeek for InflatedReader {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        let this = self.get_mut();
+        let position = match position {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(offset) => checked_add(this.len, offset),
+            SeekFrom::Current(offset) => checked_add(this.position, offset),
+        }
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        if position < this.position {
+            let reader = this.decoder.take().map(DeflateDecoder::into_inner);
+            let decoder = this.decoder.insert(DeflateDecoder::new(
+                reader.expect("decoder is always replaced"),
+            ));
+            this.position = 0;
+            Pin::new(decoder.get_mut()).start_seek(SeekFrom::Start(0))?;
+            this.rewinding = true;
+        }
+        // seeking past the end is allowed, reads then return nothing
+        this.skip_to = Some(position.min(this.len));
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        let this = self.get_mut();
+        if this.rewinding {
+            ready!(Pin::new(this.decoder().get_mut()).poll_complete(cx))?;
+            this.rewinding = false;
+        }
+
+        if let Some(skip_to) = this.skip_to {
+            let mut skipped = [0; 8 * 1024];
+            while this.position < skip_to {
+                let len = (skip_to - this.position).min(skipped.len() as u64) as usize;
+                let mut buf = ReadBuf::new(&mut skipped[..len]);
+                ready!(Pin::new(&mut *this).poll_read(cx, &mut buf))?;
+            }
+            this.skip_to = None;
+        }
+        Poll::Ready(Ok(this.position))
+    }
+}
+
+fn checked_add(position: u64, offset: i64) -> Option<u64> {
+    if offset >= 0 {
+        position.checked_add(offset as u64)
+    } else {
+        position.checked_sub(offset.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::ServeArchive;
+    use crate::test_helpers::{to_bytes, Body};
+    use http::{header, Request, Response, StatusCode};
+    use std::convert::Infallible;
+    use tower::ServiceExt;
+
+    const ZIP: &str = "../test-files/archive.zip";
+    const TAR: &str = "../test-files/archive.tar";
+
+    async fn get<S>(svc: S, uri: &str, headers: &[(&str, &str)]) -> Response<Body>
+    where
+        S: tower::Service<
+            Request<Body>,
+            Response = Response<crate::services::fs::ServeFileSystemResponseBody>,
+            Error = Infallible,
+        >,
+    {
+        let mut req = Request::builder().uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        svc.oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .map(Body::new)
+    }
+
+    #[tokio::test]
+    async fn stored_entries() {
+        for archive in [ZIP, TAR] {
+            let svc = ServeArchive::open(archive).unwrap();
+
+            let res = get(svc.clone(), "/docs/guide.txt", &[]).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", archive);
+            assert_eq!(res.headers()[header::CONTENT_TYPE], "text/plain");
+            assert_eq!(res.headers()[header::CONTENT_LENGTH], "22");
+            assert_eq!(
+                res.headers()[header::LAST_MODIFIED],
+                "Tue, 14 Nov 2023 22:13:20 GMT"
+            );
+            assert_eq!(
+                to_bytes(res.into_body()).await.unwrap(),
+                &b"Hello from the guide!\n"[..]
+            );
+
+            let res = get(svc.clone(), "/docs/guide.txt", &[("range", "bytes=6-9")]).await;
+            assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT, "{}", archive);
+            assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 6-9/22");
+            assert_eq!(to_bytes(res.into_body()).await.unwrap(), &b"from"[..]);
+
+            let res = get(svc.clone(), "/docs/guide.txt", &[("range", "bytes=-5")]).await;
+            assert_eq!(to_bytes(res.into_body()).await.unwrap(), &b"ide!\n"[..]);
+
+            let res = get(
+                svc,
+                "/docs/guide.txt",
+                &[("if-modified-since", "Tue, 14 Nov 2023 22:13:20 GMT")],
+            )
+            .await;
+            assert_eq!(res.status(), StatusCode::NOT_MODIFIED, "{}", archive);
+        }
+    }
+
+    #[tokio::test]
+    async fn deflated_entries() {
+        let svc = ServeArchive::open(ZIP).unwrap();
+
+        let res = get(svc.clone(), "/assets/app.js", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "440");
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            "console.log('hello');\n".repeat(20).as_bytes()
+        );
+
+        let res = get(svc.clone(), "/assets/app.js", &[("range", "bytes=0-10")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &b"console.log"[..]
+        );
+
+        let res = get(svc, "/assets/app.js", &[("range", "bytes=-9")]).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), &b"hello');\n"[..]);
+    }
+
+    #[tokio::test]
+    async fn seeking_deflated_entries() {
+        use std::io::SeekFrom;
+        use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+        let fs = super::ArchiveFileSystem::open("../test-files/archive.zip".as_ref()).unwrap();
+        let entry = fs.lookup("assets/app.js".as_ref()).unwrap().unwrap();
+        let mut file = fs.read_entry(entry).await.unwrap();
+
+        let mut buf = [0; 7];
+        file.seek(SeekFrom::Start(35)).await.unwrap();
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello')");
+
+        // starts over from the beginning of the entry
+        file.seek(SeekFrom::Start(8)).await.unwrap();
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"log('he");
+
+        assert_eq!(file.seek(SeekFrom::End(-2)).await.unwrap(), 438);
+        let mut rest = Vec::new();
+        file.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b";\n");
+    }
+
+    #[tokio::test]
+    async fn directories() {
+        for archive in [ZIP, TAR] {
+            let svc = ServeArchive::open(archive).unwrap();
+
+            let res = get(svc.clone(), "/docs", &[]).await;
+            assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT, "{}", archive);
+            assert_eq!(res.headers()[header::LOCATION], "/docs/");
+
+            let res = get(svc.clone(), "/docs/", &[]).await;
+            assert_eq!(res.status(), StatusCode::OK, "{}", archive);
+            assert_eq!(
+                to_bytes(res.into_body()).await.unwrap(),
+                &b"<h1>Docs</h1>\n"[..]
+            );
+
+            // `assets` only exists through the entries it contains
+            let res = get(svc.clone(), "/assets", &[]).await;
+            assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT, "{}", archive);
+
+            let res = get(svc, "/missing.txt", &[]).await;
+            assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", archive);
+        }
+    }
+
+    #[tokio::test]
+    async fn tar_long_names_and_directory_listing() {
+        let svc = ServeArchive::open(TAR).unwrap().directory_listing(true);
+
+        let dir = format!("/docs{}", "/very-long-directory-name".repeat(4));
+        let res = get(svc.clone(), &format!("{}/page.txt", dir), &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &b"long name\n"[..]
+        );
+
+        let res = get(svc, "/empty/", &[("accept", "application/json")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &br#"{"path":"/empty/","entries":[]}"#[..]
+        );
+    }
+
+    #[test]
+    fn invalid_archives() {
+        assert!(ServeArchive::open("../test-files/index.html").is_err());
+        assert!(ServeArchive::open("../test-files/missing.zip").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! Reading the index of tar archives.
+//!
+//! Supports the ustar format along with the GNU long name and pax extensions for paths longer
+//! than 100 bytes.
+
+use super::{ArchiveEntry, Compression, IndexEntry};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    time::{Duration, SystemTime},
+};
+
+const BLOCK_SIZE: u64 = 512;
+
+pub(super) fn read_index(file: &mut File) -> io::Result<Vec<IndexEntry>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    let mut pax = Pax::default();
+    let mut position = 0;
+    let len = file.metadata()?.len();
+
+    // some writers omit the blocks of zeros at the end of the archive
+    while position < len {
+        file.seek(SeekFrom::Start(position))?;
+        let mut header = [0; BLOCK_SIZE as usize];
+        file.read_exact(&mut header)?;
+
+        // the archive ends with two blocks of zeros
+        if header.iter().all(|byte| *byte == 0) {
+            break;
+        }
+        if !has_valid_checksum(&header) {
+            return Err(invalid_data("invalid tar header checksum"));
+        }
+
+        let size = pax.size.take().unwrap_or(parse_number(&header[124..136])?);
+        let modified = pax
+            .modified
+            .take()
+            .unwrap_or(parse_number(&header[136..148])?);
+        let offset = position + BLOCK_SIZE;
+        position = size
+            .checked_add(BLOCK_SIZE - 1)
+            .map(|size| size / BLOCK_SIZE * BLOCK_SIZE)
+            .and_then(|size| offset.checked_add(size))
+            .ok_or_else(|| invalid_data("invalid size in tar header"))?;
+        // entries are read by seeking in the archive, so their data must be within it
+        if offset + size > len {
+            return Err(invalid_data("invalid size in tar header"));
+        }
+
+        let name = match (pax.path.take(), long_name.take()) {
+            (Some(path), _) | (None, Some(path)) => path,
+            (None, None) => header_name(&header),
+        };
+
+        match header[156] {
+            // regular files
+            b'0' | b'\0' | b'7' => entries.push(IndexEntry::File(
+                name,
+                ArchiveEntry {
+                    offset,
+                    size,
+                    compression: Compression::Stored,
+                    modified: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(modified)),
+                },
+            )),
+            b'5' => entries.push(IndexEntry::Directory(name)),
+            // GNU long name of the next entry
+            b'L' => long_name = Some(read_string(file, offset, size)?),
+            // pax extended header of the next entry
+            b'x' => pax = Pax::parse(&read_string(file, offset, size)?)?,
+            // links, devices and global pax headers aren't served
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+// Overrides of the fields of the next header from a pax extended header.
+#[derive(Default)]
+struct Pax {
+    path: Option<String>,
+    size: Option<u64>,
+    modified: Option<u64>,
+}
+
+impl Pax {
+    // Records look like `<length> <key>=<value>\n`.
+    fn parse(records: &str) -> io::Result<Self> {
+        let mut pax = Self::default();
+        let mut rest = records;
+        while let Some((length, _)) = rest.split_once(' ') {
+            let record = match length
+                .parse::<usize>()
+                .ok()
+                .and_then(|length| rest.get(..length))
+            {
+                Some(record) => record,
+                None => break,
+            };
+            rest = &rest[record.len()..];
+
+            // the length counts itself, so it can be shorter than the record's prefix
+            let field = record
+                .get(length.len() + 1..)
+                .ok_or_else(|| invalid_data("invalid pax extended header record"))?;
+            let (key, value) = match field.trim_end_matches('\n').split_once('=') {
+                Some(field) => field,
+                None => continue,
+            };
+            match key {
+                "path" => pax.path = Some(value.to_owned()),
+                "size" => pax.size = value.parse().ok(),
+                // may have a fractional part
+                "mtime" => {
+                    pax.modified = value.split('.').next().and_then(|secs| secs.parse().ok())
+                }
+                _ => {}
+            }
+        }
+        Ok(pax)
+    }
+}
+
+fn header_name(header: &[u8]) -> String {
+    let name = string_field(&header[..100]);
+    let is_ustar = &header[257..262] == b"ustar";
+    let prefix = string_field(&header[345..500]);
+    if is_ustar && !prefix.is_empty() {
+        format!("{}/{}", prefix, name)
+    } else {
+        name
+    }
+}
+
+fn string_field(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+// Numbers are octal, or base-256 if the high bit of the first byte is set.
+fn parse_number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |number, byte| {
+                number << 8 | u64::from(*byte)
+            }));
+    }
+
+    let digits = std::str::from_utf8(field)
+        .map_err(|_| invalid_data("invalid number in tar header"))?
+        .trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid number in tar header"))
+}
+
+// The checksum is the sum of all bytes of the header, with the checksum field itself taken as
+// spaces.
+fn has_valid_checksum(header: &[u8]) -> bool {
+    let expected = match parse_number(&header[148..156]) {
+        Ok(expected) => expected,
+        Err(_) => return false,
+    };
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(index, byte)| {
+            if (148..156).contains(&index) {
+                u64::from(b' ')
+            } else {
+                u64::from(*byte)
+            }
+        })
+        .sum();
+    sum == expected
+}
+
+fn read_string(file: &mut File, offset: u64, size: u64) -> io::Result<String> {
+    let mut data = Vec::new();
+    file.seek(SeekFrom::Start(offset))?;
+    file.take(size).read_to_end(&mut data)?;
+    Ok(string_field(&data))
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers_and_pax_records() {
+        assert_eq!(parse_number(b"00000001750\0").unwrap(), 0o1750);
+        assert_eq!(parse_number(b"   17 \0").unwrap(), 0o17);
+        assert_eq!(parse_number(&[0x80, 0, 0, 0, 0, 1, 0]).unwrap(), 256);
+        assert!(parse_number(b"12x\0").is_err());
+
+        let pax =
+            Pax::parse("30 mtime=1700000000.123456789\n16 path=a/b.txt\n11 size=42\n").unwrap();
+        assert_eq!(pax.path.as_deref(), Some("a/b.txt"));
+        assert_eq!(pax.size, Some(42));
+        assert_eq!(pax.modified, Some(1_700_000_000));
+    }
+
+    #[test]
+    fn rejects_truncated_pax_records() {
+        for records in ["0 ", "1 x", "16 path=a/b.txt\n1 "] {
+            let err = Pax::parse(records).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{:?}", records);
+        }
+    }
+
+    // An archive with a single file entry, whose header has the given size field, followed by
+    // `data`.
+    fn read_archive(size: &[u8], data: &[u8]) -> io::Result<Vec<IndexEntry>> {
+        let mut header = [0; BLOCK_SIZE as usize];
+        header[..8].copy_from_slice(b"file.txt");
+        header[124..124 + size.len()].copy_from_slice(size);
+        header[156] = b'0';
+        let sum: u64 =
+            header.iter().map(|byte| u64::from(*byte)).sum::<u64>() + 8 * u64::from(b' ');
+        header[148..155].copy_from_slice(format!("{:06o}\0", sum).as_bytes());
+
+        let path =
+            std::env::temp_dir().join(format!("tower-http-tar-{}.tar", uuid::Uuid::new_v4()));
+        std::fs::write(&path, [&header[..], data].concat()).unwrap();
+        let index = read_index(&mut File::open(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        index
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        // base-256 size of `u64::MAX`
+        let size = [
+            0x80, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+        ];
+        let err = read_archive(&size, &[]).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_entries_past_the_end() {
+        let data = [b'a'; BLOCK_SIZE as usize];
+        let index = read_archive(b"00000000003\0", &data).unwrap();
+        assert!(
+            matches!(&index[..], [IndexEntry::File(name, entry)] if name == "file.txt" && entry.size == 3)
+        );
+
+        let err = read_archive(b"00000001001\0", &data).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,282 @@
+//! Reading the index of zip archives.
+//!
+//! Entries are found through the central directory at the end of the archive. Zip64 archives,
+//! archives spanning several files and encrypted entries aren't supported.
+
+use super::{ArchiveEntry, Compression, IndexEntry};
+use std::{
+    convert::{TryFrom, TryInto},
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    time::{Duration, SystemTime},
+};
+
+const END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+const CENTRAL_DIRECTORY_HEADER: &[u8] = b"PK\x01\x02";
+const LOCAL_FILE_HEADER: &[u8] = b"PK\x03\x04";
+
+const END_OF_CENTRAL_DIRECTORY_SIZE: usize = 22;
+const CENTRAL_DIRECTORY_HEADER_SIZE: usize = 46;
+const LOCAL_FILE_HEADER_SIZE: usize = 30;
+const MAX_COMMENT_SIZE: u64 = u16::MAX as u64;
+
+// Extended timestamp extra field, with the modification time as a unix timestamp.
+const EXTENDED_TIMESTAMP: u16 = 0x5455;
+
+pub(super) fn is_zip(file: &mut File) -> io::Result<bool> {
+    let mut signature = [0; 4];
+    file.seek(SeekFrom::Start(0))?;
+    match file.read_exact(&mut signature) {
+        Ok(()) => Ok(signature == LOCAL_FILE_HEADER || signature == END_OF_CENTRAL_DIRECTORY),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+pub(super) fn read_index(file: &mut File) -> io::Result<Vec<IndexEntry>> {
+    let (len, count, directory_offset, directory_size) = read_end_of_central_directory(file)?;
+
+    let mut directory = vec![0; directory_size as usize];
+    file.seek(SeekFrom::Start(directory_offset))?;
+    file.read_exact(&mut directory)?;
+
+    let mut entries = Vec::with_capacity(count);
+    let mut rest = &directory[..];
+    for _ in 0..count {
+        if rest.len() < CENTRAL_DIRECTORY_HEADER_SIZE || &rest[..4] != CENTRAL_DIRECTORY_HEADER {
+            return Err(invalid_data("invalid zip central directory"));
+        }
+        let flags = u16_at(rest, 8);
+        let method = u16_at(rest, 10);
+        let (time, date) = (u16_at(rest, 12), u16_at(rest, 14));
+        let compressed_size = u32_at(rest, 20);
+        let uncompressed_size = u32_at(rest, 24);
+        let name_len = usize::from(u16_at(rest, 28));
+        let extra_len = usize::from(u16_at(rest, 30));
+        let comment_len = usize::from(u16_at(rest, 32));
+        let local_header_offset = u32_at(rest, 42);
+
+        let end = CENTRAL_DIRECTORY_HEADER_SIZE + name_len + extra_len + comment_len;
+        if rest.len() < end {
+            return Err(invalid_data("invalid zip central directory"));
+        }
+        let name_end = CENTRAL_DIRECTORY_HEADER_SIZE + name_len;
+        let name = String::from_utf8_lossy(&rest[CENTRAL_DIRECTORY_HEADER_SIZE..name_end]);
+        let extra = &rest[name_end..name_end + extra_len];
+        let name = name.into_owned();
+        rest = &rest[end..];
+
+        if name.ends_with('/') {
+            entries.push(IndexEntry::Directory(name));
+            continue;
+        }
+
+        // encrypted
+        if flags & 1 != 0 {
+            continue;
+        }
+        let compression = match method {
+            0 => Compression::Stored,
+            8 => Compression::Deflated { uncompressed_size },
+            // other methods are rare, skip the entry rather than failing
+            _ => continue,
+        };
+
+        let offset = data_offset(file, local_header_offset)?;
+        // entries are read by seeking in the archive, so their data must be within it
+        if offset + compressed_size > len {
+            return Err(invalid_data("invalid zip entry size"));
+        }
+        let modified = extended_timestamp(extra).or_else(|| dos_date_time(date, time));
+        entries.push(IndexEntry::File(
+            name,
+            ArchiveEntry {
+                offset,
+                size: compressed_size,
+                compression,
+                modified,
+            },
+        ));
+    }
+
+    Ok(entries)
+}
+
+// Returns the length of the archive, the number of entries and the offset and size of the central
+// directory.
+fn read_end_of_central_directory(file: &mut File) -> io::Result<(u64, usize, u64, u64)> {
+    // the record is followed by a comment of up to 64 KiB
+    let len = file.seek(SeekFrom::End(0))?;
+    let search_len = len.min(END_OF_CENTRAL_DIRECTORY_SIZE as u64 + MAX_COMMENT_SIZE);
+    let mut tail = vec![0; search_len as usize];
+    file.seek(SeekFrom::Start(len - search_len))?;
+    file.read_exact(&mut tail)?;
+
+    let start = (0..tail.len().saturating_sub(END_OF_CENTRAL_DIRECTORY_SIZE - 1))
+        .rev()
+        .find(|&start| &tail[start..start + 4] == END_OF_CENTRAL_DIRECTORY)
+        .ok_or_else(|| invalid_data("not a zip archive"))?;
+    let record = &tail[start..];
+
+    let disk = u16_at(record, 4);
+    let directory_disk = u16_at(record, 6);
+    if disk != 0 || directory_disk != 0 {
+        return Err(unsupported(
+            "zip archives spanning several files aren't supported",
+        ));
+    }
+
+    let count = u16_at(record, 10);
+    let directory_size = u32_at(record, 12);
+    let directory_offset = u32_at(record, 16);
+    if count == u16::MAX || directory_size == u64::from(u32::MAX) {
+        return Err(unsupported("zip64 archives aren't supported"));
+    }
+    // the directory is read into memory, so its size must not be trusted
+    if directory_offset + directory_size > len {
+        return Err(invalid_data("invalid zip central directory"));
+    }
+
+    Ok((len, usize::from(count), directory_offset, directory_size))
+}
+
+// The data of an entry follows its local header, whose variable sized fields can differ from
+// those in the central directory.
+fn data_offset(file: &mut File, local_header_offset: u64) -> io::Result<u64> {
+    let mut header = [0; LOCAL_FILE_HEADER_SIZE];
+    file.seek(SeekFrom::Start(local_header_offset))?;
+    file.read_exact(&mut header)?;
+    if &header[..4] != LOCAL_FILE_HEADER {
+        return Err(invalid_data("invalid zip local file header"));
+    }
+
+    let name_len = u64::from(u16_at(&header, 26));
+    let extra_len = u64::from(u16_at(&header, 28));
+    Ok(local_header_offset + LOCAL_FILE_HEADER_SIZE as u64 + name_len + extra_len)
+}
+
+fn extended_timestamp(mut extra: &[u8]) -> Option<SystemTime> {
+    while extra.len() >= 4 {
+        let id = u16_at(extra, 0);
+        let len = usize::from(u16_at(extra, 2));
+        let data = extra.get(4..4 + len)?;
+        // the flags are followed by the modification time if the first bit is set
+        if id == EXTENDED_TIMESTAMP && len >= 5 && data[0] & 1 != 0 {
+            let secs = i32::from_le_bytes(data[1..5].try_into().unwrap());
+            return u64::try_from(secs)
+                .ok()
+                .map(|secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs));
+        }
+        extra = &extra[4 + len..];
+    }
+    None
+}
+
+// MS-DOS dates have no time zone, they are taken as UTC.
+fn dos_date_time(date: u16, time: u16) -> Option<SystemTime> {
+    let year = 1980 + i64::from(date >> 9);
+    let month = i64::from((date >> 5) & 0xf);
+    let day = i64::from(date & 0x1f);
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    let secs = days_from_civil(year, month, day) * 86400
+        + i64::from(time >> 11) * 3600
+        + i64::from((time >> 5) & 0x3f) * 60
+        + i64::from(time & 0x1f) * 2;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs.try_into().ok()?))
+}
+
+// Days since the unix epoch of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from(u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()))
+}
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unsupported(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_dos_date_times() {
+        // 2023-11-14 22:13:20
+        let date = (2023 - 1980) << 9 | 11 << 5 | 14;
+        let time = 22 << 11 | 13 << 5 | 10;
+        assert_eq!(
+            dos_date_time(date, time),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+        assert_eq!(dos_date_time(0, 0), None);
+
+        let extra = [0x55, 0x54, 5, 0, 1, 0x00, 0xf1, 0x53, 0x65];
+        assert_eq!(
+            extended_timestamp(&extra),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        );
+    }
+
+    #[test]
+    fn rejects_entries_past_the_end() {
+        // a stored entry `a` with the data `abc`, and its size in the central directory
+        let archive = |size: u32| {
+            let mut archive = Vec::new();
+            archive.extend_from_slice(LOCAL_FILE_HEADER);
+            archive.extend_from_slice(&[0; 22]);
+            archive.extend_from_slice(&[1, 0, 0, 0]);
+            archive.extend_from_slice(b"aabc");
+
+            let directory_offset = archive.len() as u32;
+            archive.extend_from_slice(CENTRAL_DIRECTORY_HEADER);
+            archive.extend_from_slice(&[0; 16]);
+            archive.extend_from_slice(&size.to_le_bytes());
+            archive.extend_from_slice(&3u32.to_le_bytes());
+            archive.extend_from_slice(&[1, 0]);
+            archive.extend_from_slice(&[0; 16]);
+            archive.push(b'a');
+
+            let directory_size = archive.len() as u32 - directory_offset;
+            archive.extend_from_slice(END_OF_CENTRAL_DIRECTORY);
+            archive.extend_from_slice(&[0, 0, 0, 0, 1, 0, 1, 0]);
+            archive.extend_from_slice(&directory_size.to_le_bytes());
+            archive.extend_from_slice(&directory_offset.to_le_bytes());
+            archive.extend_from_slice(&[0, 0]);
+            archive
+        };
+        let read_index = |archive: Vec<u8>| {
+            let path =
+                std::env::temp_dir().join(format!("tower-http-zip-{}.zip", uuid::Uuid::new_v4()));
+            std::fs::write(&path, archive).unwrap();
+            let index = read_index(&mut File::open(&path).unwrap());
+            std::fs::remove_file(&path).unwrap();
+            index
+        };
+
+        let index = read_index(archive(3)).unwrap();
+        assert!(
+            matches!(&index[..], [IndexEntry::File(name, entry)] if name == "a" && entry.size == 3)
+        );
+
+        let err = read_index(archive(100)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{body::UnsyncBoxBody, services::fs::AsyncReadBody};
+use bytes::Bytes;
+use http_body::{Body, Frame, SizeHint};
+use std::{
+    fmt, io, mem,
+    ops::Range,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::Take;
+
+/// Response body for [`ServeDir`] and [`ServeFile`].
+///
+/// Bodies of files served from disk with the default [`TokioFileSystem`] carry the open file
+/// and the range of it to send until they are first polled. Servers that can send files without
+/// copying them through userspace, for example with `sendfile(2)` or `splice(2)`, can take them
+/// with [`ResponseBody::into_file`]. Other servers just poll the body, which reads the file in
+/// chunks of [`ServeDir::with_buf_chunk_size`] bytes.
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeFile`]: crate::services::ServeFile
+/// [`TokioFileSystem`]: crate::services::fs::TokioFileSystem
+/// [`ServeDir::with_buf_chunk_size`]: super::ServeDir::with_buf_chunk_size
+pub struct ResponseBody {
+    inner: Inner,
+}
+
+enum Inner {
+    Boxed(UnsyncBoxBody<Bytes, io::Error>),
+    // boxed to keep the body small, it is moved around a lot
+    File {
+        file: Box<FileBody>,
+        chunk_size: usize,
+    },
+    Reading(Box<AsyncReadBody<Take<tokio::fs::File>>>),
+}
+
+impl ResponseBody {
+    pub(crate) fn new(inner: UnsyncBoxBody<Bytes, io::Error>) -> Self {
+        Self {
+            inner: Inner::Boxed(inner),
+        }
+    }
+
+    pub(crate) fn file(file: FileBody, chunk_size: usize) -> Self {
+        Self {
+            inner: Inner::File {
+                file: Box::new(file),
+                chunk_size,
+            },
+        }
+    }
+
+    /// Get the file and range sent as this body, if this is the body of a file served from
+    /// disk that hasn't been polled yet.
+    pub fn as_file(&self) -> Option<&FileBody> {
+        match &self.inner {
+            Inner::File { file, .. } => Some(&**file),
+            _ => None,
+        }
+    }
+
+    /// Take the file and range sent as this body, or get the body back if it isn't the body of
+    /// a file served from disk or has already been polled.
+    ///
+    /// The caller is then responsible for sending the range of the file, the headers of the
+    /// response already describe it.
+    pub fn into_file(self) -> Result<FileBody, Self> {
+        match self.inner {
+            Inner::File { file, .. } => Ok(*file),
+            inner => Err(Self { inner }),
+        }
+    }
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        Self::new(UnsyncBoxBody::default())
+    }
+}
+
+impl fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResponseBody")
+            .field("file", &self.as_file())
+            .finish()
+    }
+}
+
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if let Inner::File { .. } = this.inner {
+            // only start reading once the body is polled, so the file can still be taken
+            let placeholder = Inner::Boxed(UnsyncBoxBody::default());
+            if let Inner::File { file, chunk_size } = mem::replace(&mut this.inner, placeholder) {
+                let len = file.range.end - file.range.start;
+                this.inner = Inner::Reading(Box::new(AsyncReadBody::with_capacity_limited(
+                    file.file, chunk_size, len,
+                )));
+            }
+        }
+
+        match &mut this.inner {
+            Inner::Boxed(body) => Pin::new(body).poll_frame(cx),
+            Inner::Reading(body) => Pin::new(body).poll_frame(cx),
+            Inner::File { .. } => unreachable!("the file is turned into a reader above"),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.inner {
+            Inner::Boxed(body) => body.is_end_stream(),
+            Inner::File { file, .. } => file.range.is_empty(),
+            Inner::Reading(body) => body.is_end_stream(),
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.inner {
+            Inner::Boxed(body) => body.size_hint(),
+            Inner::File { file, .. } => SizeHint::with_exact(file.range.end - file.range.start),
+            Inner::Reading(body) => body.size_hint(),
+        }
+    }
+}
+
+/// A file served from disk by [`ServeDir`] or [`ServeFile`], and the range of it to send as the
+/// response body.
+///
+/// See [`ResponseBody::into_file`].
+///
+/// [`ServeDir`]: super::ServeDir
+/// [`ServeFile`]: crate::services::ServeFile
+#[derive(Debug)]
+pub struct FileBody {
+    file: tokio::fs::File,
+    range: Range<u64>,
+}
+
+impl FileBody {
+    pub(crate) fn new(file: tokio::fs::File, range: Range<u64>) -> Self {
+        Self { file, range }
+    }
+
+    /// The open file.
+    ///
+    /// Its position is the start of [`FileBody::range`].
+    pub fn file(&self) -> &tokio::fs::File {
+        &self.file
+    }
+
+    /// The range of bytes of the file to send, which is the whole file unless a single range
+    /// was requested.
+    pub fn range(&self) -> Range<u64> {
+        self.range.clone()
+    }
+
+    /// Get the open file and the range of bytes to send.
+    ///
+    /// [`tokio::fs::File::into_std`] gives access to the underlying file descriptor or handle.
+    pub fn into_parts(self) -> (tokio::fs::File, Range<u64>) {
+        (self.file, self.range)
+    }
+}
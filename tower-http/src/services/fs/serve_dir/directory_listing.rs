@@ -0,0 +1,286 @@
+use super::super::{DirEntry, FileSystem};
+use bytes::Bytes;
+use http::{header, HeaderValue, Request};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{cmp::Ordering, fmt::Write, io, path::Path};
+
+// Characters that are escaped in the links to the entries, everything but the unreserved
+// characters of RFC 3986.
+const ENTRY_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+pub(super) struct DirectoryListing {
+    pub(super) content_type: HeaderValue,
+    pub(super) body: Bytes,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortBy {
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortBy {
+    fn as_str(self) -> &'static str {
+        match self {
+            SortBy::Name => "name",
+            SortBy::Size => "size",
+            SortBy::Modified => "modified",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Order {
+    Ascending,
+    Descending,
+}
+
+impl Order {
+    fn as_str(self) -> &'static str {
+        match self {
+            Order::Ascending => "asc",
+            Order::Descending => "desc",
+        }
+    }
+}
+
+// Render the listing of the directory at `path`, as HTML or as JSON if the client asks for it.
+//
+// The entries are sorted according to the `sort` (`name`, `size` or `modified`) and `order`
+// (`asc` or `desc`) query parameters. Only the entries for which `is_visible` returns `true` are
+// listed.
+pub(super) async fn render<FS, B, V>(
+    fs: &FS,
+    path: &Path,
+    req: &Request<B>,
+    is_visible: V,
+) -> io::Result<DirectoryListing>
+where
+    FS: FileSystem,
+    V: Fn(&DirEntry) -> bool,
+{
+    let mut entries = fs.read_dir(path).await?;
+    entries.retain(|entry| is_visible(entry));
+
+    let (sort_by, order) = sort_from_query(req.uri().query().unwrap_or_default());
+    entries.sort_by(|a, b| {
+        let ordering = match sort_by {
+            SortBy::Name => Ordering::Equal,
+            SortBy::Size => a.metadata().size().cmp(&b.metadata().size()),
+            SortBy::Modified => a.metadata().modified().cmp(&b.metadata().modified()),
+        }
+        .then_with(|| a.name().cmp(b.name()));
+
+        match order {
+            Order::Ascending => ordering,
+            Order::Descending => ordering.reverse(),
+        }
+    });
+
+    let request_path = percent_decode_str(req.uri().path()).decode_utf8_lossy();
+
+    if wants_json(req) {
+        Ok(DirectoryListing {
+            content_type: HeaderValue::from_static("application/json"),
+            body: Bytes::from(render_json(&request_path, &entries)),
+        })
+    } else {
+        Ok(DirectoryListing {
+            content_type: HeaderValue::from_static("text/html; charset=utf-8"),
+            body: Bytes::from(render_html(&request_path, &entries, sort_by, order)),
+        })
+    }
+}
+
+fn sort_from_query(query: &str) -> (SortBy, Order) {
+    let mut sort_by = SortBy::Name;
+    let mut order = Order::Ascending;
+
+    for pair in query.split('&') {
+        match pair.split_once('=') {
+            Some(("sort", "name")) => sort_by = SortBy::Name,
+            Some(("sort", "size")) => sort_by = SortBy::Size,
+            Some(("sort", "modified")) => sort_by = SortBy::Modified,
+            Some(("order", "asc")) => order = Order::Ascending,
+            Some(("order", "desc")) => order = Order::Descending,
+            _ => {}
+        }
+    }
+
+    (sort_by, order)
+}
+
+fn wants_json<B>(req: &Request<B>) -> bool {
+    req.headers()
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|media_range| media_range.split(';').next().unwrap_or_default().trim())
+        .find(|media_type| {
+            media_type.eq_ignore_ascii_case("application/json")
+                || media_type.eq_ignore_ascii_case("text/html")
+        })
+        .map_or(false, |media_type| {
+            media_type.eq_ignore_ascii_case("application/json")
+        })
+}
+
+fn render_html(request_path: &str, entries: &[DirEntry], sort_by: SortBy, order: Order) -> String {
+    let title = format!("Index of {}", escape_html(request_path));
+
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n</head>\n<body>\n<h1>{title}</h1>\n<table>\n<thead>\n<tr>",
+        title = title
+    );
+
+    for (column, label) in [
+        (SortBy::Name, "Name"),
+        (SortBy::Size, "Size"),
+        (SortBy::Modified, "Last modified"),
+    ] {
+        // clicking the column the listing is sorted by reverses the order
+        let next_order = if column == sort_by && order == Order::Ascending {
+            Order::Descending
+        } else {
+            Order::Ascending
+        };
+        let _ = write!(
+            html,
+            "<th><a href=\"?sort={}&amp;order={}\">{}</a></th>",
+            column.as_str(),
+            next_order.as_str(),
+            label
+        );
+    }
+    html.push_str("</tr>\n</thead>\n<tbody>\n");
+
+    if request_path != "/" {
+        html.push_str("<tr><td><a href=\"../\">../</a></td><td></td><td></td></tr>\n");
+    }
+
+    for entry in entries {
+        let metadata = entry.metadata();
+        let suffix = if metadata.is_dir() { "/" } else { "" };
+        let size = if metadata.is_dir() {
+            String::new()
+        } else {
+            metadata.size().to_string()
+        };
+        let modified = metadata
+            .modified()
+            .map(httpdate::fmt_http_date)
+            .unwrap_or_default();
+
+        let _ = writeln!(
+            html,
+            "<tr><td><a href=\"{href}{suffix}\">{name}{suffix}</a></td><td>{size}</td><td>{modified}</td></tr>",
+            href = utf8_percent_encode(entry.name(), ENTRY_NAME),
+            name = escape_html(entry.name()),
+            suffix = suffix,
+            size = size,
+            modified = modified,
+        );
+    }
+
+    html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+    html
+}
+
+fn render_json(request_path: &str, entries: &[DirEntry]) -> String {
+    let mut json = String::new();
+    let _ = write!(
+        json,
+        "{{\"path\":\"{}\",\"entries\":[",
+        escape_json(request_path)
+    );
+
+    for (index, entry) in entries.iter().enumerate() {
+        let metadata = entry.metadata();
+        if index > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            "{{\"name\":\"{}\",\"type\":\"{}\",\"size\":{},\"modified\":",
+            escape_json(entry.name()),
+            if metadata.is_dir() {
+                "directory"
+            } else {
+                "file"
+            },
+            metadata.size(),
+        );
+        match metadata.modified() {
+            Some(modified) => {
+                let _ = write!(json, "\"{}\"}}", httpdate::fmt_http_date(modified));
+            }
+            None => json.push_str("null}"),
+        }
+    }
+
+    json.push_str("]}");
+    json
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_entry_names() {
+        use crate::services::fs::FileMetadata;
+
+        let entries = [DirEntry::new("<a href=\"x\">&</a>", FileMetadata::file(1))];
+        let html = render_html("/", &entries, SortBy::Name, Order::Ascending);
+        assert!(html.contains(
+            "<a href=\"%3Ca%20href%3D%22x%22%3E%26%3C%2Fa%3E\">&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;</a>"
+        ));
+
+        let json = render_json("/", &entries);
+        assert_eq!(
+            json,
+            "{\"path\":\"/\",\"entries\":[{\"name\":\"<a href=\\\"x\\\">&</a>\",\"type\":\"file\",\"size\":1,\"modified\":null}]}"
+        );
+    }
+}
@@ -1,25 +1,34 @@
 use super::{
-    open_file::{FileOpened, FileRequestExtent, OpenFileOutput},
-    DefaultServeDirFallback, ResponseBody,
+    super::file_system::BoxFileRead,
+    open_file::{is_not_found_error, FileOpened, FileRequestExtent, OpenFileOutput},
+    DefaultServeDirFallback, FileBody, ResponseBody,
 };
 use crate::{
     body::UnsyncBoxBody, content_encoding::Encoding, services::fs::AsyncReadBody, BoxError,
 };
-use bytes::Bytes;
-use futures_util::future::{BoxFuture, FutureExt, TryFutureExt};
+use bytes::{Bytes, BytesMut};
+use futures_util::{
+    future::{BoxFuture, FutureExt, TryFutureExt},
+    Stream, TryStreamExt,
+};
 use http::{
     header::{self, ALLOW},
     HeaderValue, Request, Response, StatusCode,
 };
-use http_body_util::{BodyExt, Empty, Full};
+use http_body::Frame;
+use http_body_util::{BodyExt, Empty, Full, StreamBody};
 use pin_project_lite::pin_project;
 use std::{
+    collections::hash_map::RandomState,
     convert::Infallible,
     future::Future,
-    io,
+    hash::{BuildHasher, Hasher},
+    io::{self, SeekFrom},
+    ops::{Range, RangeInclusive},
     pin::Pin,
     task::{ready, Context, Poll},
 };
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tower_service::Service;
 
 pin_project! {
@@ -27,9 +36,19 @@ pin_project! {
     pub struct ResponseFuture<ReqBody, F = DefaultServeDirFallback> {
         #[pin]
         pub(super) inner: ResponseFutureInner<ReqBody, F>,
+        pub(super) error_page: Option<ErrorPage>,
     }
 }
 
+// Renders the error page for the status of a response produced by `ServeDir`, if there is one.
+pub(super) struct ErrorPage {
+    pub(super) is_head: bool,
+    #[allow(clippy::type_complexity)]
+    pub(super) render: Box<
+        dyn FnOnce(StatusCode) -> Option<BoxFuture<'static, Option<(HeaderValue, Bytes)>>> + Send,
+    >,
+}
+
 impl<ReqBody, F> ResponseFuture<ReqBody, F> {
     pub(super) fn open_file_future(
         future: BoxFuture<'static, io::Result<OpenFileOutput>>,
@@ -40,6 +59,7 @@ impl<ReqBody, F> ResponseFuture<ReqBody, F> {
                 future,
                 fallback_and_request,
             },
+            error_page: None,
         }
     }
 
@@ -48,14 +68,21 @@ impl<ReqBody, F> ResponseFuture<ReqBody, F> {
             inner: ResponseFutureInner::InvalidPath {
                 fallback_and_request,
             },
+            error_page: None,
         }
     }
 
     pub(super) fn method_not_allowed() -> Self {
         Self {
             inner: ResponseFutureInner::MethodNotAllowed,
+            error_page: None,
         }
     }
+
+    pub(super) fn with_error_page(mut self, error_page: Option<ErrorPage>) -> Self {
+        self.error_page = error_page;
+        self
+    }
 }
 
 pin_project! {
@@ -73,6 +100,11 @@ pin_project! {
             fallback_and_request: Option<(F, Request<ReqBody>)>,
         },
         MethodNotAllowed,
+        ErrorPage {
+            future: Option<BoxFuture<'static, Option<(HeaderValue, Bytes)>>>,
+            response: Option<Response<ResponseBody>>,
+            is_head: bool,
+        },
     }
 }
 
@@ -93,60 +125,75 @@ where
                 ResponseFutureInnerProj::OpenFileFuture {
                     future: open_file_future,
                     fallback_and_request,
-                } => match ready!(open_file_future.poll(cx)) {
-                    Ok(OpenFileOutput::FileOpened(file_output)) => {
-                        break Poll::Ready(Ok(build_response(*file_output)));
-                    }
+                } => {
+                    let res = match ready!(open_file_future.poll(cx)) {
+                        Ok(OpenFileOutput::FileOpened(file_output)) => build_response(*file_output),
 
-                    Ok(OpenFileOutput::Redirect { location }) => {
-                        let mut res = response_with_status(StatusCode::TEMPORARY_REDIRECT);
-                        res.headers_mut().insert(http::header::LOCATION, location);
-                        break Poll::Ready(Ok(res));
-                    }
+                        Ok(OpenFileOutput::DirectoryListing { listing, is_head }) => {
+                            let content_length = listing.body.len().to_string();
+                            let body = if is_head {
+                                empty_body()
+                            } else {
+                                body_from_bytes(listing.body)
+                            };
+                            Response::builder()
+                                .header(header::CONTENT_TYPE, listing.content_type)
+                                .header(header::CONTENT_LENGTH, content_length)
+                                .body(body)
+                                .unwrap()
+                        }
 
-                    Ok(OpenFileOutput::FileNotFound) => {
-                        if let Some((mut fallback, request)) = fallback_and_request.take() {
-                            call_fallback(&mut fallback, request)
-                        } else {
-                            break Poll::Ready(Ok(not_found()));
+                        Ok(OpenFileOutput::Redirect { location, status }) => {
+                            let mut res = response_with_status(status);
+                            res.headers_mut().insert(http::header::LOCATION, location);
+                            res
                         }
-                    }
 
-                    Ok(OpenFileOutput::PreconditionFailed) => {
-                        break Poll::Ready(Ok(response_with_status(
-                            StatusCode::PRECONDITION_FAILED,
-                        )));
-                    }
+                        Ok(OpenFileOutput::FileNotFound) => match fallback_and_request.take() {
+                            Some((mut fallback, request)) => {
+                                this.inner.set(call_fallback(&mut fallback, request));
+                                continue;
+                            }
+                            None => not_found(),
+                        },
 
-                    Ok(OpenFileOutput::NotModified) => {
-                        break Poll::Ready(Ok(response_with_status(StatusCode::NOT_MODIFIED)));
-                    }
+                        Ok(OpenFileOutput::Forbidden) => {
+                            response_with_status(StatusCode::FORBIDDEN)
+                        }
 
-                    Err(err) => {
-                        #[cfg(unix)]
-                        // 20 = libc::ENOTDIR => "not a directory
-                        // when `io_error_more` landed, this can be changed
-                        // to checking for `io::ErrorKind::NotADirectory`.
-                        // https://github.com/rust-lang/rust/issues/86442
-                        let error_is_not_a_directory = err.raw_os_error() == Some(20);
-                        #[cfg(not(unix))]
-                        let error_is_not_a_directory = false;
-
-                        if matches!(
-                            err.kind(),
-                            io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
-                        ) || error_is_not_a_directory
-                        {
-                            if let Some((mut fallback, request)) = fallback_and_request.take() {
-                                call_fallback(&mut fallback, request)
-                            } else {
-                                break Poll::Ready(Ok(not_found()));
+                        Ok(OpenFileOutput::PreconditionFailed) => {
+                            response_with_status(StatusCode::PRECONDITION_FAILED)
+                        }
+
+                        Ok(OpenFileOutput::NotModified {
+                            etag,
+                            cache_control,
+                        }) => {
+                            let mut res = response_with_status(StatusCode::NOT_MODIFIED);
+                            if let Some(etag) = etag {
+                                res.headers_mut()
+                                    .insert(header::ETAG, etag.to_header_value());
                             }
-                        } else {
-                            break Poll::Ready(Err(err));
+                            if let Some(cache_control) = cache_control {
+                                res.headers_mut()
+                                    .insert(header::CACHE_CONTROL, cache_control);
+                            }
+                            res
                         }
-                    }
-                },
+
+                        Err(err) if is_not_found_error(&err) => match fallback_and_request.take() {
+                            Some((mut fallback, request)) => {
+                                this.inner.set(call_fallback(&mut fallback, request));
+                                continue;
+                            }
+                            None => not_found(),
+                        },
+
+                        Err(err) => break Poll::Ready(Err(err)),
+                    };
+
+                    with_error_page(this.error_page, res)
+                }
 
                 ResponseFutureInnerProj::FallbackFuture { future } => {
                     break Pin::new(future).poll(cx).map_err(|err| match err {})
@@ -158,7 +205,7 @@ where
                     if let Some((mut fallback, request)) = fallback_and_request.take() {
                         call_fallback(&mut fallback, request)
                     } else {
-                        break Poll::Ready(Ok(not_found()));
+                        with_error_page(this.error_page, not_found())
                     }
                 }
 
@@ -166,6 +213,30 @@ where
                     let mut res = response_with_status(StatusCode::METHOD_NOT_ALLOWED);
                     res.headers_mut()
                         .insert(ALLOW, HeaderValue::from_static("GET,HEAD"));
+                    with_error_page(this.error_page, res)
+                }
+
+                ResponseFutureInnerProj::ErrorPage {
+                    future,
+                    response,
+                    is_head,
+                } => {
+                    let page = match future {
+                        Some(future) => ready!(future.as_mut().poll(cx)),
+                        None => None,
+                    };
+                    let mut res = response.take().expect("polled after completion");
+                    if let Some((content_type, body)) = page {
+                        let headers = res.headers_mut();
+                        headers.remove(header::CONTENT_ENCODING);
+                        headers.insert(header::CONTENT_TYPE, content_type);
+                        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(body.len()));
+                        *res.body_mut() = if *is_head {
+                            empty_body()
+                        } else {
+                            body_from_bytes(body)
+                        };
+                    }
                     break Poll::Ready(Ok(res));
                 }
             };
@@ -182,6 +253,23 @@ fn response_with_status(status: StatusCode) -> Response<ResponseBody> {
         .unwrap()
 }
 
+// Responses produced by `ServeDir` itself, rather than the fallback, are sent with the error page
+// for their status, if there is one.
+fn with_error_page<ReqBody, F>(
+    error_page: &mut Option<ErrorPage>,
+    res: Response<ResponseBody>,
+) -> ResponseFutureInner<ReqBody, F> {
+    let (future, is_head) = match error_page.take() {
+        Some(error_page) => ((error_page.render)(res.status()), error_page.is_head),
+        None => (None, false),
+    };
+    ResponseFutureInner::ErrorPage {
+        future,
+        response: Some(res),
+        is_head,
+    }
+}
+
 fn not_found() -> Response<ResponseBody> {
     response_with_status(StatusCode::NOT_FOUND)
 }
@@ -218,12 +306,12 @@ where
 
 fn build_response(output: FileOpened) -> Response<ResponseBody> {
     let (maybe_file, size) = match output.extent {
-        FileRequestExtent::Full(file, meta) => (Some(file), meta.len()),
-        FileRequestExtent::Head(meta) => (None, meta.len()),
+        FileRequestExtent::Full(file, meta) => (Some(file), meta.size()),
+        FileRequestExtent::Head(meta) => (None, meta.size()),
     };
 
     let mut builder = Response::builder()
-        .header(header::CONTENT_TYPE, output.mime_header_value)
+        .header(header::CONTENT_TYPE, output.mime_header_value.clone())
         .header(header::ACCEPT_RANGES, "bytes");
 
     if let Some(encoding) = output
@@ -237,42 +325,99 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
         builder = builder.header(header::LAST_MODIFIED, last_modified.0.to_string());
     }
 
+    if let Some(etag) = output.etag {
+        builder = builder.header(header::ETAG, etag.to_header_value());
+    }
+
+    if let Some(cache_control) = output.cache_control {
+        builder = builder.header(header::CACHE_CONTROL, cache_control);
+    }
+
+    if let Some(content_disposition) = output.content_disposition {
+        builder = builder.header(header::CONTENT_DISPOSITION, content_disposition);
+    }
+
     match output.maybe_range {
+        Some(Ok(ranges)) if ranges.len() > output.max_ranges => builder
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .body(empty_body())
+            .unwrap(),
+
+        Some(Ok(ranges)) if ranges.len() > 1 => {
+            let boundary = multipart_boundary();
+            let content_type = format!("multipart/byteranges; boundary={}", boundary);
+
+            let mime = &output.mime_header_value;
+            let parts = ranges
+                .into_iter()
+                .enumerate()
+                .map(|(index, range)| {
+                    // the first delimiter doesn't need to be preceded by a line break
+                    let delimiter = if index == 0 { "" } else { "\r\n" };
+                    let part_header = format!(
+                        "{}--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        delimiter,
+                        boundary,
+                        mime.to_str().unwrap_or_default(),
+                        range.start(),
+                        range.end(),
+                        size,
+                    );
+                    (Bytes::from(part_header), range)
+                })
+                .collect::<Vec<_>>();
+            let closing_delimiter = Bytes::from(format!("\r\n--{}--\r\n", boundary));
+
+            let content_length = parts
+                .iter()
+                .map(|(part_header, range)| {
+                    part_header.len() as u64 + range.end() - range.start() + 1
+                })
+                .sum::<u64>()
+                + closing_delimiter.len() as u64;
+
+            let body = if let Some(file) = maybe_file {
+                let stream =
+                    multipart_byteranges_stream(file, parts, closing_delimiter, output.chunk_size);
+                ResponseBody::new(UnsyncBoxBody::new(
+                    StreamBody::new(stream.map_ok(Frame::data)).boxed_unsync(),
+                ))
+            } else {
+                empty_body()
+            };
+
+            if let Some(headers) = builder.headers_mut() {
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_str(&content_type).unwrap(),
+                );
+            }
+
+            builder
+                .header(header::CONTENT_LENGTH, content_length)
+                .status(StatusCode::PARTIAL_CONTENT)
+                .body(body)
+                .unwrap()
+        }
+
         Some(Ok(ranges)) => {
             if let Some(range) = ranges.first() {
-                if ranges.len() > 1 {
-                    builder
-                        .header(header::CONTENT_RANGE, format!("bytes */{}", size))
-                        .status(StatusCode::RANGE_NOT_SATISFIABLE)
-                        .body(body_from_bytes(Bytes::from(
-                            "Cannot serve multipart range requests",
-                        )))
-                        .unwrap()
+                let body = if let Some(file) = maybe_file {
+                    file_body(file, *range.start()..range.end() + 1, output.chunk_size)
                 } else {
-                    let body = if let Some(file) = maybe_file {
-                        let range_size = range.end() - range.start() + 1;
-                        ResponseBody::new(UnsyncBoxBody::new(
-                            AsyncReadBody::with_capacity_limited(
-                                file,
-                                output.chunk_size,
-                                range_size,
-                            )
-                            .boxed_unsync(),
-                        ))
-                    } else {
-                        empty_body()
-                    };
+                    empty_body()
+                };
 
-                    builder
-                        .header(
-                            header::CONTENT_RANGE,
-                            format!("bytes {}-{}/{}", range.start(), range.end(), size),
-                        )
-                        .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
-                        .status(StatusCode::PARTIAL_CONTENT)
-                        .body(body)
-                        .unwrap()
-                }
+                builder
+                    .header(
+                        header::CONTENT_RANGE,
+                        format!("bytes {}-{}/{}", range.start(), range.end(), size),
+                    )
+                    .header(header::CONTENT_LENGTH, range.end() - range.start() + 1)
+                    .status(StatusCode::PARTIAL_CONTENT)
+                    .body(body)
+                    .unwrap()
             } else {
                 builder
                     .header(header::CONTENT_RANGE, format!("bytes */{}", size))
@@ -293,9 +438,7 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
         // Not a range request
         None => {
             let body = if let Some(file) = maybe_file {
-                ResponseBody::new(UnsyncBoxBody::new(
-                    AsyncReadBody::with_capacity(file, output.chunk_size).boxed_unsync(),
-                ))
+                file_body(file, 0..size, output.chunk_size)
             } else {
                 empty_body()
             };
@@ -308,6 +451,88 @@ fn build_response(output: FileOpened) -> Response<ResponseBody> {
     }
 }
 
+// Bodies of files opened by `TokioFileSystem` keep the file, so servers can send it without
+// copying it through userspace.
+fn file_body(file: BoxFileRead, range: Range<u64>, chunk_size: usize) -> ResponseBody {
+    match file.into_tokio_file() {
+        Ok(file) => ResponseBody::file(FileBody::new(file, range), chunk_size),
+        Err(file) => {
+            let len = range.end - range.start;
+            ResponseBody::new(UnsyncBoxBody::new(
+                AsyncReadBody::with_capacity_limited(file, chunk_size, len).boxed_unsync(),
+            ))
+        }
+    }
+}
+
+// The boundary only has to be unlikely to appear in the served file, so the randomly seeded
+// hasher from the standard library is good enough and avoids a dependency on a random number
+// generator.
+fn multipart_boundary() -> String {
+    let high = RandomState::new().build_hasher().finish();
+    let low = RandomState::new().build_hasher().finish();
+    format!("{:016x}{:016x}", high, low)
+}
+
+struct MultipartByteranges {
+    file: BoxFileRead,
+    parts: std::vec::IntoIter<(Bytes, RangeInclusive<u64>)>,
+    closing_delimiter: Option<Bytes>,
+    remaining_in_part: u64,
+    chunk_size: usize,
+}
+
+impl MultipartByteranges {
+    async fn next_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        if self.remaining_in_part > 0 {
+            let len = self.remaining_in_part.min(self.chunk_size as u64);
+            let mut buf = BytesMut::with_capacity(len as usize);
+            let read = (&mut self.file).take(len).read_buf(&mut buf).await?;
+            if read == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "file ended before the end of the requested range",
+                ));
+            }
+            self.remaining_in_part -= read as u64;
+            return Ok(Some(buf.freeze()));
+        }
+
+        if let Some((part_header, range)) = self.parts.next() {
+            self.file.seek(SeekFrom::Start(*range.start())).await?;
+            self.remaining_in_part = range.end() - range.start() + 1;
+            return Ok(Some(part_header));
+        }
+
+        Ok(self.closing_delimiter.take())
+    }
+}
+
+fn multipart_byteranges_stream(
+    file: BoxFileRead,
+    parts: Vec<(Bytes, RangeInclusive<u64>)>,
+    closing_delimiter: Bytes,
+    chunk_size: usize,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static {
+    let state = MultipartByteranges {
+        file,
+        parts: parts.into_iter(),
+        closing_delimiter: Some(closing_delimiter),
+        remaining_in_part: 0,
+        chunk_size,
+    };
+
+    futures_util::stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        match state.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(state))),
+            Ok(None) => None,
+            // stop the stream after the first error
+            Err(err) => Some((Err(err), None)),
+        }
+    })
+}
+
 fn body_from_bytes(bytes: Bytes) -> ResponseBody {
     let body = Full::from(bytes).map_err(|err| match err {}).boxed_unsync();
     ResponseBody::new(UnsyncBoxBody::new(body))
@@ -1,6 +1,8 @@
+use super::super::FileMetadata;
+use crate::content_encoding::Encoding;
 use http::header::HeaderValue;
 use httpdate::HttpDate;
-use std::time::SystemTime;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub(super) struct LastModified(pub(super) HttpDate);
 
@@ -43,3 +45,167 @@ impl IfUnmodifiedSince {
             .map(|time| IfUnmodifiedSince(time.into()))
     }
 }
+
+/// A strong entity tag derived from a file's metadata.
+#[derive(Clone)]
+pub(super) struct ETag(String);
+
+impl ETag {
+    /// Build an entity tag from the size, modification time and inode of a file, as well as
+    /// the encoding of the variant being served.
+    ///
+    /// Returns `None` if the modification time isn't known.
+    pub(super) fn from_metadata(
+        metadata: &FileMetadata,
+        encoding: Option<Encoding>,
+    ) -> Option<ETag> {
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH).ok()?;
+        let inode = metadata.inode().unwrap_or(0);
+
+        let mut tag = format!(
+            "\"{:x}-{:x}.{:x}-{:x}",
+            inode,
+            modified.as_secs(),
+            modified.subsec_nanos(),
+            metadata.size(),
+        );
+        if let Some(encoding) = encoding.filter(|encoding| *encoding != Encoding::Identity) {
+            tag.push('-');
+            tag.push_str(encoding.to_str());
+        }
+        tag.push('"');
+
+        Some(ETag(tag))
+    }
+
+    /// The opaque part of the entity tag, without the surrounding quotes.
+    pub(super) fn opaque(&self) -> &str {
+        &self.0[1..self.0.len() - 1]
+    }
+
+    pub(super) fn to_header_value(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.0).expect("entity tag is a valid header value")
+    }
+}
+
+/// A parsed `If-Match` or `If-None-Match` header value.
+enum EntityTagList {
+    Any,
+    Tags(Vec<EntityTag>),
+}
+
+struct EntityTag {
+    weak: bool,
+    opaque: String,
+}
+
+impl EntityTagList {
+    /// Parse a header value as either `*` or a comma separated list of entity tags, invalid
+    /// values are silently ignored.
+    fn from_header_value(value: &HeaderValue) -> Option<EntityTagList> {
+        let value = value.to_str().ok()?.trim();
+        if value == "*" {
+            return Some(EntityTagList::Any);
+        }
+
+        let mut tags = Vec::new();
+        let mut rest = value;
+        loop {
+            rest = rest.trim_start_matches([',', ' ', '\t']);
+            if rest.is_empty() {
+                break;
+            }
+
+            let (weak, quoted) = match rest.strip_prefix("W/") {
+                Some(quoted) => (true, quoted),
+                None => (false, rest),
+            };
+            let quoted = quoted.strip_prefix('"')?;
+            let end = quoted.find('"')?;
+            tags.push(EntityTag {
+                weak,
+                opaque: quoted[..end].to_owned(),
+            });
+            rest = &quoted[end + 1..];
+
+            if !rest.is_empty() && !rest.starts_with([',', ' ', '\t']) {
+                return None;
+            }
+        }
+
+        if tags.is_empty() {
+            None
+        } else {
+            Some(EntityTagList::Tags(tags))
+        }
+    }
+}
+
+pub(super) struct IfMatch(EntityTagList);
+
+impl IfMatch {
+    /// Check if the current entity tag passes the precondition, using the strong comparison
+    /// function.
+    pub(super) fn precondition_passes(&self, etag: Option<&ETag>) -> bool {
+        match &self.0 {
+            EntityTagList::Any => true,
+            EntityTagList::Tags(tags) => etag.map_or(false, |etag| {
+                tags.iter()
+                    .any(|tag| !tag.weak && tag.opaque == etag.opaque())
+            }),
+        }
+    }
+
+    /// Convert a header value into a IfMatch, invalid values are silently ignored
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfMatch> {
+        EntityTagList::from_header_value(value).map(IfMatch)
+    }
+}
+
+pub(super) struct IfNoneMatch(EntityTagList);
+
+impl IfNoneMatch {
+    /// Check if the current entity tag passes the precondition, using the weak comparison
+    /// function.
+    pub(super) fn precondition_passes(&self, etag: Option<&ETag>) -> bool {
+        match &self.0 {
+            EntityTagList::Any => false,
+            EntityTagList::Tags(tags) => etag.map_or(true, |etag| {
+                tags.iter().all(|tag| tag.opaque != etag.opaque())
+            }),
+        }
+    }
+
+    /// Convert a header value into a IfNoneMatch, invalid values are silently ignored
+    pub(super) fn from_header_value(value: &HeaderValue) -> Option<IfNoneMatch> {
+        EntityTagList::from_header_value(value).map(IfNoneMatch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn etag(opaque: &str) -> ETag {
+        ETag(format!("\"{}\"", opaque))
+    }
+
+    #[test]
+    fn parse_entity_tag_list() {
+        let if_none_match =
+            IfNoneMatch::from_header_value(&HeaderValue::from_static("W/\"a,b\", \"c\"")).unwrap();
+        assert!(!if_none_match.precondition_passes(Some(&etag("a,b"))));
+        assert!(!if_none_match.precondition_passes(Some(&etag("c"))));
+        assert!(if_none_match.precondition_passes(Some(&etag("d"))));
+
+        let if_match =
+            IfMatch::from_header_value(&HeaderValue::from_static("W/\"a,b\", \"c\"")).unwrap();
+        assert!(!if_match.precondition_passes(Some(&etag("a,b"))));
+        assert!(if_match.precondition_passes(Some(&etag("c"))));
+        assert!(!if_match.precondition_passes(None));
+
+        assert!(IfMatch::from_header_value(&HeaderValue::from_static("\"unterminated")).is_none());
+        assert!(IfMatch::from_header_value(&HeaderValue::from_static("unquoted")).is_none());
+        assert!(IfMatch::from_header_value(&HeaderValue::from_static("")).is_none());
+    }
+}
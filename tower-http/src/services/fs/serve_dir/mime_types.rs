@@ -0,0 +1,113 @@
+use http::HeaderValue;
+use mime::Mime;
+use std::{collections::HashMap, path::Path};
+
+// How the `Content-Type` of served files is determined.
+#[derive(Clone, Debug, Default)]
+pub(super) struct MimeTypes {
+    // keyed by lowercase extension without the leading `.`
+    pub(super) overrides: HashMap<String, HeaderValue>,
+    pub(super) extensionless: Option<HeaderValue>,
+    pub(super) charset_utf8: bool,
+}
+
+impl MimeTypes {
+    pub(super) fn insert_override(&mut self, extension: &str, mime: &Mime) {
+        let extension = extension.trim_start_matches('.').to_ascii_lowercase();
+        self.overrides
+            .insert(extension, HeaderValue::from_str(mime.as_ref()).unwrap());
+    }
+
+    pub(super) fn guess(&self, path: &Path) -> HeaderValue {
+        let mime = match path.extension() {
+            Some(extension) => extension
+                .to_str()
+                .and_then(|extension| self.overrides.get(&extension.to_ascii_lowercase()))
+                .cloned()
+                .unwrap_or_else(|| guess_from_path(path)),
+            None => self
+                .extensionless
+                .clone()
+                .unwrap_or_else(|| guess_from_path(path)),
+        };
+
+        if self.charset_utf8 {
+            with_utf8_charset(mime)
+        } else {
+            mime
+        }
+    }
+}
+
+fn guess_from_path(path: &Path) -> HeaderValue {
+    mime_guess::from_path(path)
+        .first_raw()
+        .map(HeaderValue::from_static)
+        .unwrap_or_else(|| HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap())
+}
+
+// Append `; charset=utf-8` to textual types that don't specify a charset already.
+fn with_utf8_charset(mime: HeaderValue) -> HeaderValue {
+    let parsed = match mime
+        .to_str()
+        .ok()
+        .and_then(|mime| mime.parse::<Mime>().ok())
+    {
+        Some(parsed) => parsed,
+        None => return mime,
+    };
+
+    if parsed.get_param(mime::CHARSET).is_some() || !is_textual(&parsed) {
+        return mime;
+    }
+
+    HeaderValue::from_str(&format!("{}; charset=utf-8", parsed)).unwrap_or(mime)
+}
+
+fn is_textual(mime: &Mime) -> bool {
+    let suffix = mime.suffix().map(|suffix| suffix.as_str());
+    mime.type_() == mime::TEXT
+        || matches!(suffix, Some("json" | "xml"))
+        || (mime.type_() == mime::APPLICATION
+            && matches!(
+                mime.subtype().as_str(),
+                "javascript" | "json" | "xml" | "x-javascript" | "ecmascript"
+            ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_and_charset() {
+        let mut mime_types = MimeTypes::default();
+        mime_types.insert_override(".WASM", &"application/wasm".parse().unwrap());
+        mime_types.extensionless = Some(HeaderValue::from_static("text/plain"));
+
+        assert_eq!(mime_types.guess(Path::new("app.wasm")), "application/wasm");
+        assert_eq!(mime_types.guess(Path::new("APP.Wasm")), "application/wasm");
+        assert_eq!(mime_types.guess(Path::new("README")), "text/plain");
+        assert_eq!(mime_types.guess(Path::new("app.js")), "text/javascript");
+        assert_eq!(
+            mime_types.guess(Path::new("unknown.xyz123")),
+            "application/octet-stream"
+        );
+
+        mime_types.charset_utf8 = true;
+        assert_eq!(
+            mime_types.guess(Path::new("README")),
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(
+            mime_types.guess(Path::new("data.json")),
+            "application/json; charset=utf-8"
+        );
+        assert_eq!(
+            mime_types.guess(Path::new("logo.svg")),
+            "image/svg+xml; charset=utf-8"
+        );
+        assert_eq!(mime_types.guess(Path::new("app.wasm")), "application/wasm");
+        assert_eq!(mime_types.guess(Path::new("logo.png")), "image/png");
+    }
+}
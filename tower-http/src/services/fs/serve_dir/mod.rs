@@ -1,4 +1,8 @@
-use self::future::ResponseFuture;
+use self::{future::ResponseFuture, mime_types::MimeTypes};
+use super::{
+    access_policy::AccessPolicy, glob::Glob, Attachment, CachePolicy, CompressionCache, DotFiles,
+    ErrorPages, FileSystem, Symlinks, TokioFileSystem,
+};
 use crate::{
     body::UnsyncBoxBody,
     content_encoding::{encodings, SupportedEncodings},
@@ -8,29 +12,43 @@ use bytes::Bytes;
 use futures_util::FutureExt;
 use http::{header, HeaderValue, Method, Request, Response, StatusCode};
 use http_body_util::{BodyExt, Empty};
+use mime::Mime;
 use percent_encoding::percent_decode;
 use std::{
     convert::Infallible,
     io,
     path::{Component, Path, PathBuf},
+    sync::Arc,
     task::{Context, Poll},
 };
 use tower_service::Service;
 
+mod body;
+mod directory_listing;
 pub(crate) mod future;
 mod headers;
+mod mime_types;
 mod open_file;
 
 #[cfg(test)]
 mod tests;
 
+pub use self::body::{FileBody, ResponseBody};
+
 // default capacity 64KiB
 const DEFAULT_CAPACITY: usize = 65536;
 
+// default maximum number of ranges served in a single multipart/byteranges response
+const DEFAULT_MAX_RANGES: usize = 16;
+
 /// Service that serves files from a given directory and all its sub directories.
 ///
 /// The `Content-Type` will be guessed from the file extension.
 ///
+/// Responses carry `Last-Modified` and `ETag` headers, and the `If-Match`, `If-None-Match`,
+/// `If-Modified-Since`, `If-Unmodified-Since` and `If-Range` request headers are evaluated in the
+/// order defined by [RFC 9110].
+///
 /// An empty response with status `404 Not Found` will be returned if:
 ///
 /// - The file doesn't exist
@@ -49,14 +67,28 @@ const DEFAULT_CAPACITY: usize = 65536;
 /// // its subdirectories
 /// let service = ServeDir::new("assets");
 /// ```
+///
+/// [RFC 9110]: https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2
 #[derive(Clone, Debug)]
-pub struct ServeDir<F = DefaultServeDirFallback> {
+pub struct ServeDir<F = DefaultServeDirFallback, FS = TokioFileSystem> {
     base: PathBuf,
+    // searched in order after `base` for paths that `base` doesn't have
+    overlay_roots: Arc<[PathBuf]>,
+    fs: FS,
     buf_chunk_size: usize,
+    max_ranges: usize,
     precompressed_variants: Option<PrecompressedVariants>,
     // This is used to specialise implementation for
     // single files
     variant: ServeVariant,
+    spa_index: Option<PathBuf>,
+    cache_policy: Option<Arc<CachePolicy>>,
+    access_policy: Arc<AccessPolicy>,
+    mime_types: Arc<MimeTypes>,
+    compression_cache: Option<CompressionCache>,
+    attachment: Option<Attachment>,
+    download_query_param: Option<String>,
+    error_pages: Option<Arc<ErrorPages>>,
     fallback: Option<F>,
     call_fallback_on_method_not_allowed: bool,
 }
@@ -72,32 +104,102 @@ impl ServeDir<DefaultServeDirFallback> {
 
         Self {
             base,
+            overlay_roots: Arc::from(Vec::new()),
+            fs: TokioFileSystem::new(),
             buf_chunk_size: DEFAULT_CAPACITY,
+            max_ranges: DEFAULT_MAX_RANGES,
             precompressed_variants: None,
             variant: ServeVariant::Directory {
                 append_index_html_on_directories: true,
+                index_files: Arc::from(vec![String::from("index.html")]),
+                trailing_slash_redirect: Some(StatusCode::TEMPORARY_REDIRECT),
+                clean_urls: false,
+                directory_listing: false,
             },
+            spa_index: None,
+            cache_policy: None,
+            access_policy: Default::default(),
+            mime_types: Default::default(),
+            compression_cache: None,
+            attachment: None,
+            download_query_param: None,
+            error_pages: None,
             fallback: None,
             call_fallback_on_method_not_allowed: false,
         }
     }
 
+    /// Create a new [`ServeDir`] serving the union of several directories.
+    ///
+    /// Each request is resolved against the `roots` in order and answered from the first one
+    /// that has the requested path, so files in earlier roots override files in later ones.
+    /// This is useful for serving a base theme with per-tenant overrides, for example.
+    ///
+    /// Unlike chaining several [`ServeDir`]s with [`ServeDir::fallback`], the `Accept-Encoding`
+    /// negotiation and conditional request evaluation happen once, for the file that is
+    /// served. Precompressed variants are only looked up in the root of the uncompressed file,
+    /// so an override is never served with a stale variant from a later root.
+    ///
+    /// # Panics
+    ///
+    /// If `roots` is empty.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// // `tenants/acme/logo.svg` is served if it exists, `theme/logo.svg` otherwise
+    /// let service = ServeDir::with_roots(["tenants/acme", "theme"]);
+    /// ```
+    pub fn with_roots<I>(roots: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<Path>,
+    {
+        let mut roots = roots.into_iter();
+        let base = roots
+            .next()
+            .expect("`ServeDir::with_roots` requires at least one root");
+
+        let mut serve_dir = Self::new(base);
+        serve_dir.overlay_roots = roots
+            .map(|root| {
+                let mut path = PathBuf::from(".");
+                path.push(root.as_ref());
+                path
+            })
+            .collect();
+        serve_dir
+    }
+
     pub(crate) fn new_single_file<P>(path: P, mime: HeaderValue) -> Self
     where
         P: AsRef<Path>,
     {
         Self {
             base: path.as_ref().to_owned(),
+            overlay_roots: Arc::from(Vec::new()),
+            fs: TokioFileSystem::new(),
             buf_chunk_size: DEFAULT_CAPACITY,
+            max_ranges: DEFAULT_MAX_RANGES,
             precompressed_variants: None,
             variant: ServeVariant::SingleFile { mime },
+            spa_index: None,
+            cache_policy: None,
+            access_policy: Default::default(),
+            mime_types: Default::default(),
+            compression_cache: None,
+            attachment: None,
+            download_query_param: None,
+            error_pages: None,
             fallback: None,
             call_fallback_on_method_not_allowed: false,
         }
     }
 }
 
-impl<F> ServeDir<F> {
+impl<F, FS> ServeDir<F, FS> {
     /// If the requested path is a directory append `index.html`.
     ///
     /// This is useful for static sites.
@@ -107,6 +209,7 @@ impl<F> ServeDir<F> {
         match &mut self.variant {
             ServeVariant::Directory {
                 append_index_html_on_directories,
+                ..
             } => {
                 *append_index_html_on_directories = append;
                 self
@@ -115,6 +218,349 @@ impl<F> ServeDir<F> {
         }
     }
 
+    /// Set the names of the documents served for requests for directories, in order of
+    /// preference.
+    ///
+    /// The first document that exists in the requested directory is served. Has no effect if
+    /// [`ServeDir::append_index_html_on_directories`] is disabled.
+    ///
+    /// Defaults to `index.html`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("public").index_files(["index.html", "index.htm", "default.html"]);
+    /// ```
+    pub fn index_files<I>(mut self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        match &mut self.variant {
+            ServeVariant::Directory { index_files, .. } => {
+                *index_files = names.into_iter().map(Into::into).collect();
+                self
+            }
+            ServeVariant::SingleFile { mime: _ } => self,
+        }
+    }
+
+    /// Set the status code of the redirects from directories requested without a trailing
+    /// slash, like `/docs`, to the path with a trailing slash, `/docs/`.
+    ///
+    /// With `None` directories are served without redirecting. Note that relative links in the
+    /// served document or directory listing then resolve against the parent directory.
+    ///
+    /// Defaults to `Some(StatusCode::TEMPORARY_REDIRECT)`.
+    ///
+    /// # Panics
+    ///
+    /// If `status` isn't a [redirection status code][mdn] (3xx).
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http::StatusCode;
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("public").trailing_slash_redirect(Some(StatusCode::MOVED_PERMANENTLY));
+    /// ```
+    ///
+    /// [mdn]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Status#redirection_messages
+    pub fn trailing_slash_redirect(mut self, status: Option<StatusCode>) -> Self {
+        if let Some(status) = status {
+            assert!(status.is_redirection(), "not a redirection status code");
+        }
+
+        match &mut self.variant {
+            ServeVariant::Directory {
+                trailing_slash_redirect,
+                ..
+            } => {
+                *trailing_slash_redirect = status;
+                self
+            }
+            ServeVariant::SingleFile { mime: _ } => self,
+        }
+    }
+
+    /// Serve requests for paths that don't exist from the file with the same path and an
+    /// `.html` extension, so `/about` is answered with `about.html`.
+    ///
+    /// This matches the output of many static site generators. Directories take precedence, so
+    /// `/blog` is still answered from `blog/index.html` if it exists.
+    ///
+    /// Defaults to `false`.
+    pub fn clean_urls(mut self, enabled: bool) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory { clean_urls, .. } => {
+                *clean_urls = enabled;
+                self
+            }
+            ServeVariant::SingleFile { mime: _ } => self,
+        }
+    }
+
+    /// Render a listing of the entries of directories that have no `index.html`.
+    ///
+    /// The listing is an HTML page, or a JSON document if the request's `Accept` header prefers
+    /// `application/json`, with the name, size and last modification time of every entry. The
+    /// entries can be sorted with the `sort` (`name`, `size` or `modified`) and `order` (`asc`
+    /// or `desc`) query parameters, for example `?sort=size&order=desc`.
+    ///
+    /// Requests for directories without a trailing slash are redirected to the path with a
+    /// trailing slash so the links in the listing resolve correctly.
+    ///
+    /// The [`FileSystem`] must support [`FileSystem::read_dir`].
+    ///
+    /// Defaults to `false`.
+    pub fn directory_listing(mut self, enabled: bool) -> Self {
+        match &mut self.variant {
+            ServeVariant::Directory {
+                directory_listing, ..
+            } => {
+                *directory_listing = enabled;
+                self
+            }
+            ServeVariant::SingleFile { mime: _ } => self,
+        }
+    }
+
+    /// Serve a single page application, answering navigations to paths that don't exist with
+    /// the document at `index`.
+    ///
+    /// `index` is relative to the served directory. It is only served for requests that look
+    /// like the browser navigating to a page: the last path segment has no file extension and
+    /// the `Accept` header includes `text/html`. Requests for missing assets like `/app.js`
+    /// still result in `404 Not Found` (or a call to the fallback), rather than HTML that the
+    /// browser fails to interpret as a script.
+    ///
+    /// The index document is sent with `Cache-Control: no-cache` so clients always revalidate
+    /// it and pick up new deployments.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// // `/users/42` responds with `dist/index.html`, `/assets/missing.js` with `404 Not Found`
+    /// let service = ServeDir::new("dist").single_page_app("index.html");
+    /// ```
+    pub fn single_page_app<P>(mut self, index: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.spa_index = Some(index.as_ref().to_owned());
+        self
+    }
+
+    /// Set the policy deciding the `Cache-Control` header sent for each file.
+    ///
+    /// By default no `Cache-Control` header is sent. See [`CachePolicy`] for more details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::CachePolicy, ServeDir};
+    ///
+    /// // HTML is revalidated, files like `assets/app.3f9a1c.js` are cached forever
+    /// let service = ServeDir::new("dist").cache_policy(CachePolicy::new());
+    /// ```
+    pub fn cache_policy(mut self, policy: CachePolicy) -> Self {
+        self.cache_policy = Some(Arc::new(policy));
+        self
+    }
+
+    /// Set how requests for paths with a component starting with `.`, like `.env` or
+    /// `.git/config`, are handled.
+    ///
+    /// Defaults to [`DotFiles::Allow`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::DotFiles, ServeDir};
+    ///
+    /// let service = ServeDir::new("assets").dot_files(DotFiles::NotFound);
+    /// ```
+    pub fn dot_files(mut self, dot_files: DotFiles) -> Self {
+        Arc::make_mut(&mut self.access_policy).dot_files = dot_files;
+        self
+    }
+
+    /// Set how symbolic links are handled.
+    ///
+    /// Unless they are followed unconditionally, the requested path is canonicalized with
+    /// [`FileSystem::canonicalize`] and compared to the canonicalized served directory. Files
+    /// served through links that aren't allowed result in `404 Not Found`, or a call to the
+    /// fallback.
+    ///
+    /// Files are checked after being opened, and the opened file must have the same
+    /// [`FileMetadata::inode`] as the checked path, so a link swapped in between the check and
+    /// opening isn't followed. For file systems without inode numbers, like on Windows, and for
+    /// `HEAD` requests, which only read metadata, the check is best-effort.
+    ///
+    /// Defaults to [`Symlinks::Follow`].
+    ///
+    /// [`FileMetadata::inode`]: super::FileMetadata::inode
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::Symlinks, ServeDir};
+    ///
+    /// let service = ServeDir::new("assets").symlinks(Symlinks::FollowWithinRoot);
+    /// ```
+    pub fn symlinks(mut self, symlinks: Symlinks) -> Self {
+        Arc::make_mut(&mut self.access_policy).symlinks = symlinks;
+        self
+    }
+
+    /// Only serve files whose path, relative to the served directory, matches the glob
+    /// `pattern`.
+    ///
+    /// Can be called multiple times to allow files matching any of the patterns. Files that
+    /// aren't allowed result in `404 Not Found`, or a call to the fallback. See
+    /// [`CachePolicy::path`] for the syntax of the patterns.
+    ///
+    /// By default all files are allowed.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("dist")
+    ///     .allow_path("*.html")
+    ///     .allow_path("/assets/**");
+    /// ```
+    pub fn allow_path(mut self, pattern: &str) -> Self {
+        Arc::make_mut(&mut self.access_policy)
+            .allow
+            .push(Glob::new(pattern));
+        self
+    }
+
+    /// Never serve files or directories whose path, relative to the served directory, matches
+    /// the glob `pattern`.
+    ///
+    /// Can be called multiple times. Denied paths result in `404 Not Found`, or a call to the
+    /// fallback, even if they are also allowed with [`ServeDir::allow_path`]. See
+    /// [`CachePolicy::path`] for the syntax of the patterns.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("dist").deny_path("*.map").deny_path("/private/**");
+    /// ```
+    pub fn deny_path(mut self, pattern: &str) -> Self {
+        Arc::make_mut(&mut self.access_policy)
+            .deny
+            .push(Glob::new(pattern));
+        self
+    }
+
+    /// Serve files with the extension `extension` with the `Content-Type` `mime`, rather than
+    /// the type guessed from the extension.
+    ///
+    /// Extensions are matched case insensitively. Can be called multiple times to override the
+    /// type of several extensions.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// let service = ServeDir::new("assets")
+    ///     .mime_override("mjs", &mime::TEXT_JAVASCRIPT)
+    ///     .mime_override("webmanifest", &"application/manifest+json".parse().unwrap());
+    /// ```
+    pub fn mime_override(mut self, extension: &str, mime: &Mime) -> Self {
+        Arc::make_mut(&mut self.mime_types).insert_override(extension, mime);
+        self
+    }
+
+    /// Set the `Content-Type` of files without an extension.
+    ///
+    /// Defaults to `application/octet-stream`.
+    pub fn extensionless_mime(mut self, mime: &Mime) -> Self {
+        Arc::make_mut(&mut self.mime_types).extensionless =
+            Some(HeaderValue::from_str(mime.as_ref()).unwrap());
+        self
+    }
+
+    /// Append `; charset=utf-8` to the `Content-Type` of textual files, like `text/*`,
+    /// JavaScript, JSON and XML, that don't specify a charset already.
+    ///
+    /// Defaults to `false`.
+    pub fn charset_utf8(mut self, enabled: bool) -> Self {
+        Arc::make_mut(&mut self.mime_types).charset_utf8 = enabled;
+        self
+    }
+
+    /// Serve all files as downloads, with a `Content-Disposition: attachment` header.
+    ///
+    /// To serve only some responses as downloads, insert an [`Attachment`] into the extensions
+    /// of the request or see [`ServeDir::download_query_param`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::Attachment, ServeDir};
+    ///
+    /// let service = ServeDir::new("exports").attachment(Attachment::new());
+    /// ```
+    pub fn attachment(mut self, attachment: Attachment) -> Self {
+        self.attachment = Some(attachment);
+        self
+    }
+
+    /// Serve files as downloads, with a `Content-Disposition: attachment` header, if the request
+    /// has the query parameter `name`.
+    ///
+    /// The value of the parameter, if any, is used as the file name of the download instead of
+    /// the name of the served file.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::ServeDir;
+    ///
+    /// // `/report.pdf?download` is served as a download named `report.pdf` and
+    /// // `/report.pdf?download=Q3%20report.pdf` as a download named `Q3 report.pdf`
+    /// let service = ServeDir::new("exports").download_query_param("download");
+    /// ```
+    pub fn download_query_param(mut self, name: impl Into<String>) -> Self {
+        self.download_query_param = Some(name.into());
+        self
+    }
+
+    /// Send the error responses of the service, like `404 Not Found`, with the bodies from
+    /// `error_pages` rather than empty bodies.
+    ///
+    /// Unlike [`ServeDir::not_found_service`] this keeps the status code and covers the other
+    /// error responses too, like `405 Method Not Allowed`, `412 Precondition Failed` and `416
+    /// Range Not Satisfiable`. A fallback still takes precedence for files that don't exist. See
+    /// [`ErrorPages`] for more details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use http::StatusCode;
+    /// use tower_http::services::{fs::ErrorPages, ServeDir};
+    ///
+    /// let service = ServeDir::new("assets")
+    ///     .error_pages(ErrorPages::new().file(StatusCode::NOT_FOUND, "404.html"));
+    /// ```
+    pub fn error_pages(mut self, error_pages: ErrorPages) -> Self {
+        self.error_pages = Some(Arc::new(error_pages));
+        self
+    }
+
     /// Set a specific read buffer chunk size.
     ///
     /// The default capacity is 64kb.
@@ -123,6 +569,18 @@ impl<F> ServeDir<F> {
         self
     }
 
+    /// Set the maximum number of ranges served for a single request.
+    ///
+    /// Requests with more than one range are answered with a `multipart/byteranges` response.
+    /// Requests with more ranges than this are rejected with `416 Range Not Satisfiable`, which
+    /// prevents clients from amplifying a small request into a very large response.
+    ///
+    /// Defaults to 16.
+    pub fn max_ranges(mut self, max_ranges: usize) -> Self {
+        self.max_ranges = max_ranges;
+        self
+    }
+
     /// Informs the service that it should also look for a precompressed gzip
     /// version of _any_ file in the directory.
     ///
@@ -191,6 +649,26 @@ impl<F> ServeDir<F> {
         self
     }
 
+    /// Compress files on the fly for clients that accept an encoding for which there is no
+    /// precompressed file, and keep the compressed files in `cache`.
+    ///
+    /// Each file is compressed once per encoding and then served from the cache, with a
+    /// `Content-Length` and support for range requests. See [`CompressionCache`] for details.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::CompressionCache, ServeDir};
+    ///
+    /// let service = ServeDir::new("assets")
+    ///     .precompressed_br()
+    ///     .compression_cache(CompressionCache::in_memory(64 * 1024 * 1024));
+    /// ```
+    pub fn compression_cache(mut self, cache: CompressionCache) -> Self {
+        self.compression_cache = Some(cache);
+        self
+    }
+
     /// Set the fallback service.
     ///
     /// This service will be called if there is no file at the path of the request.
@@ -209,12 +687,23 @@ impl<F> ServeDir<F> {
     ///     // respond with `not_found.html` for missing files
     ///     .fallback(ServeFile::new("assets/not_found.html"));
     /// ```
-    pub fn fallback<F2>(self, new_fallback: F2) -> ServeDir<F2> {
+    pub fn fallback<F2>(self, new_fallback: F2) -> ServeDir<F2, FS> {
         ServeDir {
             base: self.base,
+            overlay_roots: self.overlay_roots,
+            fs: self.fs,
             buf_chunk_size: self.buf_chunk_size,
+            max_ranges: self.max_ranges,
             precompressed_variants: self.precompressed_variants,
             variant: self.variant,
+            spa_index: self.spa_index,
+            cache_policy: self.cache_policy,
+            access_policy: self.access_policy,
+            mime_types: self.mime_types,
+            compression_cache: self.compression_cache,
+            attachment: self.attachment,
+            download_query_param: self.download_query_param,
+            error_pages: self.error_pages,
             fallback: Some(new_fallback),
             call_fallback_on_method_not_allowed: self.call_fallback_on_method_not_allowed,
         }
@@ -237,10 +726,43 @@ impl<F> ServeDir<F> {
     /// ```
     ///
     /// Setups like this are often found in single page applications.
-    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeDir<SetStatus<F2>> {
+    pub fn not_found_service<F2>(self, new_fallback: F2) -> ServeDir<SetStatus<F2>, FS> {
         self.fallback(SetStatus::new(new_fallback, StatusCode::NOT_FOUND))
     }
 
+    /// Set the [`FileSystem`] files are served from.
+    ///
+    /// Defaults to [`TokioFileSystem`] which serves files from disk.
+    ///
+    /// # Example
+    ///
+    /// See [`FileSystem`] for an example of serving files from memory.
+    pub fn file_system<FS2>(self, fs: FS2) -> ServeDir<F, FS2> {
+        ServeDir {
+            base: self.base,
+            overlay_roots: self.overlay_roots,
+            fs,
+            buf_chunk_size: self.buf_chunk_size,
+            max_ranges: self.max_ranges,
+            precompressed_variants: self.precompressed_variants,
+            variant: self.variant,
+            spa_index: self.spa_index,
+            cache_policy: self.cache_policy,
+            access_policy: self.access_policy,
+            mime_types: self.mime_types,
+            compression_cache: self.compression_cache,
+            attachment: self.attachment,
+            download_query_param: self.download_query_param,
+            error_pages: self.error_pages,
+            fallback: self.fallback,
+            call_fallback_on_method_not_allowed: self.call_fallback_on_method_not_allowed,
+        }
+    }
+
+    pub(crate) fn file_system_mut(&mut self) -> &mut FS {
+        &mut self.fs
+    }
+
     /// Customize whether or not to call the fallback for requests that aren't `GET` or `HEAD`.
     ///
     /// Defaults to not calling the fallback and instead returning `405 Method Not Allowed`.
@@ -312,16 +834,20 @@ impl<F> ServeDir<F> {
         F::Future: Send + 'static,
         FResBody: http_body::Body<Data = Bytes> + Send + 'static,
         FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        FS: FileSystem,
     {
+        let error_page = self.error_page(&req);
+
         if req.method() != Method::GET && req.method() != Method::HEAD {
             if self.call_fallback_on_method_not_allowed {
                 if let Some(fallback) = &mut self.fallback {
                     return ResponseFuture {
                         inner: future::call_fallback(fallback, req),
+                        error_page: None,
                     };
                 }
             } else {
-                return ResponseFuture::method_not_allowed();
+                return ResponseFuture::method_not_allowed().with_error_page(error_page);
             }
         }
 
@@ -330,6 +856,15 @@ impl<F> ServeDir<F> {
         //
         // this is necessary because we cannot clone bodies
         let (mut parts, body) = req.into_parts();
+        let attachment = parts
+            .extensions
+            .get::<Attachment>()
+            .cloned()
+            .or_else(|| {
+                let name = self.download_query_param.as_deref()?;
+                attachment_from_query(parts.uri.query()?, name)
+            })
+            .or_else(|| self.attachment.clone());
         // same goes for extensions
         let extensions = std::mem::take(&mut parts.extensions);
         let req = Request::from_parts(parts, Empty::<Bytes>::new());
@@ -354,11 +889,11 @@ impl<F> ServeDir<F> {
         {
             Some(path_to_file) => path_to_file,
             None => {
-                return ResponseFuture::invalid_path(fallback_and_request);
+                return ResponseFuture::invalid_path(fallback_and_request)
+                    .with_error_page(error_page);
             }
         };
 
-        let buf_chunk_size = self.buf_chunk_size;
         let range_header = req
             .headers()
             .get(header::RANGE)
@@ -371,27 +906,74 @@ impl<F> ServeDir<F> {
         )
         .collect();
 
-        let variant = self.variant.clone();
+        let settings = open_file::OpenFileSettings {
+            variant: self.variant.clone(),
+            base: self.base.clone(),
+            overlay_roots: self.overlay_roots.clone(),
+            buf_chunk_size: self.buf_chunk_size,
+            max_ranges: self.max_ranges,
+            cache_policy: self.cache_policy.clone(),
+            access_policy: self.access_policy.clone(),
+            mime_types: self.mime_types.clone(),
+            compression_cache: self.compression_cache.clone(),
+        };
+
+        let spa_index = self
+            .spa_index
+            .as_ref()
+            .filter(|_| is_navigation(&req))
+            .map(|index| self.base.join(index));
 
         let open_file_future = Box::pin(open_file::open_file(
-            variant,
+            self.fs.clone(),
+            settings,
             path_to_file,
             req,
             negotiated_encodings,
             range_header,
-            buf_chunk_size,
+            spa_index,
+            attachment,
         ));
 
         ResponseFuture::open_file_future(open_file_future, fallback_and_request)
+            .with_error_page(error_page)
+    }
+
+    fn error_page<B>(&self, req: &Request<B>) -> Option<future::ErrorPage>
+    where
+        FS: FileSystem,
+    {
+        let error_pages = self.error_pages.clone()?;
+        let fs = self.fs.clone();
+        let base = self.base.clone();
+
+        let mut page_req = Request::new(());
+        *page_req.method_mut() = req.method().clone();
+        *page_req.uri_mut() = req.uri().clone();
+        *page_req.version_mut() = req.version();
+        *page_req.headers_mut() = req.headers().clone();
+
+        Some(future::ErrorPage {
+            is_head: req.method() == Method::HEAD,
+            render: Box::new(move |status| {
+                if !error_pages.handles(status) {
+                    return None;
+                }
+                Some(Box::pin(async move {
+                    error_pages.page(&fs, &base, status, &page_req).await
+                }))
+            }),
+        })
     }
 }
 
-impl<ReqBody, F, FResBody> Service<Request<ReqBody>> for ServeDir<F>
+impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeDir<F, FS>
 where
     F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
     F::Future: Send + 'static,
     FResBody: http_body::Body<Data = Bytes> + Send + 'static,
     FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    FS: FileSystem,
 {
     type Response = Response<ResponseBody>;
     type Error = Infallible;
@@ -437,12 +1019,56 @@ opaque_future! {
         >;
 }
 
+// Whether the request looks like a browser navigating to a page of a single page application,
+// rather than loading an asset.
+fn is_navigation<B>(req: &Request<B>) -> bool {
+    let last_segment = req.uri().path().rsplit('/').next().unwrap_or_default();
+    if Path::new(last_segment).extension().is_some() {
+        return false;
+    }
+
+    req.headers()
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            media_range
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case("text/html")
+        })
+}
+
+fn attachment_from_query(query: &str, name: &str) -> Option<Attachment> {
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        if key != name {
+            return None;
+        }
+
+        let value = value.replace('+', " ");
+        let filename = percent_decode(value.as_bytes()).decode_utf8().ok()?;
+        if filename.is_empty() {
+            Some(Attachment::new())
+        } else {
+            Some(Attachment::with_filename(filename))
+        }
+    })
+}
+
 // Allow the ServeDir service to be used in the ServeFile service
 // with almost no overhead
 #[derive(Clone, Debug)]
 enum ServeVariant {
     Directory {
         append_index_html_on_directories: bool,
+        index_files: Arc<[String]>,
+        trailing_slash_redirect: Option<StatusCode>,
+        clean_urls: bool,
+        directory_listing: bool,
     },
     SingleFile {
         mime: HeaderValue,
@@ -452,9 +1078,7 @@ enum ServeVariant {
 impl ServeVariant {
     fn build_and_validate_path(&self, base_path: &Path, requested_path: &str) -> Option<PathBuf> {
         match self {
-            ServeVariant::Directory {
-                append_index_html_on_directories: _,
-            } => {
+            ServeVariant::Directory { .. } => {
                 let path = requested_path.trim_start_matches('/');
 
                 let path_decoded = percent_decode(path.as_ref()).decode_utf8().ok()?;
@@ -487,12 +1111,6 @@ impl ServeVariant {
     }
 }
 
-opaque_body! {
-    /// Response body for [`ServeDir`] and [`ServeFile`][super::ServeFile].
-    #[derive(Default)]
-    pub type ResponseBody = UnsyncBoxBody<Bytes, io::Error>;
-}
-
 /// The default fallback service used with [`ServeDir`].
 #[derive(Debug, Clone, Copy)]
 pub struct DefaultServeDirFallback(Infallible);
@@ -1,129 +1,329 @@
 use super::{
-    headers::{IfModifiedSince, IfUnmodifiedSince, LastModified},
+    super::{
+        access_policy::{Access, AccessPolicy},
+        file_system::BoxFileRead,
+        Attachment, CachePolicy, CompressionCache, DirEntry, FileMetadata, FileSystem, Symlinks,
+    },
+    directory_listing::{self, DirectoryListing},
+    headers::{ETag, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince, LastModified},
+    mime_types::MimeTypes,
     ServeVariant,
 };
-use crate::content_encoding::{Encoding, QValue};
+use crate::{
+    content_encoding::{Encoding, QValue},
+    range_utils::{if_range_passes, parse_range},
+};
 use bytes::Bytes;
-use http::{header, HeaderValue, Method, Request, Uri};
+use http::{header, HeaderValue, Method, Request, StatusCode, Uri};
 use http_body_util::Empty;
 use http_range_header::RangeUnsatisfiableError;
 use std::{
     ffi::OsStr,
-    fs::Metadata,
     io::{self, SeekFrom},
     ops::RangeInclusive,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
 };
-use tokio::{fs::File, io::AsyncSeekExt};
+use tokio::io::AsyncSeekExt;
 
 pub(super) enum OpenFileOutput {
     FileOpened(Box<FileOpened>),
-    Redirect { location: HeaderValue },
+    Redirect {
+        location: HeaderValue,
+        status: StatusCode,
+    },
+    DirectoryListing {
+        listing: DirectoryListing,
+        is_head: bool,
+    },
     FileNotFound,
+    Forbidden,
     PreconditionFailed,
-    NotModified,
+    NotModified {
+        etag: Option<ETag>,
+        cache_control: Option<HeaderValue>,
+    },
+}
+
+impl OpenFileOutput {
+    fn with_cache_control(mut self, value: HeaderValue) -> Self {
+        match &mut self {
+            OpenFileOutput::FileOpened(file_output) => {
+                file_output.cache_control = Some(value);
+            }
+            OpenFileOutput::NotModified { cache_control, .. } => *cache_control = Some(value),
+            _ => {}
+        }
+        self
+    }
 }
 
 pub(super) struct FileOpened {
     pub(super) extent: FileRequestExtent,
     pub(super) chunk_size: usize,
+    pub(super) max_ranges: usize,
     pub(super) mime_header_value: HeaderValue,
     pub(super) maybe_encoding: Option<Encoding>,
     pub(super) maybe_range: Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>>,
     pub(super) last_modified: Option<LastModified>,
+    pub(super) etag: Option<ETag>,
+    pub(super) cache_control: Option<HeaderValue>,
+    pub(super) content_disposition: Option<HeaderValue>,
 }
 
 pub(super) enum FileRequestExtent {
-    Full(File, Metadata),
-    Head(Metadata),
+    Full(BoxFileRead, FileMetadata),
+    Head(FileMetadata),
 }
 
-pub(super) async fn open_file(
-    variant: ServeVariant,
-    mut path_to_file: PathBuf,
+// The settings of the `ServeDir` that decide how requests are answered.
+#[derive(Clone)]
+pub(super) struct OpenFileSettings {
+    pub(super) variant: ServeVariant,
+    pub(super) base: PathBuf,
+    pub(super) overlay_roots: Arc<[PathBuf]>,
+    pub(super) buf_chunk_size: usize,
+    pub(super) max_ranges: usize,
+    pub(super) cache_policy: Option<Arc<CachePolicy>>,
+    pub(super) access_policy: Arc<AccessPolicy>,
+    pub(super) mime_types: Arc<MimeTypes>,
+    pub(super) compression_cache: Option<CompressionCache>,
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(super) async fn open_file<FS: FileSystem>(
+    fs: FS,
+    settings: OpenFileSettings,
+    path_to_file: PathBuf,
     req: Request<Empty<Bytes>>,
     negotiated_encodings: Vec<(Encoding, QValue)>,
     range_header: Option<String>,
-    buf_chunk_size: usize,
+    spa_index: Option<PathBuf>,
+    attachment: Option<Attachment>,
 ) -> io::Result<OpenFileOutput> {
-    let if_unmodified_since = req
-        .headers()
-        .get(header::IF_UNMODIFIED_SINCE)
-        .and_then(IfUnmodifiedSince::from_header_value);
-
-    let if_modified_since = req
-        .headers()
-        .get(header::IF_MODIFIED_SINCE)
-        .and_then(IfModifiedSince::from_header_value);
+    let output = open_in_roots(
+        &fs,
+        &settings,
+        path_to_file,
+        &req,
+        negotiated_encodings.clone(),
+        range_header.clone(),
+        attachment.as_ref(),
+    )
+    .await;
+
+    // navigations to paths that don't exist get the index document of the single page app
+    let spa_index = match spa_index {
+        Some(spa_index) if is_not_found(&output) => spa_index,
+        _ => return output,
+    };
+    let settings = OpenFileSettings {
+        variant: ServeVariant::SingleFile {
+            mime: settings.mime_types.guess(&spa_index),
+        },
+        ..settings
+    };
+    let output = open_in_roots(
+        &fs,
+        &settings,
+        spa_index,
+        &req,
+        negotiated_encodings,
+        range_header,
+        attachment.as_ref(),
+    )
+    .await?;
+    Ok(output.with_cache_control(HeaderValue::from_static("no-cache")))
+}
 
-    let mime = match variant {
-        ServeVariant::Directory {
-            append_index_html_on_directories,
-        } => {
-            // Might already at this point know a redirect or not found result should be
-            // returned which corresponds to a Some(output). Otherwise the path might be
-            // modified and proceed to the open file/metadata future.
-            if let Some(output) = maybe_redirect_or_append_path(
-                &mut path_to_file,
-                req.uri(),
-                append_index_html_on_directories,
+// Open the path in the served directory or, if it isn't found there, in the first of the overlay
+// roots that has it.
+async fn open_in_roots<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
+    path_to_file: PathBuf,
+    req: &Request<Empty<Bytes>>,
+    negotiated_encodings: Vec<(Encoding, QValue)>,
+    range_header: Option<String>,
+    attachment: Option<&Attachment>,
+) -> io::Result<OpenFileOutput> {
+    let relative = match path_to_file.strip_prefix(&settings.base) {
+        Ok(relative) if !settings.overlay_roots.is_empty() => relative.to_owned(),
+        _ => {
+            return open_path(
+                fs,
+                settings,
+                path_to_file,
+                req,
+                negotiated_encodings,
+                range_header,
+                attachment,
             )
             .await
+        }
+    };
+
+    let mut output = open_path(
+        fs,
+        settings,
+        path_to_file,
+        req,
+        negotiated_encodings.clone(),
+        range_header.clone(),
+        attachment,
+    )
+    .await;
+    for root in settings.overlay_roots.iter() {
+        if !is_not_found(&output) {
+            break;
+        }
+        // policies and symlinks are checked relative to the root the file is served from
+        let settings = OpenFileSettings {
+            base: root.clone(),
+            ..settings.clone()
+        };
+        output = open_path(
+            fs,
+            &settings,
+            root.join(&relative),
+            req,
+            negotiated_encodings.clone(),
+            range_header.clone(),
+            attachment,
+        )
+        .await;
+    }
+    output
+}
+
+async fn open_path<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
+    mut path_to_file: PathBuf,
+    req: &Request<Empty<Bytes>>,
+    negotiated_encodings: Vec<(Encoding, QValue)>,
+    range_header: Option<String>,
+    attachment: Option<&Attachment>,
+) -> io::Result<OpenFileOutput> {
+    let preconditions = Preconditions::from_request(req);
+    let if_range = req.headers().get(header::IF_RANGE).cloned();
+
+    // the path might still turn out to be a directory, to which the allow list doesn't apply
+    if let Some(output) = check_access(settings, &path_to_file, false) {
+        return Ok(output);
+    }
+    check_symlinks(fs, settings, &path_to_file, None).await?;
+
+    let mime = match settings.variant {
+        ServeVariant::Directory { .. } => {
+            // Might already at this point know a redirect, not found or directory listing
+            // result should be returned which corresponds to a Some(output). Otherwise the path
+            // might be modified and proceed to the open file/metadata future.
+            if let Some(output) =
+                maybe_redirect_or_append_path(fs, settings, &mut path_to_file, req).await?
             {
                 return Ok(output);
             }
 
-            mime_guess::from_path(&path_to_file)
-                .first_raw()
-                .map(HeaderValue::from_static)
-                .unwrap_or_else(|| {
-                    HeaderValue::from_str(mime::APPLICATION_OCTET_STREAM.as_ref()).unwrap()
-                })
+            settings.mime_types.guess(&path_to_file)
         }
 
-        ServeVariant::SingleFile { mime } => mime,
+        ServeVariant::SingleFile { ref mime } => mime.clone(),
     };
 
+    if let Some(output) = check_access(settings, &path_to_file, true) {
+        return Ok(output);
+    }
+
+    let cache_control = settings.cache_policy.as_ref().and_then(|policy| {
+        policy.cache_control(&relative_path(&settings.base, &path_to_file), &mime)
+    });
+    let content_disposition = attachment.map(|attachment| {
+        attachment.header_value(path_to_file.file_name().and_then(|name| name.to_str()))
+    });
+
     if req.method() == Method::HEAD {
         let (meta, maybe_encoding) =
-            file_metadata_with_fallback(path_to_file, negotiated_encodings).await?;
-
-        let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
-            return Ok(output);
+            file_metadata_with_fallback(fs, settings, path_to_file.clone(), negotiated_encodings)
+                .await?;
+        // only compressed variants that are cached already are served, `HEAD` requests don't
+        // compress the file
+        let cached = cached_encoding(settings, req, &mime, &meta, maybe_encoding).and_then(
+            |(cache, encoding)| {
+                let size = cache.cached_size(&path_to_file, &meta, encoding)?;
+                Some((encoding, size))
+            },
+        );
+
+        let last_modified = meta.modified().map(LastModified::from);
+        let etag = ETag::from_metadata(&meta, maybe_encoding.or(cached.map(|(enc, _)| enc)));
+        if let Some(output) = preconditions.check(last_modified.as_ref(), etag.as_ref()) {
+            return Ok(match cache_control {
+                Some(cache_control) => output.with_cache_control(cache_control),
+                None => output,
+            });
         }
 
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
+        let (meta, maybe_encoding) = match cached {
+            Some((encoding, size)) => (compressed_metadata(&meta, size), Some(encoding)),
+            None => (meta, maybe_encoding),
+        };
+
+        let range_header = range_header.filter(|_| {
+            if_range_passes(
+                if_range.as_ref(),
+                last_modified.as_ref().map(|last_modified| last_modified.0),
+                etag.as_ref().map(ETag::opaque),
+            )
+        });
+        let maybe_range = parse_range(range_header.as_deref(), meta.size());
 
         Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
             extent: FileRequestExtent::Head(meta),
-            chunk_size: buf_chunk_size,
+            chunk_size: settings.buf_chunk_size,
+            max_ranges: settings.max_ranges,
             mime_header_value: mime,
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
+            cache_control,
+            content_disposition,
         })))
     } else {
-        let (mut file, maybe_encoding) =
-            open_file_with_fallback(path_to_file, negotiated_encodings).await?;
-        let meta = file.metadata().await?;
-        let last_modified = meta.modified().ok().map(LastModified::from);
-        if let Some(output) = check_modified_headers(
-            last_modified.as_ref(),
-            if_unmodified_since,
-            if_modified_since,
-        ) {
-            return Ok(output);
+        let (file, meta, maybe_encoding) =
+            open_file_with_fallback(fs, settings, path_to_file.clone(), negotiated_encodings)
+                .await?;
+        let cached = cached_encoding(settings, req, &mime, &meta, maybe_encoding);
+
+        let last_modified = meta.modified().map(LastModified::from);
+        let etag = ETag::from_metadata(&meta, maybe_encoding.or(cached.map(|(_, enc)| enc)));
+        if let Some(output) = preconditions.check(last_modified.as_ref(), etag.as_ref()) {
+            return Ok(match cache_control {
+                Some(cache_control) => output.with_cache_control(cache_control),
+                None => output,
+            });
         }
 
-        let maybe_range = try_parse_range(range_header.as_deref(), meta.len());
+        let (mut file, meta, maybe_encoding) = match cached {
+            Some((cache, encoding)) => {
+                let (file, meta) =
+                    open_compressed(fs, cache, &path_to_file, &meta, encoding).await?;
+                (file, meta, Some(encoding))
+            }
+            None => (Box::new(file) as BoxFileRead, meta, maybe_encoding),
+        };
+
+        let range_header = range_header.filter(|_| {
+            if_range_passes(
+                if_range.as_ref(),
+                last_modified.as_ref().map(|last_modified| last_modified.0),
+                etag.as_ref().map(ETag::opaque),
+            )
+        });
+        let maybe_range = parse_range(range_header.as_deref(), meta.size());
         if let Some(Ok(ranges)) = maybe_range.as_ref() {
-            // if there is any other amount of ranges than 1 we'll return an
-            // unsatisfiable later as there isn't yet support for multipart ranges
+            // multipart responses seek to the start of each range while streaming the body
             if ranges.len() == 1 {
                 file.seek(SeekFrom::Start(*ranges[0].start())).await?;
             }
@@ -131,43 +331,227 @@ pub(super) async fn open_file(
 
         Ok(OpenFileOutput::FileOpened(Box::new(FileOpened {
             extent: FileRequestExtent::Full(file, meta),
-            chunk_size: buf_chunk_size,
+            chunk_size: settings.buf_chunk_size,
+            max_ranges: settings.max_ranges,
             mime_header_value: mime,
             maybe_encoding,
             maybe_range,
             last_modified,
+            etag,
+            cache_control,
+            content_disposition,
         })))
     }
 }
 
-fn check_modified_headers(
-    modified: Option<&LastModified>,
+// The compression cache and encoding to serve the file with, unless a precompressed file is served
+// or the client accepts none of the encodings of the cache.
+fn cached_encoding<'a>(
+    settings: &'a OpenFileSettings,
+    req: &Request<Empty<Bytes>>,
+    mime: &HeaderValue,
+    meta: &FileMetadata,
+    maybe_encoding: Option<Encoding>,
+) -> Option<(&'a CompressionCache, Encoding)> {
+    if maybe_encoding.is_some() {
+        return None;
+    }
+    let cache = settings.compression_cache.as_ref()?;
+    let encoding = cache.encoding_for(req.headers(), mime, meta)?;
+    Some((cache, encoding))
+}
+
+// Open the compressed variant of a file from the compression cache, along with metadata
+// describing the compressed variant.
+async fn open_compressed<FS: FileSystem>(
+    fs: &FS,
+    cache: &CompressionCache,
+    path_to_file: &Path,
+    meta: &FileMetadata,
+    encoding: Encoding,
+) -> io::Result<(BoxFileRead, FileMetadata)> {
+    let (file, size) = cache.compressed(fs, path_to_file, meta, encoding).await?;
+    Ok((file, compressed_metadata(meta, size)))
+}
+
+// Metadata describing the compressed variant of a file of `size` bytes.
+fn compressed_metadata(meta: &FileMetadata, size: u64) -> FileMetadata {
+    let mut compressed_meta = FileMetadata::file(size);
+    if let Some(modified) = meta.modified() {
+        compressed_meta = compressed_meta.with_modified(modified);
+    }
+    if let Some(inode) = meta.inode() {
+        compressed_meta = compressed_meta.with_inode(inode);
+    }
+    compressed_meta
+}
+
+// The path of the file relative to the served directory, with `/` as the separator, for matching
+// against the patterns of policies.
+fn relative_path(base: &Path, path_to_file: &Path) -> String {
+    let relative = match path_to_file.strip_prefix(base) {
+        // `ServeFile` serves the base path itself
+        Ok(relative) if relative.as_os_str().is_empty() => {
+            path_to_file.file_name().map(Path::new).unwrap_or(relative)
+        }
+        Ok(relative) => relative,
+        Err(_) => path_to_file,
+    };
+
+    relative
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(name) => Some(name.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn check_access(
+    settings: &OpenFileSettings,
+    path_to_file: &Path,
+    is_file: bool,
+) -> Option<OpenFileOutput> {
+    let relative = relative_path(&settings.base, path_to_file);
+    match settings.access_policy.check(&relative, is_file) {
+        Access::Allowed => None,
+        Access::NotFound => Some(OpenFileOutput::FileNotFound),
+        Access::Forbidden => Some(OpenFileOutput::Forbidden),
+    }
+}
+
+// Results in a `NotFound` error if `path` is a symbolic link, or is in a directory that is a
+// symbolic link, that the policy doesn't allow following.
+//
+// With the metadata of the file opened at `path`, it must also be the file that was checked,
+// otherwise a link could be replaced between checking and opening.
+async fn check_symlinks<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
+    path: &Path,
+    opened: Option<&FileMetadata>,
+) -> io::Result<()> {
+    let symlinks = settings.access_policy.symlinks;
+    if symlinks == Symlinks::Follow {
+        return Ok(());
+    }
+
+    let root = fs.canonicalize(&settings.base).await?;
+    let canonical = fs.canonicalize(path).await?;
+    let allowed = match symlinks {
+        Symlinks::FollowWithinRoot => canonical.starts_with(&root),
+        // without symbolic links the canonical path is the path within the canonical root
+        _ => path
+            .strip_prefix(&settings.base)
+            .map_or(false, |relative| canonical == root.join(relative)),
+    };
+    let allowed = match opened.and_then(FileMetadata::inode) {
+        Some(inode) if allowed => fs.metadata(&canonical).await?.inode() == Some(inode),
+        _ => allowed,
+    };
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "symbolic link not allowed",
+        ))
+    }
+}
+
+fn is_not_found(output: &io::Result<OpenFileOutput>) -> bool {
+    match output {
+        Ok(OpenFileOutput::FileNotFound) => true,
+        Ok(_) => false,
+        Err(err) => is_not_found_error(err),
+    }
+}
+
+// Errors that result in `404 Not Found` rather than `500 Internal Server Error`.
+pub(super) fn is_not_found_error(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    // 20 = libc::ENOTDIR => "not a directory
+    // when `io_error_more` landed, this can be changed
+    // to checking for `io::ErrorKind::NotADirectory`.
+    // https://github.com/rust-lang/rust/issues/86442
+    let error_is_not_a_directory = err.raw_os_error() == Some(20);
+    #[cfg(not(unix))]
+    let error_is_not_a_directory = false;
+
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+    ) || error_is_not_a_directory
+}
+
+// The conditional request headers, evaluated in the order defined by RFC 9110 section 13.2.2.
+struct Preconditions {
+    if_match: Option<IfMatch>,
     if_unmodified_since: Option<IfUnmodifiedSince>,
+    if_none_match: Option<IfNoneMatch>,
     if_modified_since: Option<IfModifiedSince>,
-) -> Option<OpenFileOutput> {
-    if let Some(since) = if_unmodified_since {
-        let precondition = modified
-            .as_ref()
-            .map(|time| since.precondition_passes(time))
-            .unwrap_or(false);
-
-        if !precondition {
-            return Some(OpenFileOutput::PreconditionFailed);
+}
+
+impl Preconditions {
+    fn from_request<B>(req: &Request<B>) -> Self {
+        let headers = req.headers();
+        Self {
+            if_match: headers
+                .get(header::IF_MATCH)
+                .and_then(IfMatch::from_header_value),
+            if_unmodified_since: headers
+                .get(header::IF_UNMODIFIED_SINCE)
+                .and_then(IfUnmodifiedSince::from_header_value),
+            if_none_match: headers
+                .get(header::IF_NONE_MATCH)
+                .and_then(IfNoneMatch::from_header_value),
+            if_modified_since: headers
+                .get(header::IF_MODIFIED_SINCE)
+                .and_then(IfModifiedSince::from_header_value),
         }
     }
 
-    if let Some(since) = if_modified_since {
-        let unmodified = modified
-            .as_ref()
-            .map(|time| !since.is_modified(time))
-            // no last_modified means its always modified
-            .unwrap_or(false);
-        if unmodified {
-            return Some(OpenFileOutput::NotModified);
+    fn check(self, modified: Option<&LastModified>, etag: Option<&ETag>) -> Option<OpenFileOutput> {
+        if let Some(if_match) = self.if_match {
+            if !if_match.precondition_passes(etag) {
+                return Some(OpenFileOutput::PreconditionFailed);
+            }
+        } else if let Some(since) = self.if_unmodified_since {
+            let precondition = modified
+                .map(|time| since.precondition_passes(time))
+                .unwrap_or(false);
+
+            if !precondition {
+                return Some(OpenFileOutput::PreconditionFailed);
+            }
+        }
+
+        // Only `GET` and `HEAD` requests get this far, so a failed `If-None-Match` always
+        // results in `304 Not Modified` rather than `412 Precondition Failed`.
+        if let Some(if_none_match) = self.if_none_match {
+            if !if_none_match.precondition_passes(etag) {
+                return Some(OpenFileOutput::NotModified {
+                    etag: etag.cloned(),
+                    cache_control: None,
+                });
+            }
+        } else if let Some(since) = self.if_modified_since {
+            let unmodified = modified
+                .map(|time| !since.is_modified(time))
+                // no last_modified means its always modified
+                .unwrap_or(false);
+            if unmodified {
+                return Some(OpenFileOutput::NotModified {
+                    etag: etag.cloned(),
+                    cache_control: None,
+                });
+            }
         }
-    }
 
-    None
+        None
+    }
 }
 
 // Returns the preferred_encoding encoding and modifies the path extension
@@ -199,15 +583,23 @@ fn preferred_encoding(
 // Attempts to open the file with any of the possible negotiated_encodings in the
 // preferred order. If none of the negotiated_encodings have a corresponding precompressed
 // file the uncompressed file is used as a fallback.
-async fn open_file_with_fallback(
+async fn open_file_with_fallback<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
     mut path: PathBuf,
     mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(File, Option<Encoding>)> {
-    let (file, encoding) = loop {
+) -> io::Result<(FS::File, FileMetadata, Option<Encoding>)> {
+    let (file, meta, encoding) = loop {
         // Get the preferred encoding among the negotiated ones.
         let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (File::open(&path).await, encoding) {
-            (Ok(file), maybe_encoding) => break (file, maybe_encoding),
+        let opened = match fs.open(&path).await {
+            Ok((file, meta)) => check_symlinks(fs, settings, &path, Some(&meta))
+                .await
+                .map(|()| (file, meta)),
+            Err(err) => Err(err),
+        };
+        match (opened, encoding) {
+            (Ok((file, meta)), maybe_encoding) => break (file, meta, maybe_encoding),
             (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
                 // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
                 // to reset the path before the next iteration.
@@ -220,20 +612,26 @@ async fn open_file_with_fallback(
             (Err(err), _) => return Err(err),
         };
     };
-    Ok((file, encoding))
+    Ok((file, meta, encoding))
 }
 
 // Attempts to get the file metadata with any of the possible negotiated_encodings in the
 // preferred order. If none of the negotiated_encodings have a corresponding precompressed
 // file the uncompressed file is used as a fallback.
-async fn file_metadata_with_fallback(
+async fn file_metadata_with_fallback<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
     mut path: PathBuf,
     mut negotiated_encoding: Vec<(Encoding, QValue)>,
-) -> io::Result<(Metadata, Option<Encoding>)> {
+) -> io::Result<(FileMetadata, Option<Encoding>)> {
     let (file, encoding) = loop {
         // Get the preferred encoding among the negotiated ones.
         let encoding = preferred_encoding(&mut path, &negotiated_encoding);
-        match (tokio::fs::metadata(&path).await, encoding) {
+        let metadata = match check_symlinks(fs, settings, &path, None).await {
+            Ok(()) => fs.metadata(&path).await,
+            Err(err) => Err(err),
+        };
+        match (metadata, encoding) {
             (Ok(file), maybe_encoding) => break (file, maybe_encoding),
             (Err(err), Some(encoding)) if err.kind() == io::ErrorKind::NotFound => {
                 // Remove the extension corresponding to a precompressed file (.gz, .br, .zz)
@@ -250,43 +648,119 @@ async fn file_metadata_with_fallback(
     Ok((file, encoding))
 }
 
-async fn maybe_redirect_or_append_path(
+async fn maybe_redirect_or_append_path<FS: FileSystem>(
+    fs: &FS,
+    settings: &OpenFileSettings,
     path_to_file: &mut PathBuf,
-    uri: &Uri,
-    append_index_html_on_directories: bool,
-) -> Option<OpenFileOutput> {
-    if !is_dir(path_to_file).await {
-        return None;
+    req: &Request<Empty<Bytes>>,
+) -> io::Result<Option<OpenFileOutput>> {
+    let (
+        append_index_html_on_directories,
+        index_files,
+        trailing_slash_redirect,
+        clean_urls,
+        directory_listing,
+    ) = match &settings.variant {
+        ServeVariant::Directory {
+            append_index_html_on_directories,
+            index_files,
+            trailing_slash_redirect,
+            clean_urls,
+            directory_listing,
+        } => (
+            *append_index_html_on_directories,
+            index_files,
+            *trailing_slash_redirect,
+            *clean_urls,
+            *directory_listing,
+        ),
+        ServeVariant::SingleFile { .. } => return Ok(None),
+    };
+
+    match fs.metadata(path_to_file).await {
+        Ok(meta) if meta.is_dir() => {}
+        Ok(_) => return Ok(None),
+        Err(_) => {
+            // serve `/about` from `about.html`
+            if clean_urls {
+                let mut html = path_to_file.clone().into_os_string();
+                html.push(".html");
+                let html = PathBuf::from(html);
+                if fs
+                    .metadata(&html)
+                    .await
+                    .map_or(false, |meta| !meta.is_dir())
+                {
+                    *path_to_file = html;
+                }
+            }
+            return Ok(None);
+        }
     }
 
-    if !append_index_html_on_directories {
-        return Some(OpenFileOutput::FileNotFound);
+    if !append_index_html_on_directories && !directory_listing {
+        return Ok(Some(OpenFileOutput::FileNotFound));
     }
 
-    if uri.path().ends_with('/') {
-        path_to_file.push("index.html");
-        None
-    } else {
+    let uri = req.uri();
+    if let Some(status) = trailing_slash_redirect.filter(|_| !uri.path().ends_with('/')) {
         let location =
             HeaderValue::from_str(&append_slash_on_path(uri.clone()).to_string()).unwrap();
-        Some(OpenFileOutput::Redirect { location })
+        return Ok(Some(OpenFileOutput::Redirect { location, status }));
     }
-}
 
-fn try_parse_range(
-    maybe_range_ref: Option<&str>,
-    file_size: u64,
-) -> Option<Result<Vec<RangeInclusive<u64>>, RangeUnsatisfiableError>> {
-    maybe_range_ref.map(|header_value| {
-        http_range_header::parse_range_header(header_value)
-            .and_then(|first_pass| first_pass.validate(file_size))
-    })
-}
+    if append_index_html_on_directories {
+        let mut first_index = None;
+        for name in index_files.iter() {
+            let index = path_to_file.join(name);
+            if fs
+                .metadata(&index)
+                .await
+                .map_or(false, |meta| !meta.is_dir())
+            {
+                *path_to_file = index;
+                return Ok(None);
+            }
+            first_index.get_or_insert(index);
+        }
+
+        // without a listing to fall back to a missing index document is handled like any other
+        // missing file
+        if !directory_listing {
+            return Ok(match first_index {
+                Some(index) => {
+                    *path_to_file = index;
+                    None
+                }
+                None => Some(OpenFileOutput::FileNotFound),
+            });
+        }
+    }
 
-async fn is_dir(path_to_file: &Path) -> bool {
-    tokio::fs::metadata(path_to_file)
-        .await
-        .map_or(false, |meta_data| meta_data.is_dir())
+    // hide the entries that can't be requested
+    let dir = relative_path(&settings.base, path_to_file);
+    let is_visible = |entry: &DirEntry| {
+        let path = if dir.is_empty() {
+            entry.name().to_owned()
+        } else {
+            format!("{}/{}", dir, entry.name())
+        };
+        settings
+            .access_policy
+            .check(&path, !entry.metadata().is_dir())
+            == Access::Allowed
+    };
+
+    match directory_listing::render(fs, path_to_file, req, is_visible).await {
+        Ok(listing) => Ok(Some(OpenFileOutput::DirectoryListing {
+            listing,
+            is_head: req.method() == Method::HEAD,
+        })),
+        Err(err) if err.kind() == io::ErrorKind::Unsupported => {
+            Ok(Some(OpenFileOutput::FileNotFound))
+        }
+        Err(err) => Err(err),
+    }
 }
 
 fn append_slash_on_path(uri: Uri) -> Uri {
@@ -1,16 +1,24 @@
+use crate::services::fs::{
+    Attachment, CachePolicy, CompressionCache, DotFiles, ErrorPages, FileMetadata, FileSystem,
+    FileSystemFuture, Symlinks,
+};
 use crate::services::{ServeDir, ServeFile};
 use crate::test_helpers::{to_bytes, Body};
 use brotli::BrotliDecompress;
 use bytes::Bytes;
 use flate2::bufread::{DeflateDecoder, GzDecoder};
 use http::header::ALLOW;
-use http::{header, Method, Response};
+use http::{header, HeaderValue, Method, Response};
 use http::{Request, StatusCode};
 use http_body::Body as HttpBody;
 use http_body_util::BodyExt;
+use std::collections::HashMap;
 use std::convert::Infallible;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 use tower::{service_fn, ServiceExt};
 
 #[tokio::test]
@@ -815,3 +823,1324 @@ async fn calls_fallback_on_invalid_paths() {
 
     assert_eq!(res.headers()["from-fallback"], "1");
 }
+
+#[tokio::test]
+async fn etag() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let etag = res
+        .headers()
+        .get(header::ETAG)
+        .expect("Missing etag header!")
+        .clone();
+    assert!(etag.to_str().unwrap().starts_with('"'));
+
+    // -- If-None-Match
+
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, &etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()[header::ETAG], etag);
+    assert!(res.into_body().frame().await.is_none());
+
+    // weak comparison is used for `If-None-Match`
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(
+            header::IF_NONE_MATCH,
+            format!("\"other\", W/{}", etag.to_str().unwrap()),
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, "\"other\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, "*")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // -- If-Match
+
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MATCH, &etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // strong comparison is used for `If-Match`
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MATCH, format!("W/{}", etag.to_str().unwrap()))
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MATCH, "\"other\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    assert!(res.into_body().frame().await.is_none());
+}
+
+#[tokio::test]
+async fn etag_takes_precedence_over_dates() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let etag = res.headers()[header::ETAG].clone();
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+
+    // `If-None-Match` doesn't match so `If-Modified-Since` must be ignored
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_NONE_MATCH, "\"other\"")
+        .header(header::IF_MODIFIED_SINCE, &last_modified)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // `If-Match` matches so `If-Unmodified-Since` must be ignored
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header(header::IF_MATCH, &etag)
+        .header(header::IF_UNMODIFIED_SINCE, "Fri, 09 Aug 1996 14:21:40 GMT")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn etag_differs_between_precompressed_variants() {
+    let svc = ServeDir::new("../test-files").precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let identity_etag = res.headers()[header::ETAG].clone();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .method(Method::HEAD)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let gzip_etag = res.headers()[header::ETAG].clone();
+
+    assert_ne!(identity_etag, gzip_etag);
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header("Accept-Encoding", "gzip")
+        .header(header::IF_NONE_MATCH, &identity_etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn if_range() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let etag = res.headers()[header::ETAG].clone();
+    let last_modified = res.headers()[header::LAST_MODIFIED].clone();
+    let file_contents = std::fs::read("../README.md").unwrap();
+
+    for validator in [etag.clone(), last_modified] {
+        let svc = ServeDir::new("..");
+        let req = Request::builder()
+            .uri("/README.md")
+            .header(header::RANGE, "bytes=0-9")
+            .header(header::IF_RANGE, validator)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, &file_contents[..10]);
+    }
+
+    for validator in [
+        "\"other\"".to_owned(),
+        format!("W/{}", etag.to_str().unwrap()),
+        "Fri, 09 Aug 1996 14:21:40 GMT".to_owned(),
+        "garbage".to_owned(),
+    ] {
+        let svc = ServeDir::new("..");
+        let req = Request::builder()
+            .uri("/README.md")
+            .header(header::RANGE, "bytes=0-9")
+            .header(header::IF_RANGE, validator)
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        assert!(res.headers().get(header::CONTENT_RANGE).is_none());
+        let body = to_bytes(res.into_body()).await.unwrap();
+        assert_eq!(body, file_contents);
+    }
+}
+
+#[tokio::test]
+async fn read_partial_multiple_ranges() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .header("Range", "bytes=0-9, 20-29, -5")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = res.headers()["content-type"].to_str().unwrap().to_owned();
+    let boundary = content_type
+        .strip_prefix("multipart/byteranges; boundary=")
+        .expect("not a multipart/byteranges response");
+    let content_length: usize = res.headers()["content-length"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let body = to_bytes(res.into_body()).await.unwrap();
+    assert_eq!(body.len(), content_length);
+
+    let file_contents = std::fs::read("../README.md").unwrap();
+    let len = file_contents.len();
+    let mut expected = Vec::new();
+    for (index, (start, end)) in [(0, 9), (20, 29), (len - 5, len - 1)].iter().enumerate() {
+        if index > 0 {
+            expected.extend_from_slice(b"\r\n");
+        }
+        expected.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: text/markdown\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary, start, end, len
+            )
+            .as_bytes(),
+        );
+        expected.extend_from_slice(&file_contents[*start..=*end]);
+    }
+    expected.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+    assert_eq!(body, expected);
+}
+
+#[tokio::test]
+async fn read_partial_multiple_ranges_head_request() {
+    let svc = ServeDir::new("..");
+    let req = Request::builder()
+        .uri("/README.md")
+        .method(Method::HEAD)
+        .header("Range", "bytes=0-9, 20-29")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(res.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("multipart/byteranges; boundary="));
+    assert!(res.headers().contains_key("content-length"));
+    assert!(res.into_body().frame().await.is_none());
+}
+
+#[tokio::test]
+async fn read_partial_too_many_ranges() {
+    let svc = ServeDir::new("..").max_ranges(2);
+    let req = Request::builder()
+        .uri("/README.md")
+        .header("Range", "bytes=0-9, 20-29, 40-49")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    let file_contents = std::fs::read("../README.md").unwrap();
+    assert_eq!(
+        res.headers()["content-range"],
+        &format!("bytes */{}", file_contents.len())
+    );
+    assert!(res.into_body().frame().await.is_none());
+}
+
+#[derive(Clone)]
+struct InMemoryFileSystem {
+    files: Arc<HashMap<&'static str, &'static [u8]>>,
+    modified: SystemTime,
+}
+
+impl InMemoryFileSystem {
+    fn new(files: &[(&'static str, &'static [u8])]) -> Self {
+        Self {
+            files: Arc::new(files.iter().copied().collect()),
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000),
+        }
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<FileMetadata> {
+        let path = path.to_str().unwrap().trim_start_matches("./");
+        if path.is_empty() || path == "." {
+            Ok(FileMetadata::directory())
+        } else if let Some(contents) = self.files.get(path) {
+            Ok(FileMetadata::file(contents.len() as u64).with_modified(self.modified))
+        } else if self
+            .files
+            .keys()
+            .any(|file| file.starts_with(&format!("{}/", path.trim_end_matches('/'))))
+        {
+            Ok(FileMetadata::directory())
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+}
+
+impl FileSystem for InMemoryFileSystem {
+    type File = Cursor<&'static [u8]>;
+
+    fn open<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+        Box::pin(async move {
+            let meta = self.lookup(path)?;
+            let key = path.to_str().unwrap().trim_start_matches("./");
+            let contents = self.files.get(key).ok_or(io::ErrorKind::NotFound)?;
+            Ok((Cursor::new(*contents), meta))
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+        Box::pin(async move { self.lookup(path) })
+    }
+}
+
+#[tokio::test]
+async fn custom_file_system() {
+    let fs = InMemoryFileSystem::new(&[
+        ("hello.txt", b"Hello, World!"),
+        ("hello.txt.gz", b"not really gzip"),
+        ("dir/index.html", b"<b>index</b>"),
+    ]);
+    let svc = ServeDir::new("").file_system(fs).precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["content-length"], "13");
+    assert_eq!(
+        res.headers()["last-modified"],
+        "Mon, 12 Jan 1970 13:46:40 GMT"
+    );
+    let etag = res.headers()[header::ETAG].clone();
+    assert_eq!(body_into_text(res.into_body()).await, "Hello, World!");
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .header("Range", "bytes=7-11")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_into_text(res.into_body()).await, "World");
+
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(body_into_text(res.into_body()).await, "not really gzip");
+
+    let req = Request::builder().uri("/dir").body(Body::empty()).unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "/dir/");
+
+    let req = Request::builder().uri("/dir/").body(Body::empty()).unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "<b>index</b>");
+
+    let req = Request::builder()
+        .uri("/missing.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn directory_listing() {
+    let svc = ServeDir::new("../test-files")
+        .append_index_html_on_directories(false)
+        .directory_listing(true);
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains("<title>Index of /</title>"));
+    assert!(body.contains("<a href=\"%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt\">你好世界.txt</a>"));
+    assert!(body.contains("<a href=\"filename%20with%20space.txt\">filename with space.txt</a>"));
+    assert!(!body.contains("href=\"../\""));
+
+    let precompressed = body.find("\"precompressed.txt\"").unwrap();
+    let precompressed_br = body.find("\"precompressed_br.txt\"").unwrap();
+    assert!(precompressed < precompressed_br);
+
+    let req = Request::builder()
+        .uri("/?sort=name&order=desc")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    let precompressed = body.find("\"precompressed.txt\"").unwrap();
+    let precompressed_br = body.find("\"precompressed_br.txt\"").unwrap();
+    assert!(precompressed > precompressed_br);
+    assert!(body.contains("<a href=\"?sort=name&amp;order=asc\">Name</a>"));
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers()["content-length"] != "0");
+    assert!(res.into_body().frame().await.is_none());
+}
+
+#[tokio::test]
+async fn directory_listing_json() {
+    let svc = ServeDir::new("..")
+        .append_index_html_on_directories(false)
+        .directory_listing(true);
+
+    let req = Request::builder()
+        .uri("/test-files/?sort=size")
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/json");
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.starts_with("{\"path\":\"/test-files/\",\"entries\":[{\"name\":\""));
+    assert!(body.contains("{\"name\":\"你好世界.txt\",\"type\":\"file\",\"size\":"));
+
+    // the empty files are the smallest
+    let empty = body.find("\"filename with space.txt\"").unwrap();
+    let index = body.find("\"index.html\"").unwrap();
+    assert!(empty < index);
+}
+
+#[tokio::test]
+async fn directory_listing_redirects_and_prefers_index_html() {
+    let svc = ServeDir::new("..").directory_listing(true);
+
+    let req = Request::builder()
+        .uri("/test-files")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(res.headers()[header::LOCATION], "/test-files/");
+
+    let req = Request::builder()
+        .uri("/test-files/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    assert_eq!(body, "<b>HTML!</b>\n");
+
+    // no `index.html` in the parent directory
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains("<a href=\"test-files/\">test-files/</a>"));
+    assert!(!body.contains("<a href=\"../\">../</a>"));
+}
+
+#[tokio::test]
+async fn directory_listing_disabled_by_default() {
+    let svc = ServeDir::new("../test-files").append_index_html_on_directories(false);
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn directory_listing_unsupported_by_file_system() {
+    let fs = InMemoryFileSystem::new(&[("dir/hello.txt", b"Hello, World!")]);
+    let svc = ServeDir::new("").file_system(fs).directory_listing(true);
+
+    let req = Request::builder().uri("/dir/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn single_page_app() {
+    let svc = ServeDir::new("../test-files").single_page_app("index.html");
+
+    let req = Request::builder()
+        .uri("/users/42")
+        .header(
+            header::ACCEPT,
+            "text/html,application/xhtml+xml;q=0.9,*/*;q=0.8",
+        )
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html");
+    assert_eq!(res.headers()["cache-control"], "no-cache");
+    let etag = res.headers()[header::ETAG].clone();
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    let req = Request::builder()
+        .uri("/users/42")
+        .header(header::ACCEPT, "text/html")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()["cache-control"], "no-cache");
+
+    // existing files are served as usual
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert!(res.headers().get(header::CACHE_CONTROL).is_none());
+
+    // missing assets are not found
+    let req = Request::builder()
+        .uri("/assets/app.js")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // as are requests that don't accept html
+    let req = Request::builder()
+        .uri("/users/42")
+        .header(header::ACCEPT, "application/json")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn single_page_app_calls_fallback_for_missing_assets() {
+    let svc = ServeDir::new("../test-files")
+        .single_page_app("index.html")
+        .fallback(ServeFile::new("../README.md"));
+
+    let req = Request::builder()
+        .uri("/assets/app.css")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/markdown");
+
+    let req = Request::builder()
+        .uri("/users/42/")
+        .header(header::ACCEPT, "text/html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html");
+}
+
+#[tokio::test]
+async fn cache_policy() {
+    let fs = InMemoryFileSystem::new(&[
+        ("index.html", b"<b>index</b>"),
+        ("docs/index.html", b"<b>docs</b>"),
+        ("assets/app.3f9a1c.js", b"app"),
+        ("assets/app.js", b"app"),
+        ("assets/logo.png", b"png"),
+        ("downloads/report.3f9a1c.pdf", b"pdf"),
+    ]);
+    let policy = CachePolicy::new()
+        .path("/downloads/**", HeaderValue::from_static("no-store"))
+        .extension("png", HeaderValue::from_static("max-age=600"));
+    let svc = ServeDir::new("").file_system(fs).cache_policy(policy);
+
+    for (uri, expected) in [
+        ("/", Some("no-cache")),
+        ("/docs/", Some("no-cache")),
+        (
+            "/assets/app.3f9a1c.js",
+            Some("public, max-age=31536000, immutable"),
+        ),
+        ("/assets/app.js", None),
+        ("/assets/logo.png", Some("max-age=600")),
+        ("/downloads/report.3f9a1c.pdf", Some("no-store")),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(
+            res.headers()
+                .get(header::CACHE_CONTROL)
+                .map(|value| value.to_str().unwrap()),
+            expected,
+            "{}",
+            uri
+        );
+    }
+
+    let req = Request::builder()
+        .uri("/assets/app.3f9a1c.js")
+        .header(header::IF_MODIFIED_SINCE, "Mon, 12 Jan 1970 13:46:40 GMT")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(
+        res.headers()[header::CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+}
+
+#[tokio::test]
+async fn no_cache_control_by_default() {
+    let svc = ServeDir::new("../test-files");
+
+    let req = Request::builder().uri("/").body(Body::empty()).unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CACHE_CONTROL).is_none());
+}
+
+#[tokio::test]
+async fn dot_files() {
+    let svc = ServeDir::new("..");
+
+    let req = Request::builder()
+        .uri("/.gitignore")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    for (dot_files, expected) in [
+        (DotFiles::NotFound, StatusCode::NOT_FOUND),
+        (DotFiles::Forbidden, StatusCode::FORBIDDEN),
+    ] {
+        let svc = svc.clone().dot_files(dot_files);
+
+        for uri in ["/.gitignore", "/.github/", "/.github/workflows/CI.yml"] {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            let res = svc.clone().oneshot(req).await.unwrap();
+            assert_eq!(res.status(), expected, "{}", uri);
+        }
+
+        let req = Request::builder()
+            .uri("/README.md")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn allow_and_deny_paths() {
+    let svc = ServeDir::new("..")
+        .allow_path("*.md")
+        .allow_path("/test-files/**")
+        .deny_path("/test-files/precompressed*")
+        .directory_listing(true)
+        .append_index_html_on_directories(false);
+
+    for (uri, expected) in [
+        ("/README.md", StatusCode::OK),
+        ("/tower-http/CHANGELOG.md", StatusCode::OK),
+        ("/test-files/index.html", StatusCode::OK),
+        ("/Cargo.toml", StatusCode::NOT_FOUND),
+        ("/test-files/precompressed.txt", StatusCode::NOT_FOUND),
+        ("/test-files/", StatusCode::OK),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), expected, "{}", uri);
+    }
+
+    // denied files are hidden from listings
+    let req = Request::builder()
+        .uri("/test-files/")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = body_into_text(res.into_body()).await;
+    assert!(body.contains("\"missing_precompressed.txt\""));
+    assert!(!body.contains("\"precompressed.txt\""));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn symlinks() {
+    let dir = std::env::temp_dir().join(format!("tower-http-symlinks-{}", uuid::Uuid::new_v4()));
+    let root = dir.join("root");
+    fs::create_dir_all(root.join("sub")).unwrap();
+    fs::write(root.join("file.txt"), "inside").unwrap();
+    fs::write(dir.join("outside.txt"), "outside").unwrap();
+    std::os::unix::fs::symlink(root.join("file.txt"), root.join("inside_link.txt")).unwrap();
+    std::os::unix::fs::symlink(dir.join("outside.txt"), root.join("outside_link.txt")).unwrap();
+    std::os::unix::fs::symlink(root.join("sub"), root.join("sub_link")).unwrap();
+    fs::write(root.join("sub/nested.txt"), "nested").unwrap();
+
+    let get = |svc: ServeDir, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap().status()
+    };
+
+    for (symlinks, inside, outside) in [
+        (Symlinks::Follow, StatusCode::OK, StatusCode::OK),
+        (
+            Symlinks::FollowWithinRoot,
+            StatusCode::OK,
+            StatusCode::NOT_FOUND,
+        ),
+        (
+            Symlinks::Never,
+            StatusCode::NOT_FOUND,
+            StatusCode::NOT_FOUND,
+        ),
+    ] {
+        let svc = ServeDir::new(&root).symlinks(symlinks);
+        assert_eq!(get(svc.clone(), "/file.txt").await, StatusCode::OK);
+        assert_eq!(get(svc.clone(), "/sub/nested.txt").await, StatusCode::OK);
+        assert_eq!(get(svc.clone(), "/inside_link.txt").await, inside);
+        assert_eq!(get(svc.clone(), "/sub_link/nested.txt").await, inside);
+        assert_eq!(get(svc.clone(), "/outside_link.txt").await, outside);
+    }
+
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[tokio::test]
+async fn mime_types() {
+    let svc = ServeDir::new("../test-files")
+        .mime_override("TXT", &mime::TEXT_CSV)
+        .extensionless_mime(&mime::TEXT_PLAIN)
+        .precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "text/csv");
+
+    let req = Request::builder()
+        .uri("/extensionless_precompressed")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+
+    let svc = svc.charset_utf8(true);
+    for (uri, expected) in [
+        ("/precompressed.txt", "text/csv; charset=utf-8"),
+        ("/extensionless_precompressed", "text/plain; charset=utf-8"),
+        ("/index.html", "text/html; charset=utf-8"),
+    ] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["content-type"], expected, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn extensionless_files_are_octet_streams_by_default() {
+    let svc = ServeDir::new("../test-files").charset_utf8(true);
+
+    let req = Request::builder()
+        .uri("/extensionless_precompressed")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-type"], "application/octet-stream");
+}
+
+#[tokio::test]
+async fn attachments() {
+    let svc = ServeDir::new("../test-files")
+        .download_query_param("download")
+        .precompressed_gzip();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert!(res.headers().get(header::CONTENT_DISPOSITION).is_none());
+
+    // the name of the uncompressed file is used for precompressed files
+    let req = Request::builder()
+        .uri("/precompressed.txt?download")
+        .header("Accept-Encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(
+        res.headers()[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"precompressed.txt\""
+    );
+
+    let req = Request::builder()
+        .uri("/precompressed.txt?foo=bar&download=%E4%BD%A0%E5%A5%BD.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(
+        res.headers()[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"__.txt\"; filename*=UTF-8''%E4%BD%A0%E5%A5%BD.txt"
+    );
+
+    // a request extension takes precedence over the query
+    let mut req = Request::builder()
+        .uri("/filename%20with%20space.txt?download=other.txt")
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut().insert(Attachment::new());
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(
+        res.headers()[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"filename with space.txt\""
+    );
+
+    let svc = svc.attachment(Attachment::new());
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(
+        res.headers()[header::CONTENT_DISPOSITION],
+        "attachment; filename=\"index.html\""
+    );
+}
+
+#[cfg(feature = "compression-gzip")]
+#[tokio::test]
+async fn compression_cache() {
+    let svc = ServeDir::new("../test-files").compression_cache(CompressionCache::in_memory(1024));
+    let get = |svc: ServeDir, accept_encoding: &'static str, range: Option<&'static str>| async move {
+        let mut req = Request::builder()
+            .uri("/precompressed.txt")
+            .header(header::ACCEPT_ENCODING, accept_encoding);
+        if let Some(range) = range {
+            req = req.header(header::RANGE, range);
+        }
+        svc.oneshot(req.body(Body::empty()).unwrap()).await.unwrap()
+    };
+
+    let res = get(svc.clone(), "gzip", None).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let content_length = res.headers()["content-length"].clone();
+    let etag = res.headers()["etag"].clone();
+    let compressed = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(content_length, compressed.len().to_string().as_str());
+    let mut decompressed = String::new();
+    GzDecoder::new(&compressed[..])
+        .read_to_string(&mut decompressed)
+        .unwrap();
+    assert_eq!(
+        decompressed,
+        fs::read_to_string("../test-files/precompressed.txt").unwrap()
+    );
+
+    // served from the cache, with ranges applying to the compressed file
+    let res = get(svc.clone(), "gzip", Some("bytes=0-9")).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(
+        res.headers()["content-range"],
+        format!("bytes 0-9/{}", compressed.len()).as_str()
+    );
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(body, compressed.slice(0..10));
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/precompressed.txt")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .header(header::IF_NONE_MATCH, etag)
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    let res = get(svc.clone(), "identity", None).await;
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers()["content-length"], "23");
+}
+
+#[cfg(feature = "compression-gzip")]
+#[tokio::test]
+async fn compression_cache_head_requests() {
+    let svc = ServeDir::new("../test-files").compression_cache(CompressionCache::in_memory(1024));
+    let request = |method: Method| {
+        Request::builder()
+            .method(method)
+            .uri("/precompressed.txt")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    // not compressed for `HEAD` requests
+    let res = svc.clone().oneshot(request(Method::HEAD)).await.unwrap();
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers()["content-length"], "23");
+
+    let res = svc.clone().oneshot(request(Method::GET)).await.unwrap();
+    let content_length = res.headers()["content-length"].clone();
+    let etag = res.headers()["etag"].clone();
+
+    // but served compressed once cached
+    let res = svc.clone().oneshot(request(Method::HEAD)).await.unwrap();
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_eq!(res.headers()["content-length"], content_length);
+    assert_eq!(res.headers()["etag"], etag);
+}
+
+#[cfg(feature = "compression-gzip")]
+#[tokio::test]
+async fn compression_cache_prefers_precompressed_files() {
+    let svc = ServeDir::new("../test-files")
+        .precompressed_gzip()
+        .compression_cache(CompressionCache::in_memory(1024).br(false));
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::ACCEPT_ENCODING, "gzip, br")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let body = res.into_body().collect().await.unwrap().to_bytes();
+    assert_eq!(
+        body,
+        fs::read("../test-files/precompressed.txt.gz").unwrap()
+    );
+}
+
+#[cfg(feature = "compression-gzip")]
+#[tokio::test]
+async fn compression_cache_in_directory() {
+    let dir = std::env::temp_dir().join(format!(
+        "tower-http-compression-cache-{}",
+        uuid::Uuid::new_v4()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let svc = ServeDir::new("../test-files")
+        .compression_cache(CompressionCache::in_directory(&dir, 1024));
+
+    let mut bodies = Vec::new();
+    for _ in 0..2 {
+        let req = Request::builder()
+            .uri("/precompressed.txt")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.clone().oneshot(req).await.unwrap();
+        assert_eq!(res.headers()["content-encoding"], "gzip");
+        bodies.push(res.into_body().collect().await.unwrap().to_bytes());
+    }
+    assert_eq!(bodies[0], bodies[1]);
+
+    let cached: Vec<_> = fs::read_dir(&dir).unwrap().collect();
+    assert_eq!(cached.len(), 1);
+    assert_eq!(
+        fs::read(cached[0].as_ref().unwrap().path()).unwrap(),
+        bodies[0]
+    );
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn index_files() {
+    let fs = InMemoryFileSystem::new(&[
+        ("htm/index.htm", b"htm"),
+        ("both/index.htm", b"htm"),
+        ("both/default.html", b"default"),
+        ("none/file.txt", b"file"),
+    ]);
+    let svc =
+        ServeDir::new("")
+            .file_system(fs)
+            .index_files(["index.html", "index.htm", "default.html"]);
+    let get = |svc: ServeDir<_, _>, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap()
+    };
+
+    let res = get(svc.clone(), "/htm/").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html");
+    assert_eq!(body_into_text(res.into_body()).await, "htm");
+
+    let res = get(svc.clone(), "/both/").await;
+    assert_eq!(body_into_text(res.into_body()).await, "htm");
+
+    let res = get(svc, "/none/").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn trailing_slash_redirect() {
+    let get = |svc: ServeDir, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap()
+    };
+    let svc = ServeDir::new(".");
+
+    let res = get(
+        svc.clone()
+            .trailing_slash_redirect(Some(StatusCode::PERMANENT_REDIRECT)),
+        "/src?foo=bar",
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(res.headers()["location"], "/src/?foo=bar");
+
+    let svc = ServeDir::new("..").trailing_slash_redirect(None);
+    let res = get(svc, "/test-files").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+}
+
+#[test]
+#[should_panic(expected = "not a redirection status code")]
+fn trailing_slash_redirect_requires_redirection_status() {
+    let _ = ServeDir::new(".").trailing_slash_redirect(Some(StatusCode::OK));
+}
+
+#[tokio::test]
+async fn clean_urls() {
+    let fs = InMemoryFileSystem::new(&[
+        ("about.html", b"about"),
+        ("blog.html", b"blog page"),
+        ("blog/index.html", b"blog index"),
+        ("notes.txt", b"notes"),
+    ]);
+    let svc = ServeDir::new("").file_system(fs).clean_urls(true);
+    let get = |svc: ServeDir<_, _>, uri: &'static str| async move {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        svc.oneshot(req).await.unwrap()
+    };
+
+    let res = get(svc.clone(), "/about").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/html");
+    assert_eq!(body_into_text(res.into_body()).await, "about");
+
+    let res = get(svc.clone(), "/about.html").await;
+    assert_eq!(body_into_text(res.into_body()).await, "about");
+
+    // directories take precedence
+    let res = get(svc.clone(), "/blog/").await;
+    assert_eq!(body_into_text(res.into_body()).await, "blog index");
+
+    let res = get(svc.clone(), "/notes").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let res = get(svc.clean_urls(false), "/about").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn with_roots() {
+    let dir = std::env::temp_dir().join(format!("tower-http-roots-{}", uuid::Uuid::new_v4()));
+    let (tenant, theme) = (dir.join("tenant"), dir.join("theme"));
+    fs::create_dir_all(tenant.join("docs")).unwrap();
+    fs::create_dir_all(theme.join("docs")).unwrap();
+    fs::write(tenant.join("style.css"), "tenant").unwrap();
+    fs::write(theme.join("style.css"), "theme").unwrap();
+    fs::write(theme.join("style.css.gz"), "stale").unwrap();
+    fs::write(theme.join("logo.svg"), "<svg/>").unwrap();
+    fs::write(theme.join("docs/index.html"), "docs").unwrap();
+
+    let svc = ServeDir::with_roots([&tenant, &theme]).precompressed_gzip();
+    let get = |svc: ServeDir, uri: &'static str| async move {
+        let req = Request::builder()
+            .uri(uri)
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        svc.oneshot(req).await.unwrap()
+    };
+
+    // the precompressed variant of the overridden file isn't served
+    let res = get(svc.clone(), "/style.css").await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get(header::CONTENT_ENCODING).is_none());
+    assert_eq!(body_into_text(res.into_body()).await, "tenant");
+
+    let res = get(svc.clone(), "/logo.svg").await;
+    assert_eq!(res.headers()["content-type"], "image/svg+xml");
+    assert_eq!(body_into_text(res.into_body()).await, "<svg/>");
+
+    let res = get(svc.clone(), "/docs").await;
+    assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+    let res = get(svc.clone(), "/docs/").await;
+    assert_eq!(body_into_text(res.into_body()).await, "docs");
+
+    let res = get(svc.clone(), "/missing.txt").await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri("/logo.svg")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[should_panic(expected = "requires at least one root")]
+fn with_roots_requires_a_root() {
+    let _ = ServeDir::with_roots(Vec::<&str>::new());
+}
+
+#[tokio::test]
+async fn error_pages() {
+    let error_pages = ErrorPages::new()
+        .file(StatusCode::NOT_FOUND, "index.html")
+        .file(StatusCode::FORBIDDEN, "missing.html")
+        .render(|status, req| {
+            let body = format!("{} {}", status.as_u16(), req.uri().path());
+            Some((
+                HeaderValue::from_static("application/problem+json"),
+                Bytes::from(body),
+            ))
+        });
+    let svc = ServeDir::new("../test-files")
+        .dot_files(DotFiles::Forbidden)
+        .error_pages(error_pages);
+    let send = |svc: ServeDir, req: Request<Body>| async move { svc.oneshot(req).await.unwrap() };
+
+    let req = Request::builder()
+        .uri("/missing")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert_eq!(res.headers()["content-type"], "text/html");
+    assert_eq!(res.headers()["content-length"], "13");
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+
+    // the file can't be read so the page is rendered
+    let req = Request::builder().uri("/.env").body(Body::empty()).unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    assert_eq!(res.headers()["content-type"], "application/problem+json");
+    assert_eq!(body_into_text(res.into_body()).await, "403 /.env");
+
+    let req = Request::builder()
+        .method(Method::POST)
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(res.headers()[ALLOW], "GET,HEAD");
+    assert_eq!(body_into_text(res.into_body()).await, "405 /index.html");
+
+    let req = Request::builder()
+        .uri("/index.html")
+        .header(header::IF_MATCH, "\"nope\"")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(body_into_text(res.into_body()).await, "412 /index.html");
+
+    let req = Request::builder()
+        .method(Method::HEAD)
+        .uri("/index.html")
+        .header(header::RANGE, "bytes=100-200")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(res.headers()["content-range"], "bytes */13");
+    assert_eq!(res.headers()["content-length"], "15");
+    assert!(body_into_text(res.into_body()).await.is_empty());
+
+    // successful responses are untouched
+    let req = Request::builder()
+        .uri("/index.html")
+        .body(Body::empty())
+        .unwrap();
+    let res = send(svc.clone(), req).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(body_into_text(res.into_body()).await, "<b>HTML!</b>\n");
+}
+
+#[tokio::test]
+async fn error_pages_with_fallback() {
+    let svc = ServeDir::new("../test-files")
+        .error_pages(ErrorPages::new().file(StatusCode::NOT_FOUND, "index.html"))
+        .fallback(ServeFile::new("../README.md"));
+
+    let req = Request::builder()
+        .uri("/missing")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/markdown");
+}
+
+#[tokio::test]
+async fn file_body() {
+    let svc = ServeDir::new("../test-files");
+    let contents = fs::read("../test-files/precompressed.txt").unwrap();
+
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let body = res.into_body();
+    assert_eq!(body.size_hint().exact(), Some(23));
+    let (file, range) = body.into_file().unwrap().into_parts();
+    assert_eq!(range, 0..23);
+    let mut read = Vec::new();
+    file.into_std().await.read_to_end(&mut read).unwrap();
+    assert_eq!(read, contents);
+
+    // the file is positioned at the start of the range
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::RANGE, "bytes=2-5")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let (file, range) = res.into_body().into_file().unwrap().into_parts();
+    assert_eq!(range, 2..6);
+    let mut read = Vec::new();
+    file.into_std()
+        .await
+        .take(4)
+        .read_to_end(&mut read)
+        .unwrap();
+    assert_eq!(read, contents[2..6]);
+
+    // polling the body reads the file
+    let req = Request::builder()
+        .uri("/precompressed.txt")
+        .header(header::RANGE, "bytes=2-5")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.clone().oneshot(req).await.unwrap();
+    let mut body = res.into_body();
+    assert!(body.as_file().is_some());
+    let chunk = body.frame().await.unwrap().unwrap().into_data().unwrap();
+    assert_eq!(chunk, contents[2..6]);
+    assert!(body.as_file().is_none());
+    assert!(body.into_file().is_err());
+
+    // only files on disk can be taken
+    let svc =
+        ServeDir::new("").file_system(InMemoryFileSystem::new(&[("hello.txt", b"Hello, World!")]));
+    let req = Request::builder()
+        .uri("/hello.txt")
+        .body(Body::empty())
+        .unwrap();
+    let res = svc.oneshot(req).await.unwrap();
+    let body = res.into_body().into_file().unwrap_err();
+    assert_eq!(body_into_text(body).await, "Hello, World!");
+}
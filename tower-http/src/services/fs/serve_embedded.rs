@@ -0,0 +1,632 @@
+//! Service that serves files embedded in the binary.
+
+use super::{
+    file_system::DirEntry,
+    serve_dir::{future::ResponseFuture, InfallibleResponseFuture, ResponseBody},
+    Attachment, CachePolicy, CompressionCache, DefaultServeDirFallback, DotFiles, ErrorPages,
+    FileMetadata, FileSystem, FileSystemFuture, ServeDir,
+};
+use bytes::Bytes;
+use http::{Request, Response, StatusCode};
+use mime::Mime;
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    fmt,
+    io::{self, Cursor},
+    path::{Component, Path},
+    sync::Arc,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+use tower_service::Service;
+
+/// Service that serves files embedded in the binary, for example with [`include_bytes!`].
+///
+/// Files are looked up in a static table of paths and their contents, and are otherwise served
+/// exactly like [`ServeDir`] serves files from disk: the `Content-Type` is guessed from the file
+/// extension, range and conditional requests are supported, precompressed variants can be
+/// served and requests for directories are redirected or answered with their `index.html`.
+///
+/// Paths in the table are relative to the root of the served directory and use `/` as the
+/// separator. Directories are implied by the paths of the files they contain.
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::ServeEmbedded;
+/// use std::time::{Duration, SystemTime};
+///
+/// static ASSETS: &[(&str, &[u8])] = &[
+///     ("index.html", b"<h1>Hello, World!</h1>"),
+///     ("assets/app.js", b"console.log('Hello, World!');"),
+///     // a precompressed variant, only served if `precompressed_gzip` is enabled
+///     // ("assets/app.js.gz", include_bytes!("../dist/assets/app.js.gz")),
+/// ];
+///
+/// let service = ServeEmbedded::new(ASSETS)
+///     .precompressed_gzip()
+///     // the time the assets were built, used for `Last-Modified` and `ETag`
+///     .last_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServeEmbedded<F = DefaultServeDirFallback>(ServeDir<F, EmbeddedFileSystem>);
+
+impl ServeEmbedded<DefaultServeDirFallback> {
+    /// Create a new [`ServeEmbedded`] serving the files in `files`.
+    pub fn new(files: &'static [(&'static str, &'static [u8])]) -> Self {
+        Self(ServeDir::new("").file_system(EmbeddedFileSystem::new(files)))
+    }
+}
+
+impl<F> ServeEmbedded<F> {
+    /// Set the time the embedded files were last modified, usually the time the binary was
+    /// built.
+    ///
+    /// This is used for the `Last-Modified` and `ETag` headers, which aren't sent otherwise.
+    pub fn last_modified(mut self, modified: SystemTime) -> Self {
+        self.0.file_system_mut().modified = Some(modified);
+        self
+    }
+
+    /// If the requested path is a directory append `index.html`.
+    ///
+    /// See [`ServeDir::append_index_html_on_directories`] for more details.
+    pub fn append_index_html_on_directories(self, append: bool) -> Self {
+        Self(self.0.append_index_html_on_directories(append))
+    }
+
+    /// Set the names of the documents served for requests for directories, in order of
+    /// preference.
+    ///
+    /// See [`ServeDir::index_files`] for more details.
+    pub fn index_files<I>(self, names: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        Self(self.0.index_files(names))
+    }
+
+    /// Set the status code of the redirects from directories requested without a trailing
+    /// slash, or disable them with `None`.
+    ///
+    /// See [`ServeDir::trailing_slash_redirect`] for more details.
+    pub fn trailing_slash_redirect(self, status: Option<StatusCode>) -> Self {
+        Self(self.0.trailing_slash_redirect(status))
+    }
+
+    /// Serve requests for paths that don't exist from the file with the same path and an
+    /// `.html` extension.
+    ///
+    /// See [`ServeDir::clean_urls`] for more details.
+    pub fn clean_urls(self, enabled: bool) -> Self {
+        Self(self.0.clean_urls(enabled))
+    }
+
+    /// Render a listing of the entries of directories that have no `index.html`.
+    ///
+    /// See [`ServeDir::directory_listing`] for more details.
+    pub fn directory_listing(self, enabled: bool) -> Self {
+        Self(self.0.directory_listing(enabled))
+    }
+
+    /// Serve a single page application, answering navigations to paths that aren't embedded
+    /// with the document at `index`.
+    ///
+    /// See [`ServeDir::single_page_app`] for more details.
+    pub fn single_page_app(self, index: &str) -> Self {
+        Self(self.0.single_page_app(index))
+    }
+
+    /// Set the policy deciding the `Cache-Control` header sent for each file.
+    ///
+    /// See [`ServeDir::cache_policy`] for more details.
+    pub fn cache_policy(self, policy: CachePolicy) -> Self {
+        Self(self.0.cache_policy(policy))
+    }
+
+    /// Set how requests for paths with a component starting with `.` are handled.
+    ///
+    /// See [`ServeDir::dot_files`] for more details.
+    pub fn dot_files(self, dot_files: DotFiles) -> Self {
+        Self(self.0.dot_files(dot_files))
+    }
+
+    /// Only serve files whose path matches the glob `pattern`.
+    ///
+    /// See [`ServeDir::allow_path`] for more details.
+    pub fn allow_path(self, pattern: &str) -> Self {
+        Self(self.0.allow_path(pattern))
+    }
+
+    /// Never serve files or directories whose path matches the glob `pattern`.
+    ///
+    /// See [`ServeDir::deny_path`] for more details.
+    pub fn deny_path(self, pattern: &str) -> Self {
+        Self(self.0.deny_path(pattern))
+    }
+
+    /// Serve files with the extension `extension` with the `Content-Type` `mime`.
+    ///
+    /// See [`ServeDir::mime_override`] for more details.
+    pub fn mime_override(self, extension: &str, mime: &Mime) -> Self {
+        Self(self.0.mime_override(extension, mime))
+    }
+
+    /// Set the `Content-Type` of files without an extension.
+    ///
+    /// See [`ServeDir::extensionless_mime`] for more details.
+    pub fn extensionless_mime(self, mime: &Mime) -> Self {
+        Self(self.0.extensionless_mime(mime))
+    }
+
+    /// Append `; charset=utf-8` to the `Content-Type` of textual files.
+    ///
+    /// See [`ServeDir::charset_utf8`] for more details.
+    pub fn charset_utf8(self, enabled: bool) -> Self {
+        Self(self.0.charset_utf8(enabled))
+    }
+
+    /// Compress files on the fly and keep the compressed files in `cache`.
+    ///
+    /// See [`ServeDir::compression_cache`] for more details.
+    pub fn compression_cache(self, cache: CompressionCache) -> Self {
+        Self(self.0.compression_cache(cache))
+    }
+
+    /// Serve all files as downloads, with a `Content-Disposition: attachment` header.
+    ///
+    /// See [`ServeDir::attachment`] for more details.
+    pub fn attachment(self, attachment: Attachment) -> Self {
+        Self(self.0.attachment(attachment))
+    }
+
+    /// Serve files as downloads if the request has the query parameter `name`.
+    ///
+    /// See [`ServeDir::download_query_param`] for more details.
+    pub fn download_query_param(self, name: impl Into<String>) -> Self {
+        Self(self.0.download_query_param(name))
+    }
+
+    /// Send the error responses of the service with the bodies from `error_pages`.
+    ///
+    /// See [`ServeDir::error_pages`] for more details.
+    pub fn error_pages(self, error_pages: ErrorPages) -> Self {
+        Self(self.0.error_pages(error_pages))
+    }
+
+    /// Set a specific read buffer chunk size.
+    ///
+    /// The default capacity is 64kb.
+    pub fn with_buf_chunk_size(self, chunk_size: usize) -> Self {
+        Self(self.0.with_buf_chunk_size(chunk_size))
+    }
+
+    /// Set the maximum number of ranges served for a single request.
+    ///
+    /// See [`ServeDir::max_ranges`] for more details.
+    pub fn max_ranges(self, max_ranges: usize) -> Self {
+        Self(self.0.max_ranges(max_ranges))
+    }
+
+    /// Informs the service that it should also look for a precompressed gzip
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the gzip encoding will receive the embedded file `foo.txt.gz` instead of `foo.txt`.
+    /// If the precompressed file is not embedded, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_gzip(self) -> Self {
+        Self(self.0.precompressed_gzip())
+    }
+
+    /// Informs the service that it should also look for a precompressed brotli
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the brotli encoding will receive the embedded file `foo.txt.br` instead of `foo.txt`.
+    /// If the precompressed file is not embedded, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_br(self) -> Self {
+        Self(self.0.precompressed_br())
+    }
+
+    /// Informs the service that it should also look for a precompressed deflate
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the deflate encoding will receive the embedded file `foo.txt.zz` instead of `foo.txt`.
+    /// If the precompressed file is not embedded, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_deflate(self) -> Self {
+        Self(self.0.precompressed_deflate())
+    }
+
+    /// Informs the service that it should also look for a precompressed zstd
+    /// version of _any_ file.
+    ///
+    /// Assuming `foo.txt` is requested, a client with an `Accept-Encoding` header that allows
+    /// the zstd encoding will receive the embedded file `foo.txt.zst` instead of `foo.txt`.
+    /// If the precompressed file is not embedded, or the client doesn't support it,
+    /// the uncompressed version will be served instead.
+    pub fn precompressed_zstd(self) -> Self {
+        Self(self.0.precompressed_zstd())
+    }
+
+    /// Set the fallback service.
+    ///
+    /// See [`ServeDir::fallback`] for more details.
+    pub fn fallback<F2>(self, new_fallback: F2) -> ServeEmbedded<F2> {
+        ServeEmbedded(self.0.fallback(new_fallback))
+    }
+
+    /// Set the fallback service and override the fallback's status code to `404 Not Found`.
+    ///
+    /// See [`ServeDir::not_found_service`] for more details.
+    pub fn not_found_service<F2>(
+        self,
+        new_fallback: F2,
+    ) -> ServeEmbedded<crate::set_status::SetStatus<F2>> {
+        ServeEmbedded(self.0.not_found_service(new_fallback))
+    }
+
+    /// Customize whether or not to call the fallback for requests that aren't `GET` or `HEAD`.
+    ///
+    /// Defaults to not calling the fallback and instead returning `405 Method Not Allowed`.
+    pub fn call_fallback_on_method_not_allowed(self, call_fallback: bool) -> Self {
+        Self(self.0.call_fallback_on_method_not_allowed(call_fallback))
+    }
+
+    /// Call the service and get a future that contains any `std::io::Error` that might have
+    /// happened.
+    ///
+    /// See [`ServeDir::try_call`] for more details.
+    pub fn try_call<ReqBody, FResBody>(
+        &mut self,
+        req: Request<ReqBody>,
+    ) -> ResponseFuture<ReqBody, F>
+    where
+        F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
+        F::Future: Send + 'static,
+        FResBody: http_body::Body<Data = Bytes> + Send + 'static,
+        FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.0.try_call(req)
+    }
+}
+
+impl<ReqBody, F, FResBody> Service<Request<ReqBody>> for ServeEmbedded<F>
+where
+    F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
+    F::Future: Send + 'static,
+    FResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Future = InfallibleResponseFuture<ReqBody, F>;
+
+    #[inline]
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    #[inline]
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        self.0.call(req)
+    }
+}
+
+// A `FileSystem` backed by a static table of files.
+#[derive(Clone)]
+pub(crate) struct EmbeddedFileSystem {
+    files: Arc<HashMap<String, &'static [u8]>>,
+    directories: Arc<HashSet<String>>,
+    modified: Option<SystemTime>,
+}
+
+impl EmbeddedFileSystem {
+    fn new(table: &'static [(&'static str, &'static [u8])]) -> Self {
+        let mut files = HashMap::with_capacity(table.len());
+        let mut directories = HashSet::new();
+
+        for (path, contents) in table {
+            let path = normalize(Path::new(path));
+
+            let mut parent = path.as_str();
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                directories.insert(dir.to_owned());
+                parent = dir;
+            }
+            directories.insert(String::new());
+
+            files.insert(path, *contents);
+        }
+
+        Self {
+            files: Arc::new(files),
+            directories: Arc::new(directories),
+            modified: None,
+        }
+    }
+
+    fn lookup(&self, path: &Path) -> io::Result<(Option<&'static [u8]>, FileMetadata)> {
+        let path = normalize(path);
+
+        if let Some(contents) = self.files.get(&path) {
+            let mut metadata = FileMetadata::file(contents.len() as u64);
+            if let Some(modified) = self.modified {
+                metadata = metadata.with_modified(modified);
+            }
+            Ok((Some(*contents), metadata))
+        } else if self.directories.contains(&path) {
+            Ok((None, FileMetadata::directory()))
+        } else {
+            Err(io::ErrorKind::NotFound.into())
+        }
+    }
+}
+
+// Turn a path into the `/` separated form used as the key of the table.
+pub(super) fn normalize(path: &Path) -> String {
+    let mut normalized = String::new();
+    for component in path.components() {
+        if let Component::Normal(component) = component {
+            if !normalized.is_empty() {
+                normalized.push('/');
+            }
+            normalized.push_str(&component.to_string_lossy());
+        }
+    }
+    normalized
+}
+
+impl fmt::Debug for EmbeddedFileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EmbeddedFileSystem")
+            .field("files", &self.files.keys())
+            .field("modified", &self.modified)
+            .finish()
+    }
+}
+
+impl FileSystem for EmbeddedFileSystem {
+    type File = Cursor<&'static [u8]>;
+
+    fn open<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, (Self::File, FileMetadata)> {
+        Box::pin(async move {
+            match self.lookup(path)? {
+                (Some(contents), metadata) => Ok((Cursor::new(contents), metadata)),
+                // mirror the error opening a directory for reading gives on most platforms
+                (None, _) => Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "cannot open a directory",
+                )),
+            }
+        })
+    }
+
+    fn metadata<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, FileMetadata> {
+        Box::pin(async move { self.lookup(path).map(|(_, metadata)| metadata) })
+    }
+
+    fn read_dir<'a>(&'a self, path: &'a Path) -> FileSystemFuture<'a, Vec<DirEntry>> {
+        Box::pin(async move {
+            let dir = normalize(path);
+            if !self.directories.contains(&dir) {
+                return Err(io::ErrorKind::NotFound.into());
+            }
+
+            let child_name = |child: &str| -> Option<String> {
+                let name = if dir.is_empty() {
+                    child
+                } else {
+                    child.strip_prefix(&dir)?.strip_prefix('/')?
+                };
+                (!name.is_empty() && !name.contains('/')).then(|| name.to_owned())
+            };
+
+            let mut entries = Vec::new();
+            for (child, contents) in self.files.iter() {
+                if let Some(name) = child_name(child) {
+                    let mut metadata = FileMetadata::file(contents.len() as u64);
+                    if let Some(modified) = self.modified {
+                        metadata = metadata.with_modified(modified);
+                    }
+                    entries.push(DirEntry::new(name, metadata));
+                }
+            }
+            for child in self.directories.iter() {
+                if let Some(name) = child_name(child) {
+                    entries.push(DirEntry::new(name, FileMetadata::directory()));
+                }
+            }
+            Ok(entries)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::services::{ServeDir, ServeEmbedded};
+    use crate::test_helpers::{to_bytes, Body};
+    use http::{header, Method, Request, Response, StatusCode};
+    use std::convert::Infallible;
+    use std::time::{Duration, SystemTime};
+    use tower::{service_fn, ServiceExt};
+
+    static FILES: &[(&str, &[u8])] = &[
+        (
+            "index.html",
+            include_bytes!("../../../../test-files/index.html"),
+        ),
+        (
+            "precompressed.txt",
+            include_bytes!("../../../../test-files/precompressed.txt"),
+        ),
+        (
+            "precompressed.txt.gz",
+            include_bytes!("../../../../test-files/precompressed.txt.gz"),
+        ),
+        (
+            "extensionless_precompressed",
+            include_bytes!("../../../../test-files/extensionless_precompressed"),
+        ),
+        ("docs/guide/index.html", b"<b>guide</b>"),
+    ];
+
+    async fn get<S>(svc: S, uri: &str, headers: &[(&str, &str)]) -> Response<Body>
+    where
+        S: tower::Service<
+            Request<Body>,
+            Response = Response<crate::services::fs::ServeFileSystemResponseBody>,
+            Error = Infallible,
+        >,
+    {
+        let mut req = Request::builder().uri(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        svc.oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .map(Body::new)
+    }
+
+    #[tokio::test]
+    async fn behaves_like_serve_dir() {
+        let embedded = ServeEmbedded::new(FILES).precompressed_gzip();
+        let dir = ServeDir::new("../test-files").precompressed_gzip();
+
+        for (uri, headers) in [
+            ("/", &[][..]),
+            ("/precompressed.txt", &[][..]),
+            ("/precompressed.txt", &[("accept-encoding", "gzip")][..]),
+            ("/precompressed.txt", &[("range", "bytes=2-5")][..]),
+            ("/extensionless_precompressed", &[][..]),
+            ("/missing.txt", &[][..]),
+        ] {
+            let embedded_res = get(embedded.clone(), uri, headers).await;
+            let dir_res = get(dir.clone(), uri, headers).await;
+
+            assert_eq!(embedded_res.status(), dir_res.status(), "{}", uri);
+            for name in [
+                header::CONTENT_TYPE,
+                header::CONTENT_LENGTH,
+                header::CONTENT_ENCODING,
+                header::CONTENT_RANGE,
+            ] {
+                assert_eq!(
+                    embedded_res.headers().get(&name),
+                    dir_res.headers().get(&name),
+                    "{} {}",
+                    uri,
+                    name
+                );
+            }
+            assert_eq!(
+                to_bytes(embedded_res.into_body()).await.unwrap(),
+                to_bytes(dir_res.into_body()).await.unwrap(),
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn directories() {
+        let svc = ServeEmbedded::new(FILES);
+
+        let res = get(svc.clone(), "/docs/guide", &[]).await;
+        assert_eq!(res.status(), StatusCode::TEMPORARY_REDIRECT);
+        assert_eq!(res.headers()[header::LOCATION], "/docs/guide/");
+
+        let res = get(svc.clone(), "/docs/guide/", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &b"<b>guide</b>"[..]
+        );
+
+        // `docs` has no index.html
+        let res = get(svc.clone(), "/docs/", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+        let svc = svc.append_index_html_on_directories(false);
+        let res = get(svc, "/docs/guide/", &[]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn single_page_app() {
+        let svc = ServeEmbedded::new(FILES).single_page_app("index.html");
+
+        let res = get(svc.clone(), "/users/42", &[("accept", "text/html")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.headers()[header::CACHE_CONTROL], "no-cache");
+
+        let res = get(svc, "/app.js", &[("accept", "text/html")]).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn directory_listing() {
+        let svc = ServeEmbedded::new(FILES).directory_listing(true);
+
+        let res = get(svc, "/docs/", &[("accept", "application/json")]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &br#"{"path":"/docs/","entries":[{"name":"guide","type":"directory","size":0,"modified":null}]}"#[..]
+        );
+    }
+
+    #[tokio::test]
+    async fn last_modified() {
+        let res = get(ServeEmbedded::new(FILES), "/precompressed.txt", &[]).await;
+        assert!(res.headers().get(header::LAST_MODIFIED).is_none());
+        assert!(res.headers().get(header::ETAG).is_none());
+
+        let svc = ServeEmbedded::new(FILES)
+            .last_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000));
+        let res = get(svc.clone(), "/precompressed.txt", &[]).await;
+        assert_eq!(
+            res.headers()[header::LAST_MODIFIED],
+            "Mon, 12 Jan 1970 13:46:40 GMT"
+        );
+
+        let res = get(
+            svc,
+            "/precompressed.txt",
+            &[("if-modified-since", "Mon, 12 Jan 1970 13:46:40 GMT")],
+        )
+        .await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn fallback() {
+        async fn fallback<B>(req: Request<B>) -> Result<Response<Body>, Infallible> {
+            Ok(Response::new(Body::from(format!(
+                "from fallback {}",
+                req.uri().path()
+            ))))
+        }
+
+        let svc = ServeEmbedded::new(FILES).fallback(service_fn(fallback));
+
+        let res = get(svc.clone(), "/missing.txt", &[]).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            to_bytes(res.into_body()).await.unwrap(),
+            &b"from fallback /missing.txt"[..]
+        );
+
+        let req = Request::builder()
+            .method(Method::POST)
+            .uri("/index.html")
+            .body(Body::empty())
+            .unwrap();
+        let res = svc.oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+}
@@ -1,6 +1,6 @@
 //! Service that serves a file.
 
-use super::ServeDir;
+use super::{Attachment, ServeDir};
 use http::{HeaderValue, Request};
 use mime::Mime;
 use std::{
@@ -98,6 +98,21 @@ impl ServeFile {
         Self(self.0.precompressed_zstd())
     }
 
+    /// Serve the file as a download, with a `Content-Disposition: attachment` header.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use tower_http::services::{fs::Attachment, ServeFile};
+    ///
+    /// // download `exports/2024-q3.csv` as `Q3 report.csv`
+    /// let service = ServeFile::new("exports/2024-q3.csv")
+    ///     .attachment(Attachment::with_filename("Q3 report.csv"));
+    /// ```
+    pub fn attachment(self, attachment: Attachment) -> Self {
+        Self(self.0.attachment(attachment))
+    }
+
     /// Set a specific read buffer chunk size.
     ///
     /// The default capacity is 64kb.
@@ -105,6 +120,13 @@ impl ServeFile {
         Self(self.0.with_buf_chunk_size(chunk_size))
     }
 
+    /// Set the maximum number of ranges served for a single request.
+    ///
+    /// See [`ServeDir::max_ranges`] for more details.
+    pub fn max_ranges(self, max_ranges: usize) -> Self {
+        Self(self.0.max_ranges(max_ranges))
+    }
+
     /// Call the service and get a future that contains any `std::io::Error` that might have
     /// happened.
     ///
@@ -141,6 +163,7 @@ where
 
 #[cfg(test)]
 mod tests {
+    use crate::services::fs::Attachment;
     use crate::services::ServeFile;
     use crate::test_helpers::Body;
     use async_compression::tokio::bufread::ZstdDecoder;
@@ -185,6 +208,30 @@ mod tests {
         assert!(body.starts_with("# Tower HTTP"));
     }
 
+    #[tokio::test]
+    async fn attachment() {
+        let svc = ServeFile::new("../test-files/你好世界.txt").attachment(Attachment::new());
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(
+            res.headers()["content-disposition"],
+            "attachment; filename=\"____.txt\"; filename*=UTF-8''%E4%BD%A0%E5%A5%BD%E4%B8%96%E7%95%8C.txt"
+        );
+
+        let svc =
+            ServeFile::new("../README.md").attachment(Attachment::with_filename("readme.txt"));
+
+        let res = svc.oneshot(Request::new(Body::empty())).await.unwrap();
+
+        assert_eq!(res.headers()["content-type"], "text/markdown");
+        assert_eq!(
+            res.headers()["content-disposition"],
+            "attachment; filename=\"readme.txt\""
+        );
+    }
+
     #[tokio::test]
     async fn head_request() {
         let svc = ServeFile::new("../test-files/precompressed.txt");
@@ -0,0 +1,396 @@
+//! Service that serves a different directory for each host name.
+
+use super::{
+    serve_dir::{InfallibleResponseFuture, ResponseBody},
+    DefaultServeDirFallback, FileSystem, ServeDir, TokioFileSystem,
+};
+use bytes::Bytes;
+use http::{header, HeaderName, Request, Response, StatusCode};
+use pin_project_lite::pin_project;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+
+const X_FORWARDED_HOST: HeaderName = HeaderName::from_static("x-forwarded-host");
+
+/// Service that serves several static sites, picking a [`ServeDir`] by the host name of the
+/// request.
+///
+/// The host name is taken from the authority of the request URI if it has one, like HTTP/2
+/// requests and HTTP/1.1 requests in absolute form, and from the `Host` header otherwise. It is
+/// compared without the port and ignoring case. Host names are matched
+/// exactly first, then against wildcard patterns like `*.example.com`, which match any
+/// subdomain of `example.com` but not `example.com` itself. The most specific wildcard wins.
+///
+/// Requests for hosts that don't match any site are served by the default site if one is set,
+/// and answered with `421 Misdirected Request` otherwise, see
+/// [`ServeHosts::unknown_host_status`].
+///
+/// # Example
+///
+/// ```
+/// use tower_http::services::{ServeDir, ServeHosts};
+///
+/// let service = ServeHosts::new()
+///     .host("example.com", ServeDir::new("sites/example"))
+///     .host("www.example.com", ServeDir::new("sites/example"))
+///     .host("*.docs.example.com", ServeDir::new("sites/docs"))
+///     .default_site(ServeDir::new("sites/default"));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ServeHosts<F = DefaultServeDirFallback, FS = TokioFileSystem> {
+    exact: HashMap<String, ServeDir<F, FS>>,
+    // sorted from the longest to the shortest suffix
+    wildcards: Vec<(String, ServeDir<F, FS>)>,
+    default_site: Option<ServeDir<F, FS>>,
+    unknown_host_status: StatusCode,
+    trust_forwarded_host: bool,
+}
+
+impl<F, FS> ServeHosts<F, FS> {
+    /// Create a new [`ServeHosts`] without any sites.
+    pub fn new() -> Self {
+        Self {
+            exact: HashMap::new(),
+            wildcards: Vec::new(),
+            default_site: None,
+            unknown_host_status: StatusCode::MISDIRECTED_REQUEST,
+            trust_forwarded_host: false,
+        }
+    }
+
+    /// Serve requests for the host name `pattern` with `site`.
+    ///
+    /// `pattern` is either a host name, like `example.com`, or a wildcard pattern matching all
+    /// subdomains of a host name, like `*.example.com`. Adding a site for a pattern that already
+    /// has one replaces it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` contains a `*` anywhere but as the first label.
+    pub fn host(mut self, pattern: &str, site: ServeDir<F, FS>) -> Self {
+        let pattern = normalize_host_name(pattern);
+        match pattern.strip_prefix('*') {
+            Some(suffix) if suffix.starts_with('.') && !suffix.contains('*') => {
+                let suffix = suffix.to_owned();
+                self.wildcards.retain(|(existing, _)| *existing != suffix);
+                self.wildcards.push((suffix, site));
+                self.wildcards
+                    .sort_by_key(|(suffix, _)| Reverse(suffix.len()));
+            }
+            _ => {
+                assert!(
+                    !pattern.contains('*'),
+                    "invalid host pattern `{}`, only a leading `*.` is supported",
+                    pattern
+                );
+                self.exact.insert(pattern, site);
+            }
+        }
+        self
+    }
+
+    /// Serve requests for hosts that don't match any site, or without a host name, with `site`.
+    pub fn default_site(mut self, site: ServeDir<F, FS>) -> Self {
+        self.default_site = Some(site);
+        self
+    }
+
+    /// Set the status of the responses to requests for hosts that don't match any site, when
+    /// there is no default site.
+    ///
+    /// Defaults to `421 Misdirected Request`, which tells clients that reuse connections for
+    /// several hosts to retry on a new connection. Use `404 Not Found` to not reveal that the
+    /// host isn't served at all.
+    pub fn unknown_host_status(mut self, status: StatusCode) -> Self {
+        self.unknown_host_status = status;
+        self
+    }
+
+    /// Take the host name from the `X-Forwarded-Host` header, if present.
+    ///
+    /// Only enable this if the service is behind a proxy that sets the header, otherwise
+    /// clients can pick the site they are served by. Defaults to `false`.
+    pub fn trust_forwarded_host(mut self, trust: bool) -> Self {
+        self.trust_forwarded_host = trust;
+        self
+    }
+
+    fn site_mut<B>(&mut self, req: &Request<B>) -> Option<&mut ServeDir<F, FS>> {
+        let host = self.host_name(req);
+        if let Some(host) = host.as_deref() {
+            if let Some(site) = self.exact.get_mut(host) {
+                return Some(site);
+            }
+            if let Some((_, site)) = self
+                .wildcards
+                .iter_mut()
+                .find(|(suffix, _)| host.len() > suffix.len() && host.ends_with(suffix.as_str()))
+            {
+                return Some(site);
+            }
+        }
+        self.default_site.as_mut()
+    }
+
+    fn host_name<B>(&self, req: &Request<B>) -> Option<String> {
+        let forwarded = self
+            .trust_forwarded_host
+            .then(|| req.headers().get(X_FORWARDED_HOST))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            // the first value was set by the proxy closest to the client
+            .and_then(|value| value.split(',').next());
+
+        // the authority of a URI in absolute form takes precedence over the `Host` header, see
+        // RFC 9112 section 3.2.2
+        let authority = forwarded
+            .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+            .or_else(|| {
+                req.headers()
+                    .get(header::HOST)
+                    .and_then(|value| value.to_str().ok())
+            })?;
+
+        let host = strip_port(authority.trim())?;
+        let host = normalize_host_name(host);
+        (!host.is_empty()).then_some(host)
+    }
+}
+
+impl<F, FS> Default for ServeHosts<F, FS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Host names are case insensitive and may end with the dot of the root domain.
+fn normalize_host_name(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+fn strip_port(authority: &str) -> Option<&str> {
+    // the user info is deprecated but still allowed in the authority of URIs
+    let authority = authority.rsplit('@').next()?;
+    if authority.starts_with('[') {
+        // IPv6 literals contain colons
+        authority.get(..=authority.find(']')?)
+    } else {
+        authority.split(':').next()
+    }
+}
+
+impl<ReqBody, F, FResBody, FS> Service<Request<ReqBody>> for ServeHosts<F, FS>
+where
+    F: Service<Request<ReqBody>, Response = Response<FResBody>, Error = Infallible> + Clone,
+    F::Future: Send + 'static,
+    FResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    FResBody::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    FS: FileSystem,
+{
+    type Response = Response<ResponseBody>;
+    type Error = Infallible;
+    type Future = ResponseFuture<ReqBody, F>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // the site is only known once the request is seen, so all of them must be ready
+        let mut ready = true;
+        let sites = self
+            .exact
+            .values_mut()
+            .chain(self.wildcards.iter_mut().map(|(_, site)| site))
+            .chain(self.default_site.as_mut());
+        for site in sites {
+            ready &= site.poll_ready(cx).is_ready();
+        }
+        if ready {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let unknown_host_status = self.unknown_host_status;
+        let inner = match self.site_mut(&req) {
+            Some(site) => ResponseFutureInner::Site {
+                future: site.call(req),
+            },
+            None => {
+                let response = Response::builder()
+                    .status(unknown_host_status)
+                    .body(ResponseBody::default())
+                    .unwrap();
+                ResponseFutureInner::UnknownHost {
+                    response: Some(response),
+                }
+            }
+        };
+        ResponseFuture { inner }
+    }
+}
+
+pin_project! {
+    /// Response future of [`ServeHosts`].
+    pub struct ResponseFuture<ReqBody, F = DefaultServeDirFallback> {
+        #[pin]
+        inner: ResponseFutureInner<ReqBody, F>,
+    }
+}
+
+pin_project! {
+    #[project = ResponseFutureInnerProj]
+    enum ResponseFutureInner<ReqBody, F> {
+        Site {
+            #[pin]
+            future: InfallibleResponseFuture<ReqBody, F>,
+        },
+        UnknownHost {
+            response: Option<Response<ResponseBody>>,
+        },
+    }
+}
+
+impl<ReqBody, F> Future for ResponseFuture<ReqBody, F>
+where
+    InfallibleResponseFuture<ReqBody, F>:
+        Future<Output = Result<Response<ResponseBody>, Infallible>>,
+{
+    type Output = Result<Response<ResponseBody>, Infallible>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.project() {
+            ResponseFutureInnerProj::Site { future } => future.poll(cx),
+            ResponseFutureInnerProj::UnknownHost { response } => {
+                Poll::Ready(Ok(response.take().expect("future polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::Body;
+    use tower::ServiceExt;
+
+    // each site has a file the others don't
+    const SITES: &[(&str, &str)] = &[
+        ("root", "/index.html"),
+        ("subdomains", "/README.md"),
+        ("docs", "/lib.rs"),
+    ];
+
+    fn sites() -> ServeHosts {
+        ServeHosts::new()
+            .host("Example.com", ServeDir::new("../test-files"))
+            .host("*.example.com", ServeDir::new(".."))
+            .host("*.docs.example.com", ServeDir::new("src"))
+    }
+
+    async fn get(
+        svc: ServeHosts,
+        uri: &str,
+        host: Option<&str>,
+        headers: &[(&str, &str)],
+    ) -> StatusCode {
+        let mut req = Request::builder().uri(uri);
+        if let Some(host) = host {
+            req = req.header(header::HOST, host);
+        }
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        svc.oneshot(req.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    async fn site(svc: ServeHosts, host: Option<&str>, headers: &[(&str, &str)]) -> &'static str {
+        for (site, uri) in SITES {
+            if get(svc.clone(), uri, host, headers).await == StatusCode::OK {
+                return site;
+            }
+        }
+        panic!("no site served {:?}", host)
+    }
+
+    #[tokio::test]
+    async fn picks_site_by_host() {
+        for host in ["example.com", "EXAMPLE.com:8080", "example.com."] {
+            assert_eq!(site(sites(), Some(host), &[]).await, "root", "{}", host);
+        }
+        for host in ["www.example.com", "a.b.example.com", "docs.example.com"] {
+            assert_eq!(
+                site(sites(), Some(host), &[]).await,
+                "subdomains",
+                "{}",
+                host
+            );
+        }
+        // the most specific wildcard wins
+        let host = Some("v1.docs.example.com");
+        assert_eq!(site(sites(), host, &[]).await, "docs");
+
+        // HTTP/2 requests have no `Host` header
+        let res = get(sites(), "http://example.com/index.html", None, &[]).await;
+        assert_eq!(res, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn uri_authority_takes_precedence_over_host_header() {
+        let host = Some("example.org");
+        let res = get(sites(), "http://example.com/index.html", host, &[]).await;
+        assert_eq!(res, StatusCode::OK);
+
+        let host = Some("example.com");
+        let res = get(sites(), "http://example.org/index.html", host, &[]).await;
+        assert_eq!(res, StatusCode::MISDIRECTED_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn unknown_hosts() {
+        for host in [Some("example.org"), Some("badexample.com"), None] {
+            let status = get(sites(), "/index.html", host, &[]).await;
+            assert_eq!(status, StatusCode::MISDIRECTED_REQUEST, "{:?}", host);
+        }
+
+        let svc = sites().unknown_host_status(StatusCode::NOT_FOUND);
+        let status = get(svc, "/index.html", Some("example.org"), &[]).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let svc = sites().default_site(ServeDir::new("src"));
+        assert_eq!(site(svc, Some("example.org"), &[]).await, "docs");
+    }
+
+    #[tokio::test]
+    async fn forwarded_host() {
+        let forwarded = [("x-forwarded-host", "www.example.com, proxy.internal")];
+
+        let host = Some("example.com");
+        assert_eq!(site(sites(), host, &forwarded).await, "root");
+
+        let svc = sites().trust_forwarded_host(true);
+        assert_eq!(site(svc, host, &forwarded).await, "subdomains");
+    }
+
+    #[test]
+    fn strips_ports() {
+        assert_eq!(strip_port("example.com:80"), Some("example.com"));
+        assert_eq!(strip_port("[::1]:8080"), Some("[::1]"));
+        assert_eq!(strip_port("user@example.com"), Some("example.com"));
+    }
+
+    #[test]
+    #[should_panic(expected = "only a leading `*.` is supported")]
+    fn invalid_pattern() {
+        let _ = ServeHosts::new().host("www.*.com", ServeDir::new("../test-files"));
+    }
+}
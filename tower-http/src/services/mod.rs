@@ -18,4 +18,8 @@ pub mod fs;
 
 #[cfg(feature = "fs")]
 #[doc(inline)]
-pub use self::fs::{ServeDir, ServeFile};
+pub use self::fs::{ServeDir, ServeEmbedded, ServeFile, ServeHosts};
+
+#[cfg(feature = "fs-archive")]
+#[doc(inline)]
+pub use self::fs::ServeArchive;